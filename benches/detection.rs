@@ -0,0 +1,39 @@
+// Hand detection timings by hand size, worst case over seeded deals from a deck with every
+// joker and copy a guild can configure. Run with `cargo bench --bench detection`.
+use std::time::{Duration, Instant};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use destiny_draw::detect;
+use destiny_draw::types::{Deck, DeckTemplate};
+
+const DEALS: usize = 200;
+
+fn main() {
+    let deck = DeckTemplate {
+        jokers: DeckTemplate::MAX_JOKERS,
+        copies: DeckTemplate::MAX_COPIES,
+        ..DeckTemplate::default()
+    };
+    let mut rng = StdRng::seed_from_u64(0xbe7c);
+    for jokers_in_jackpot in [false, true] {
+        let detectors = detect::for_deck(&deck, jokers_in_jackpot);
+        println!("jokers_in_jackpot: {}", jokers_in_jackpot);
+        for size in [5, 7, 10, 12, 15, 20] {
+            let mut total = Duration::ZERO;
+            let mut slowest = Duration::ZERO;
+            for _ in 0..DEALS {
+                let mut cards = Deck::from_template(&deck).cards;
+                cards.shuffle(&mut rng);
+                cards.truncate(size);
+                let start = Instant::now();
+                let hands = detect::find_hands(&cards, &deck, &detectors);
+                let elapsed = start.elapsed();
+                std::hint::black_box(hands);
+                total += elapsed;
+                slowest = slowest.max(elapsed);
+            }
+            println!("  {:>2} cards: mean {:?}, worst {:?}", size, total / DEALS as u32, slowest);
+        }
+    }
+}
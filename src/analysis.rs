@@ -0,0 +1,286 @@
+use std::collections::HashSet;
+use rand::seq::SliceRandom;
+use rand::thread_rng;
+use crate::config::GuildConfig;
+use crate::effects::{HandEffect, HandKind};
+use crate::state::PlayerState;
+use crate::types::{CardType, DeckTemplate, HandType};
+
+// Near hands whose cards are worth keeping when nothing resolves yet
+const NEAR_HANDS_TO_KEEP: usize = 2;
+
+// Possible draws /odds will go through before giving up, each one is a full hand scan
+pub const MAX_ODDS_COMBINATIONS: u64 = 250_000;
+
+// Random draws /simulate runs when not told how many, and the most it will run
+pub const DEFAULT_SIMULATIONS: u64 = 10_000;
+pub const MAX_SIMULATIONS: u64 = 100_000;
+
+const KINDS: [HandKind; 7] = [HandKind::Jackpot, HandKind::MagicFlush, HandKind::FullStatus, HandKind::DoubleTrouble, HandKind::BlindingFlush, HandKind::TripleThreat, HandKind::MatchedEdge];
+
+// What a hand would do at this table, so hands of different kinds can be compared
+pub struct Evaluation {
+    pub hand: HandType,
+    // Over every target the hand can hit
+    pub damage: i64,
+    pub heal: i64,
+    pub mp_cost: u32,
+}
+
+impl Evaluation {
+    pub fn of(hand: &HandType, config: &GuildConfig) -> Self {
+        let effect = HandEffect::resolve(hand, &config.power(), config.hand_override(hand));
+        let targets = hand.max_targets().max(1) as i64;
+        Evaluation {
+            hand: hand.clone(),
+            damage: effect.damage.unwrap_or(0) * targets,
+            heal: effect.heal.unwrap_or(0),
+            mp_cost: config.mp_cost(hand),
+        }
+    }
+
+    pub fn value(&self) -> i64 {
+        self.damage + self.heal
+    }
+
+    pub fn per_mp(&self) -> f64 {
+        if self.mp_cost == 0 {
+            return self.value() as f64;
+        }
+        self.value() as f64 / self.mp_cost as f64
+    }
+
+    pub fn summary(&self) -> String {
+        let mut parts = Vec::new();
+        if self.damage > 0 {
+            parts.push(format!("{} damage", self.damage));
+        }
+        if self.heal > 0 {
+            parts.push(format!("{} healing", self.heal));
+        }
+        if parts.is_empty() {
+            parts.push("no damage or healing".to_string());
+        }
+        format!("{}, MP {} ({:.1} per MP)", parts.join(" and "), self.mp_cost, self.per_mp())
+    }
+}
+
+// What makes a hand the best one for /resolve_best
+#[derive(Debug, Clone, Copy, Default, PartialEq, poise::ChoiceParameter)]
+pub enum BestBy {
+    #[default]
+    #[name = "Highest value"]
+    Value,
+    #[name = "Lowest MP cost"]
+    Cheapest,
+}
+
+pub enum Advice {
+    Resolve(Evaluation),
+    // Hand positions to mulligan and the hands the kept cards are working toward
+    Mulligan { positions: Vec<usize>, aiming_for: Vec<String> },
+    Nothing,
+}
+
+// Hands the player can resolve, most total value first and MP efficiency breaking ties
+pub fn rank_hands(state: &PlayerState, config: &GuildConfig) -> Vec<Evaluation> {
+    let mut ranked: Vec<Evaluation> = state.find_available_hands(config)
+        .iter()
+        .map(|hand| Evaluation::of(hand, config))
+        .collect();
+    ranked.sort_by(|a, b| b.value().cmp(&a.value()).then(b.per_mp().total_cmp(&a.per_mp())));
+    ranked
+}
+
+// The hand /resolve_best would play. Hands that need targets only count when targets were given.
+pub fn best_hand(state: &PlayerState, config: &GuildConfig, by: BestBy, has_targets: bool) -> Option<Evaluation> {
+    let mut ranked: Vec<Evaluation> = rank_hands(state, config)
+        .into_iter()
+        .filter(|evaluation| has_targets || !evaluation.hand.needs_targets())
+        .collect();
+    if by == BestBy::Cheapest {
+        // Stable, so equally cheap hands stay in value order
+        ranked.sort_by_key(|evaluation| evaluation.mp_cost);
+    }
+    ranked.into_iter().next()
+}
+
+// The hands a made-up set of cards would make, ranked as for a real hand
+pub fn rank_cards(cards: Vec<CardType>, config: &GuildConfig) -> Vec<Evaluation> {
+    let mut probe = PlayerState::new(None, &config.deck_template.without_jokers());
+    probe.hand = cards;
+    rank_hands(&probe, config)
+}
+
+// Resolve the best hand if there is one. Otherwise keep the cards the most valuable
+// hands one card away build on and mulligan the rest.
+pub fn advise(state: &PlayerState, config: &GuildConfig) -> Advice {
+    if let Some(best) = rank_hands(state, config).into_iter().next() {
+        return Advice::Resolve(best);
+    }
+
+    let mut near: Vec<Evaluation> = state.hands_one_card_away(config)
+        .iter()
+        .map(|n| Evaluation::of(&n.hand, config))
+        .collect();
+    near.sort_by_key(|e| std::cmp::Reverse(e.value()));
+    near.truncate(NEAR_HANDS_TO_KEEP);
+
+    let keep: HashSet<u8> = near.iter().flat_map(|e| e.hand.values()).collect();
+    let positions: Vec<usize> = state.hand.iter()
+        .enumerate()
+        // Wild jokers always stay
+        .filter(|(_, card)| card.face().is_some_and(|(value, _)| !keep.contains(&value.unwrap_or(0))))
+        .map(|(i, _)| i)
+        .collect();
+    if positions.is_empty() {
+        return Advice::Nothing;
+    }
+    Advice::Mulligan {
+        positions,
+        aiming_for: near.iter().map(|e| e.hand.shape()).collect(),
+    }
+}
+
+// How likely each kind of hand is after a draw
+pub struct Odds {
+    // Draws counted: every possible one for exact odds, or the runs of a simulation
+    pub trials: u64,
+    pub by_kind: Vec<(HandKind, f64)>,
+    pub any: f64,
+}
+
+// The hand left after a mulligan and what the draw comes out of
+struct DrawSetup {
+    kept: Vec<CardType>,
+    pool: Vec<CardType>,
+    count: usize,
+}
+
+impl DrawSetup {
+    // The cards at `discard` go to the discard pile, then `draws` cards are drawn. The deck's
+    // contents are known, only its order isn't. A draw that runs the deck dry takes all of it
+    // and the rest from the discard, as the reshuffle would.
+    fn new(state: &PlayerState, discard: &[usize], draws: usize) -> Result<Self, String> {
+        let mut kept = Vec::new();
+        let mut discarded = state.discard.clone();
+        for (i, card) in state.hand.iter().enumerate() {
+            if discard.contains(&i) {
+                discarded.push(card.clone());
+            } else {
+                kept.push(card.clone());
+            }
+        }
+
+        let (pool, count) = if draws <= state.deck.cards.len() {
+            (state.deck.cards.clone(), draws)
+        } else {
+            kept.extend(state.deck.cards.iter().cloned());
+            (discarded, draws - state.deck.cards.len())
+        };
+        if count > pool.len() {
+            return Err("There aren't enough cards left in the deck and discard for that many draws".to_string());
+        }
+        Ok(DrawSetup { kept, pool, count })
+    }
+}
+
+// Running count of the draws that made each kind of hand available
+struct Tally {
+    probe: PlayerState,
+    hits: [u64; KINDS.len()],
+    any: u64,
+    trials: u64,
+}
+
+impl Tally {
+    fn new(template: &DeckTemplate) -> Self {
+        Tally { probe: PlayerState::new(None, &template.without_jokers()), hits: [0; KINDS.len()], any: 0, trials: 0 }
+    }
+
+    fn add(&mut self, kept: &[CardType], drawn: &[CardType], ways: u64, config: &GuildConfig) {
+        self.probe.hand = kept.iter().chain(drawn).cloned().collect();
+        let kinds: Vec<HandKind> = self.probe.find_available_hands(config).iter().map(HandKind::of).collect();
+        for (i, kind) in KINDS.iter().enumerate() {
+            if kinds.contains(kind) {
+                self.hits[i] += ways;
+            }
+        }
+        if !kinds.is_empty() {
+            self.any += ways;
+        }
+        self.trials += ways;
+    }
+
+    fn odds(&self) -> Odds {
+        let chance = |hits: u64| hits as f64 / self.trials.max(1) as f64;
+        Odds {
+            trials: self.trials,
+            by_kind: KINDS.iter().zip(self.hits).map(|(kind, hits)| (*kind, chance(hits))).collect(),
+            any: chance(self.any),
+        }
+    }
+}
+
+// Exact odds over every set of cards the draw could bring
+pub fn draw_odds(state: &PlayerState, config: &GuildConfig, discard: &[usize], draws: usize) -> Result<Odds, String> {
+    let setup = DrawSetup::new(state, discard, draws)?;
+    let combinations = choose(setup.pool.len() as u64, setup.count as u64);
+    if combinations > MAX_ODDS_COMBINATIONS {
+        return Err(format!("That's {} possible draws, too many to check. Try drawing fewer cards, or /simulate.", combinations));
+    }
+
+    // Identical cards (only jokers in a standard deck) are tried once, weighted by how many ways they can come up
+    let mut groups: Vec<(CardType, u64)> = Vec::new();
+    for card in setup.pool {
+        match groups.iter_mut().find(|(c, _)| c.key() == card.key()) {
+            Some((_, copies)) => *copies += 1,
+            None => groups.push((card, 1)),
+        }
+    }
+
+    let mut tally = Tally::new(&state.deck_template);
+    let mut drawn = Vec::new();
+    for_each_draw(&groups, setup.count, &mut drawn, 1, &mut |cards, ways| tally.add(&setup.kept, cards, ways, config));
+    Ok(tally.odds())
+}
+
+// Odds estimated from random draws, for decisions too big to count out exactly
+pub fn simulate(state: &PlayerState, config: &GuildConfig, discard: &[usize], draws: usize, runs: u64) -> Result<Odds, String> {
+    let setup = DrawSetup::new(state, discard, draws)?;
+    let mut rng = thread_rng();
+    let mut tally = Tally::new(&state.deck_template);
+    for _ in 0..runs {
+        let drawn: Vec<CardType> = setup.pool.choose_multiple(&mut rng, setup.count).cloned().collect();
+        tally.add(&setup.kept, &drawn, 1, config);
+    }
+    Ok(tally.odds())
+}
+
+// Every way to draw `count` cards from the groups, with how many orderless draws each one stands for
+fn for_each_draw(groups: &[(CardType, u64)], count: usize, drawn: &mut Vec<CardType>, ways: u64, visit: &mut dyn FnMut(&[CardType], u64)) {
+    if count == 0 {
+        visit(drawn, ways);
+        return;
+    }
+    let Some(((card, copies), rest)) = groups.split_first() else {
+        return;
+    };
+    for take in (0..=(*copies).min(count as u64)).rev() {
+        for _ in 0..take {
+            drawn.push(card.clone());
+        }
+        for_each_draw(rest, count - take as usize, drawn, ways.saturating_mul(choose(*copies, take)), visit);
+        drawn.truncate(drawn.len() - take as usize);
+    }
+}
+
+// Too many to count comes back as u64::MAX, far past MAX_ODDS_COMBINATIONS either way
+fn choose(n: u64, k: u64) -> u64 {
+    if k > n {
+        return 0;
+    }
+    (0..k.min(n - k))
+        .try_fold(1u64, |acc, i| acc.checked_mul(n - i).map(|product| product / (i + 1)))
+        .unwrap_or(u64::MAX)
+}
@@ -0,0 +1,188 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use serde::{Serialize, Deserialize};
+use poise::serenity_prelude::GuildId;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Mutex;
+use crate::events::unix_now;
+use crate::state::PlayerStateManager;
+
+// Keys a guild can hold at once
+pub const MAX_API_KEYS: usize = 5;
+pub const DEFAULT_RATE_LIMIT: u32 = 60;
+pub const MAX_RATE_LIMIT: u32 = 600;
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+const KEY_PREFIX: &str = "dd";
+const ID_LEN: usize = 8;
+const SECRET_LEN: usize = 32;
+// Requests only carry a key and a path, anything longer or slower is dropped
+const MAX_REQUEST_HEAD: usize = 8 * 1024;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+// A key for the HTTP API, issued by a GM and only good for that guild's data. The state
+// files already hold everything a key can read, so it's kept as issued, but it never
+// leaves them in exports.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApiKey {
+    // The public part, shown in listings and used to revoke the key
+    pub id: String,
+    secret: String,
+    pub label: String,
+    pub requests_per_minute: u32,
+    pub created_at: u64,
+}
+
+impl ApiKey {
+    // A fresh key and the token to hand to the GM, which is only ever shown once
+    pub fn generate(label: String, requests_per_minute: u32) -> (ApiKey, String) {
+        let key = ApiKey {
+            id: random_string(ID_LEN).to_lowercase(),
+            secret: random_string(SECRET_LEN),
+            label,
+            requests_per_minute,
+            created_at: unix_now(),
+        };
+        let token = format!("{}_{}_{}", KEY_PREFIX, key.id, key.secret);
+        (key, token)
+    }
+
+    // Compares every byte so a wrong guess takes as long as a nearly right one
+    fn matches(&self, secret: &str) -> bool {
+        self.secret.len() == secret.len()
+            && self.secret.bytes().zip(secret.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+    }
+}
+
+fn random_string(len: usize) -> String {
+    thread_rng().sample_iter(&Alphanumeric).take(len).map(char::from).collect()
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ApiError {
+    // No key, a malformed one, or one that was revoked
+    Unauthorized,
+    RateLimited { retry_after: Duration },
+}
+
+impl ApiError {
+    // The HTTP status line the API answers with
+    pub fn status(&self) -> &'static str {
+        match self {
+            ApiError::Unauthorized => "401 Unauthorized",
+            ApiError::RateLimited { .. } => "429 Too Many Requests",
+        }
+    }
+}
+
+// The API's middleware: resolves each request's key to the one guild it may read and
+// holds every key to its own rate. Counts live in memory, a restart starts them over.
+#[derive(Default)]
+pub struct ApiGate {
+    windows: HashMap<String, (Instant, u32)>,
+}
+
+impl ApiGate {
+    // Checks an Authorization header of the form "Bearer dd_<id>_<secret>"
+    pub fn authorize(&mut self, manager: &PlayerStateManager, header: Option<&str>, now: Instant) -> Result<GuildId, ApiError> {
+        let token = header.and_then(|h| h.strip_prefix("Bearer ")).ok_or(ApiError::Unauthorized)?;
+        let (id, secret) = token.trim()
+            .strip_prefix(KEY_PREFIX)
+            .and_then(|rest| rest.strip_prefix('_'))
+            .and_then(|rest| rest.split_once('_'))
+            .ok_or(ApiError::Unauthorized)?;
+        let (guild_id, key) = manager.guild_configs.iter()
+            .find_map(|(guild_id, config)| config.api_keys.iter().find(|key| key.id == id).map(|key| (*guild_id, key)))
+            .ok_or(ApiError::Unauthorized)?;
+        if !key.matches(secret) {
+            return Err(ApiError::Unauthorized);
+        }
+
+        let (started, count) = self.windows.entry(key.id.clone()).or_insert((now, 0));
+        if now.duration_since(*started) >= RATE_WINDOW {
+            *started = now;
+            *count = 0;
+        }
+        if *count >= key.requests_per_minute {
+            return Err(ApiError::RateLimited { retry_after: RATE_WINDOW.saturating_sub(now.duration_since(*started)) });
+        }
+        *count += 1;
+        Ok(guild_id)
+    }
+}
+
+// Serves the API until the listener fails. It has one route for now, GET /v1/campaign,
+// the key's guild exported the way /gm export_campaign does it.
+pub async fn serve(addr: String, manager: Arc<Mutex<PlayerStateManager>>) -> std::io::Result<()> {
+    let listener = TcpListener::bind(&addr).await?;
+    let gate = Arc::new(Mutex::new(ApiGate::default()));
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let manager = manager.clone();
+        let gate = gate.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle(stream, manager, gate).await {
+                eprintln!("API request failed: {}", e);
+            }
+        });
+    }
+}
+
+async fn handle(mut stream: TcpStream, manager: Arc<Mutex<PlayerStateManager>>, gate: Arc<Mutex<ApiGate>>) -> std::io::Result<()> {
+    let Ok(head) = tokio::time::timeout(REQUEST_TIMEOUT, read_head(&mut stream)).await else {
+        return Ok(());
+    };
+    let head = head?;
+    let mut lines = head.lines();
+    let request_line = lines.next().unwrap_or_default().to_string();
+    let authorization = lines.find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.trim().eq_ignore_ascii_case("authorization").then(|| value.trim().to_string())
+    });
+    
+    let player_state_manager = manager.lock().await;
+    let authorized = gate.lock().await.authorize(&player_state_manager, authorization.as_deref(), Instant::now());
+    let route = request_line.split_whitespace().take(2).collect::<Vec<_>>();
+    let export = match (&authorized, route.as_slice()) {
+        (Ok(guild_id), ["GET", "/v1/campaign"]) => Some(player_state_manager.export_campaign(*guild_id)),
+        _ => None,
+    };
+    drop(player_state_manager);
+    
+    let response = match (authorized, export) {
+        (Err(e), _) => {
+            let retry_after = match e {
+                ApiError::RateLimited { retry_after } => format!("Retry-After: {}\r\n", retry_after.as_secs().max(1)),
+                ApiError::Unauthorized => String::new(),
+            };
+            format!("HTTP/1.1 {}\r\n{}Content-Length: 0\r\nConnection: close\r\n\r\n", e.status(), retry_after)
+        },
+        (Ok(_), Some(export)) => {
+            let body = serde_json::to_string(&export)?;
+            format!("HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body)
+        },
+        (Ok(_), None) => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+    };
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+// The request line and headers, up to the blank line that ends them
+async fn read_head(stream: &mut TcpStream) -> std::io::Result<String> {
+    let mut head = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+        if head.len() > MAX_REQUEST_HEAD {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "request head too large"));
+        }
+        let read = stream.read(&mut buf).await?;
+        if read == 0 {
+            break;
+        }
+        head.extend_from_slice(&buf[..read]);
+    }
+    Ok(String::from_utf8_lossy(&head).into_owned())
+}
@@ -0,0 +1,101 @@
+use serde::{Serialize, Deserialize};
+use poise::serenity_prelude::{ChannelId, GuildId, MessageId, UserId};
+use crate::state::{ActionKind, PlayerState};
+use crate::types::{ElementType, Suit};
+
+const BUTTON_PREFIX: &str = "approval";
+
+// Something a player can only do with the GM's say-so
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub enum ApprovalAction {
+    // Re-open the last resolution without spending a Fabula Point
+    ReopenResolution,
+    // Another action of this kind this turn
+    ExtraAction(ActionKind),
+    // Take this card from the deck into the hand, on tables without the search_deck feature
+    SearchDeck(u8, ElementType),
+}
+
+impl ApprovalAction {
+    // Phrased to follow "asks to"
+    pub fn describe(&self) -> String {
+        match self {
+            ApprovalAction::ReopenResolution => "re-open the last resolution".to_string(),
+            ApprovalAction::ExtraAction(kind) => format!("take another {} action this turn", kind.label()),
+            ApprovalAction::SearchDeck(value, element) => format!("search the deck for {}{}", value, Suit::of(element.clone()).symbol),
+        }
+    }
+
+    // Turn away requests that couldn't be applied anyway, before they reach the GM
+    pub fn check(&self, state: &PlayerState) -> Result<(), String> {
+        match self {
+            ApprovalAction::ReopenResolution if state.last_resolution.is_none() => {
+                Err("There is no resolution to re-open".to_string())
+            },
+            ApprovalAction::SearchDeck(value, element) if state.deck_position(*value, element).is_none() => {
+                Err(format!("There's no {}{} left in your deck", value, Suit::of(element.clone()).symbol))
+            },
+            _ => Ok(()),
+        }
+    }
+
+    pub fn apply(&self, state: &mut PlayerState) -> Result<String, String> {
+        match self {
+            ApprovalAction::ReopenResolution => state.reopen_last_resolution()
+                .map(|_| "Your last resolution has been re-opened.".to_string()),
+            ApprovalAction::ExtraAction(kind) => {
+                state.refund_action(*kind);
+                Ok(format!("You can take another {} action this turn.", kind.label()))
+            },
+            ApprovalAction::SearchDeck(value, element) => state.search_deck(*value, element)
+                .map(|card| format!("{} is in your hand and your deck has been shuffled.", card.label())),
+        }
+    }
+}
+
+// A request waiting in the GM's inbox
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApprovalRequest {
+    pub id: u64,
+    pub guild_id: GuildId,
+    pub requester: UserId,
+    pub action: ApprovalAction,
+    pub note: Option<String>,
+    pub requested_at: u64,
+    // Where the player asked, so the answer goes back there
+    pub reply_channel: ChannelId,
+    pub gm_channel: ChannelId,
+    // The message with the Approve/Deny buttons, once it has been posted
+    pub gm_message: Option<MessageId>,
+}
+
+impl ApprovalRequest {
+    // The text of the message posted for the GM
+    pub fn describe(&self, name: &str) -> String {
+        let mut text = format!("**{}** asks to {}.", name, self.action.describe());
+        if let Some(note) = &self.note {
+            text.push_str(&format!("\n> {}", note));
+        }
+        text.push_str(&format!("\nRequested <t:{}:R>", self.requested_at));
+        text
+    }
+}
+
+pub fn button_id(id: u64, approve: bool) -> String {
+    format!("{}:{}:{}", BUTTON_PREFIX, id, if approve { "approve" } else { "deny" })
+}
+
+// The request and decision behind an Approve/Deny button, None for other components
+pub fn parse_button_id(custom_id: &str) -> Option<(u64, bool)> {
+    let mut parts = custom_id.split(':');
+    if parts.next() != Some(BUTTON_PREFIX) {
+        return None;
+    }
+    let id = parts.next()?.parse().ok()?;
+    let approve = match parts.next()? {
+        "approve" => true,
+        "deny" => false,
+        _ => return None,
+    };
+    Some((id, approve))
+}
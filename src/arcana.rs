@@ -0,0 +1,60 @@
+use crate::types::{CardType, ElementType};
+
+// The trumps of the major arcana, I to XXI, with the element each is attributed to. Water
+// trumps play as Ice. The Fool, numbered 0, is the deck's joker.
+pub const MAJOR_ARCANA: [(&str, &str, ElementType); 21] = [
+    ("I", "The Magician", ElementType::Air),
+    ("II", "The High Priestess", ElementType::Ice),
+    ("III", "The Empress", ElementType::Earth),
+    ("IV", "The Emperor", ElementType::Fire),
+    ("V", "The Hierophant", ElementType::Earth),
+    ("VI", "The Lovers", ElementType::Air),
+    ("VII", "The Chariot", ElementType::Ice),
+    ("VIII", "Strength", ElementType::Fire),
+    ("IX", "The Hermit", ElementType::Earth),
+    ("X", "Wheel of Fortune", ElementType::Fire),
+    ("XI", "Justice", ElementType::Air),
+    ("XII", "The Hanged Man", ElementType::Ice),
+    ("XIII", "Death", ElementType::Ice),
+    ("XIV", "Temperance", ElementType::Fire),
+    ("XV", "The Devil", ElementType::Earth),
+    ("XVI", "The Tower", ElementType::Fire),
+    ("XVII", "The Star", ElementType::Air),
+    ("XVIII", "The Moon", ElementType::Ice),
+    ("XIX", "The Sun", ElementType::Fire),
+    ("XX", "Judgement", ElementType::Fire),
+    ("XXI", "The World", ElementType::Earth),
+];
+
+pub const FOOL: &str = "The Fool";
+
+// Every trump with its value, element and name written out, e.g. "VII The Chariot"
+pub fn trumps() -> impl Iterator<Item = (u8, ElementType, String)> {
+    MAJOR_ARCANA.iter()
+        .enumerate()
+        .map(|(i, (numeral, name, element))| (i as u8 + 1, element.clone(), format!("{} {}", numeral, name)))
+}
+
+pub fn name(value: u8) -> Option<String> {
+    let (numeral, name, _) = MAJOR_ARCANA.get((value as usize).checked_sub(1)?)?;
+    Some(format!("{} {}", numeral, name))
+}
+
+// A card as an arcana table reads it, e.g. "VII The Chariot ❄️", with jokers as The Fool
+pub fn card_name(card: &CardType) -> String {
+    match card {
+        CardType::Number(Some(value), suit, _) => match name(*value) {
+            Some(name) => format!("{} {}", name, suit.symbol),
+            None => card.label(),
+        },
+        CardType::Number(None, ..) => card.label(),
+        CardType::Joker { .. } => match card.face() {
+            Some(_) => format!("{} as {}", FOOL, card.label()),
+            None => FOOL.to_string(),
+        },
+    }
+}
+
+pub fn card_names(cards: &[CardType]) -> String {
+    cards.iter().map(card_name).collect::<Vec<_>>().join(", ")
+}
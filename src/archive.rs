@@ -0,0 +1,84 @@
+use std::collections::BTreeMap;
+use serde::{Serialize, Deserialize};
+use poise::serenity_prelude::GuildId;
+use crate::events::unix_now;
+use crate::state::PlayerState;
+
+// Ended combats kept per player for /archive; older ones are dropped
+pub const MAX_ARCHIVED_COMBATS: usize = 100;
+
+// What's left of a combat once it ends
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CombatSummary {
+    pub combat_id: String,
+    pub guild_id: Option<GuildId>,
+    // Zero for combats started before start times were recorded
+    pub started_at: u64,
+    pub ended_at: u64,
+    pub turns: u32,
+    // Hands resolved, by hand label
+    pub resolutions: BTreeMap<String, u32>,
+    pub mulligans: u32,
+    pub reshuffles: u32,
+    pub jokers_drawn: u32,
+    pub burned: Vec<String>,
+}
+
+impl CombatSummary {
+    pub fn of(state: &PlayerState) -> Self {
+        CombatSummary {
+            combat_id: state.log.combat_id.clone(),
+            guild_id: state.guild_id,
+            started_at: state.started_at,
+            ended_at: unix_now(),
+            turns: state.turn,
+            resolutions: state.resolutions.clone(),
+            mulligans: state.mulligans,
+            reshuffles: state.reshuffles,
+            jokers_drawn: state.joker_appearances.iter().sum(),
+            burned: state.burned.iter().map(|card| card.label()).collect(),
+        }
+    }
+
+    pub fn hands_resolved(&self) -> u32 {
+        self.resolutions.values().sum()
+    }
+
+    // One line for the /archive list
+    pub fn headline(&self) -> String {
+        format!("`{}` ended <t:{}:d>, {} turn(s), {} hand(s) resolved",
+            self.combat_id, self.ended_at, self.turns, self.hands_resolved())
+    }
+
+    pub fn describe(&self) -> String {
+        let mut text = String::new();
+        if self.started_at > 0 {
+            text.push_str(&format!("Started <t:{}:f>, ended <t:{}:f>\n", self.started_at, self.ended_at));
+        } else {
+            text.push_str(&format!("Ended <t:{}:f>\n", self.ended_at));
+        }
+        text.push_str(&format!("Turns: {}\nHands resolved: {}\n", self.turns, self.hands_resolved()));
+        for (hand, count) in &self.resolutions {
+            text.push_str(&format!("• {} ×{}\n", hand, count));
+        }
+        text.push_str(&self.notable_moments());
+        text
+    }
+
+    fn notable_moments(&self) -> String {
+        let mut moments = Vec::new();
+        if self.jokers_drawn > 0 {
+            moments.push(format!("Jokers drawn: {}", self.jokers_drawn));
+        }
+        if self.mulligans > 0 {
+            moments.push(format!("Mulligans: {}", self.mulligans));
+        }
+        if self.reshuffles > 0 {
+            moments.push(format!("The discard was shuffled back in {} time(s)", self.reshuffles));
+        }
+        if !self.burned.is_empty() {
+            moments.push(format!("Burned: {}", self.burned.join(", ")));
+        }
+        moments.into_iter().map(|m| format!("{}\n", m)).collect()
+    }
+}
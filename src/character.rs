@@ -0,0 +1,100 @@
+use serde::{Serialize, Deserialize};
+use poise::serenity_prelude::GuildId;
+use crate::types::{CardType, DeckTemplate, ElementType};
+
+const MAX_DISPLAY_NAME_LEN: usize = 32;
+
+// A player's in-fiction identity, used in public announcements
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct CharacterProfile {
+    // Table the name was registered at, names are unique per table
+    pub guild_id: Option<GuildId>,
+    pub display_name: Option<String>,
+    // Named jokers that follow the player from combat to combat, by slot. Only as long as
+    // the highest slot that was ever named or drawn.
+    #[serde(default)]
+    pub jokers: Vec<SignatureJoker>,
+    // Element the character is attuned to, highlighted in hands and flourished on resolutions
+    #[serde(default)]
+    pub affinity: Option<ElementType>,
+}
+
+impl CharacterProfile {
+    pub fn joker(&self, slot: JokerSlot) -> Option<&SignatureJoker> {
+        self.jokers.get(slot.index())
+    }
+
+    // The joker in a slot, adding unnamed ones up to it
+    pub fn joker_mut(&mut self, index: usize) -> &mut SignatureJoker {
+        if self.jokers.len() <= index {
+            self.jokers.resize_with(index + 1, SignatureJoker::default);
+        }
+        &mut self.jokers[index]
+    }
+}
+
+// Shown next to cards of the character's affine element
+pub const AFFINITY_MARK: &str = "✨";
+
+pub fn is_affine(card: &CardType, affinity: Option<&ElementType>) -> bool {
+    match (card.face(), affinity) {
+        (Some((_, element)), Some(affinity)) => element == affinity,
+        _ => false,
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct SignatureJoker {
+    pub name: Option<String>,
+    // Draws in combats that have since ended; the running combat keeps its own count
+    #[serde(default)]
+    pub appearances: u32,
+}
+
+#[derive(Debug, Clone, Copy, poise::ChoiceParameter)]
+pub enum JokerSlot {
+    Left,
+    Right,
+    Third,
+    Fourth,
+}
+
+impl JokerSlot {
+    // One slot for each joker a deck can have
+    pub const ALL: [JokerSlot; DeckTemplate::MAX_JOKERS as usize] = [JokerSlot::Left, JokerSlot::Right, JokerSlot::Third, JokerSlot::Fourth];
+
+    pub fn index(&self) -> usize {
+        match self {
+            JokerSlot::Left => 0,
+            JokerSlot::Right => 1,
+            JokerSlot::Third => 2,
+            JokerSlot::Fourth => 3,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            JokerSlot::Left => "left",
+            JokerSlot::Right => "right",
+            JokerSlot::Third => "third",
+            JokerSlot::Fourth => "fourth",
+        }
+    }
+}
+
+// Strip anything that could ping people or break Discord markdown
+pub fn sanitize_display_name(name: &str) -> Result<String, String> {
+    let cleaned: String = name
+        .chars()
+        .filter(|c| !c.is_control() && !matches!(c, '@' | '#' | '`' | '*' | '_' | '~' | '|' | '<' | '>' | '\\'))
+        .collect();
+    let cleaned = cleaned.split_whitespace().collect::<Vec<_>>().join(" ");
+    
+    if cleaned.is_empty() {
+        return Err("That name is empty once formatting characters are removed".to_string());
+    }
+    if cleaned.chars().count() > MAX_DISPLAY_NAME_LEN {
+        return Err(format!("Character names can be at most {} characters", MAX_DISPLAY_NAME_LEN));
+    }
+    Ok(cleaned)
+}
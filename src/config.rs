@@ -0,0 +1,292 @@
+use std::collections::HashMap;
+use serde::{Serialize, Deserialize};
+use crate::api::ApiKey;
+use crate::effects::{Affinity, EffectOverride, HandKind, PowerCoefficients, PowerLevel};
+use crate::homebrew::HomebrewHand;
+use crate::state::{ActionKind, DEFAULT_HAND_SIZE};
+use crate::shuffle::ShuffleStrategy;
+use crate::theme::Theme;
+use crate::types::{DeckTemplate, ElementType, HandType};
+use poise::serenity_prelude::{ChannelId, UserId};
+
+pub struct Feature {
+    pub name: &'static str,
+    pub description: &'static str,
+}
+
+// Rules that ship disabled by default until a guild opts in for playtesting
+pub static FEATURES: &[Feature] = &[
+    Feature {
+        name: "burn",
+        description: "Players can burn cards from their own hand with /burn, for abilities that remove cards from the combat",
+    },
+    Feature {
+        name: "affinity_bonus",
+        description: "Resolutions in the element a character is attuned to (/character affinity) deal a little extra damage",
+    },
+    Feature {
+        name: "confirm_resolutions",
+        description: "/resolve_hand shows the cards it will use and the effect, and waits for Commit before resolving",
+    },
+    Feature {
+        name: "party_combos",
+        description: "GMs can check the table's hands for combos across players with /gm party_combos",
+    },
+    Feature {
+        name: "peek_deck",
+        description: "Players can look at the top of their deck with /peek_deck and reorder it, for scrying skills",
+    },
+    Feature {
+        name: "tuck",
+        description: "Players can put a card from their hand back on top of or under their deck with /tuck",
+    },
+    Feature {
+        name: "search_deck",
+        description: "/search_deck takes a card straight from the deck; without this each search needs a GM's approval",
+    },
+    Feature {
+        name: "give_card",
+        description: "Players can hand a card from their hand to another player with /give_card, once the other player accepts",
+    },
+    Feature {
+        name: "jokers_in_jackpot",
+        description: "Jokers can complete a Jackpot, as they can any other set of matching values",
+    },
+    Feature {
+        name: "burn_used_jokers",
+        description: "Jokers in a resolved hand are burned instead of discarded, so they can't be shuffled back in",
+    },
+    Feature {
+        name: "draw_extra",
+        description: "Players can draw past their hand size with /draw_extra, for skills that grant extra cards",
+    },
+];
+
+pub fn find_feature(name: &str) -> Option<&'static Feature> {
+    FEATURES.iter().find(|f| f.name == name)
+}
+
+// Per-guild settings chosen by the table's GM
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct GuildConfig {
+    #[serde(default)]
+    pub disabled_modules: Vec<String>,
+    #[serde(default)]
+    pub enabled_features: Vec<String>,
+    #[serde(default)]
+    pub action_budget: ActionBudget,
+    #[serde(default)]
+    pub theme: Theme,
+    // Keys the GMs issued for the HTTP API
+    #[serde(default)]
+    pub api_keys: Vec<ApiKey>,
+    #[serde(default)]
+    pub deck_template: DeckTemplate,
+    // How new combats shuffle their decks
+    #[serde(default)]
+    pub shuffle: ShuffleStrategy,
+    // Where the table plays, used for announcements from the bot itself
+    #[serde(default)]
+    pub table_channel: Option<ChannelId>,
+    // Where players' requests for GM approval are posted
+    #[serde(default)]
+    pub gm_channel: Option<ChannelId>,
+    // Players who joined the table, dealt in together by the GM
+    #[serde(default)]
+    pub table_members: Vec<UserId>,
+    // Homebrew effect tweaks, keyed by HandKind::key
+    #[serde(default)]
+    pub effect_overrides: HashMap<String, EffectOverride>,
+    // Hand types the guild made up, detected alongside the built-in ones
+    #[serde(default)]
+    pub homebrew_hands: Vec<HomebrewHand>,
+    #[serde(default)]
+    pub power_level: PowerLevel,
+    // Used when the power level is Custom
+    #[serde(default)]
+    pub custom_power: PowerCoefficients,
+    // Known weaknesses and resistances of the table's enemies, by lowercased target name
+    #[serde(default)]
+    pub target_affinities: HashMap<String, HashMap<ElementType, Affinity>>,
+    // Enemies in the current scene, offered as targets when a hand needs some
+    #[serde(default)]
+    pub encounter_targets: Vec<String>,
+    // Cards a new combat's hand is dealt and refilled to, None for the default
+    #[serde(default)]
+    pub hand_size: Option<usize>,
+    // Most cards a hand can hold after extra draws, None for the default
+    #[serde(default)]
+    pub max_hand_size: Option<usize>,
+    // Mulligans allowed per combat, None for no limit
+    #[serde(default)]
+    pub mulligan_limit: Option<u32>,
+}
+
+const DEFAULT_MAX_HAND_SIZE: usize = 7;
+
+// One line of the /setup_status checklist
+pub struct SetupStep {
+    pub name: &'static str,
+    pub done: bool,
+    pub detail: String,
+    // Shown when the GM picks this step
+    pub how_to: &'static str,
+}
+
+// How many actions of each kind a player gets per turn
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct ActionBudget {
+    pub draws: u32,
+    pub resolutions: u32,
+    pub reactions: u32,
+}
+
+impl Default for ActionBudget {
+    fn default() -> Self {
+        ActionBudget {
+            draws: 1,
+            resolutions: 1,
+            reactions: 1,
+        }
+    }
+}
+
+impl ActionBudget {
+    pub fn limit(&self, kind: ActionKind) -> u32 {
+        match kind {
+            ActionKind::Draw => self.draws,
+            ActionKind::Resolution => self.resolutions,
+            ActionKind::Reaction => self.reactions,
+        }
+    }
+}
+
+impl GuildConfig {
+    pub fn module_enabled(&self, name: &str) -> bool {
+        !self.disabled_modules.iter().any(|m| m == name)
+    }
+
+    pub fn set_module_enabled(&mut self, name: &str, enabled: bool) {
+        self.disabled_modules.retain(|m| m != name);
+        if !enabled {
+            self.disabled_modules.push(name.to_string());
+        }
+    }
+
+    pub fn feature_enabled(&self, name: &str) -> bool {
+        self.enabled_features.iter().any(|f| f == name)
+    }
+
+    pub fn set_feature_enabled(&mut self, name: &str, enabled: bool) {
+        self.enabled_features.retain(|f| f != name);
+        if enabled {
+            self.enabled_features.push(name.to_string());
+        }
+    }
+
+    pub fn effect_override(&self, kind: HandKind) -> Option<&EffectOverride> {
+        self.effect_overrides.get(kind.key())
+    }
+
+    // A homebrew hand's whole definition by name, a built-in hand's house rules by kind
+    pub fn hand_override(&self, hand: &HandType) -> Option<&EffectOverride> {
+        match hand {
+            HandType::Homebrew { name, .. } => self.homebrew_hand(name).map(|homebrew| &homebrew.effect),
+            _ => self.effect_override(HandKind::of(hand)),
+        }
+    }
+
+    pub fn homebrew_hand(&self, name: &str) -> Option<&HomebrewHand> {
+        self.homebrew_hands.iter().find(|homebrew| homebrew.name.eq_ignore_ascii_case(name))
+    }
+
+    // What resolving the hand costs here, after any house rule
+    pub fn mp_cost(&self, hand: &HandType) -> u32 {
+        self.hand_override(hand)
+            .and_then(|custom| custom.mp_cost)
+            .unwrap_or_else(|| hand.mp_cost())
+    }
+
+    // What a GM has set up so far; steps left on their defaults aren't done
+    pub fn setup_checklist(&self) -> Vec<SetupStep> {
+        let budget = self.action_budget;
+        vec![
+            SetupStep {
+                name: "Table channel",
+                done: self.table_channel.is_some(),
+                detail: match self.table_channel {
+                    Some(channel) => format!("<#{}>", channel),
+                    None => "not set, bot announcements have nowhere to go".to_string(),
+                },
+                how_to: "Run `/configure_table_channel` in the channel your table plays in.",
+            },
+            SetupStep {
+                name: "GM channel",
+                done: self.gm_channel.is_some(),
+                detail: match self.gm_channel {
+                    Some(channel) => format!("<#{}>", channel),
+                    None => "not set, players can't ask for GM approval".to_string(),
+                },
+                how_to: "Run `/configure_gm_channel` in a channel only GMs can see. Requests from `/ask_gm` show up there with Approve and Deny buttons.",
+            },
+            SetupStep {
+                name: "Players",
+                done: !self.table_members.is_empty(),
+                detail: format!("{} joined", self.table_members.len()),
+                how_to: "Players run `/table join`, then the GM can deal everyone in with `/gm deal_all`. Anyone with Manage Server can act as GM.",
+            },
+            SetupStep {
+                name: "Deck",
+                done: self.deck_template != DeckTemplate::default() || self.shuffle != ShuffleStrategy::default(),
+                detail: format!("{}, {:?} shuffle", self.deck_template.describe(), self.shuffle),
+                how_to: "Use `/configure_deck` to change the jokers, the range of values, copies of each card and which suits are in the deck. Leave out the migration option for a dry run. `/configure_shuffle` picks how decks are shuffled.",
+            },
+            SetupStep {
+                name: "Game rules",
+                done: self.power_level != PowerLevel::default() || budget != ActionBudget::default()
+                    || self.hand_size.is_some() || self.max_hand_size.is_some() || self.mulligan_limit.is_some(),
+                detail: format!("{:?} power, {}/{}/{} actions per turn, hands of {} up to {} cards",
+                    self.power_level, budget.draws, budget.resolutions, budget.reactions, self.hand_size(), self.max_hand_size()),
+                how_to: "Use `/configure_power` to scale effects, `/configure_actions` for actions per turn and hand size, and `/configure_mulligans` to limit mulligans. `/rules` shows the result.",
+            },
+            SetupStep {
+                name: "Features",
+                done: !self.enabled_features.is_empty(),
+                detail: if self.enabled_features.is_empty() {
+                    "none enabled".to_string()
+                } else {
+                    self.enabled_features.join(", ")
+                },
+                how_to: "Use `/configure_feature` to opt in to playtest rules, and `/configure_module` to turn modules such as Fabula Points off.",
+            },
+            SetupStep {
+                name: "Look",
+                done: self.theme != Theme::default(),
+                detail: format!("{:?} theme", self.theme),
+                how_to: "Use `/configure_theme` to pick how hands are shown.",
+            },
+        ]
+    }
+
+    pub fn affinity(&self, target: &str, element: &ElementType) -> Option<Affinity> {
+        self.target_affinities
+            .get(&target.to_lowercase())
+            .and_then(|affinities| affinities.get(element))
+            .copied()
+    }
+
+    pub fn hand_size(&self) -> usize {
+        self.hand_size.unwrap_or(DEFAULT_HAND_SIZE)
+    }
+
+    pub fn max_hand_size(&self) -> usize {
+        self.max_hand_size.unwrap_or(DEFAULT_MAX_HAND_SIZE).max(self.hand_size())
+    }
+
+    pub fn power(&self) -> PowerCoefficients {
+        match self.power_level {
+            PowerLevel::Custom => self.custom_power.clone(),
+            level => level.coefficients(),
+        }
+    }
+}
@@ -0,0 +1,961 @@
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Mutex;
+use crate::homebrew::{HandPattern, HomebrewHand};
+use crate::types::{CardId, CardType, DeckKind, DeckTemplate, ElementType, HandType};
+
+// Finds every hand of one kind that the grouped cards can make. The same cards may come
+// back more than once, find_hands sorts that out.
+pub trait HandDetector: Sync {
+    fn detect(&self, groups: &CardGroups) -> Vec<HandType>;
+}
+
+// The hands in the rules, searched for at every table. Jokers only complete a Jackpot
+// under the jokers_in_jackpot house rule.
+pub fn built_in(jokers_in_jackpot: bool) -> Vec<&'static dyn HandDetector> {
+    let jackpot: &'static dyn HandDetector = if jokers_in_jackpot {
+        &JackpotDetector { jokers: true }
+    } else {
+        &JackpotDetector { jokers: false }
+    };
+    vec![
+        jackpot,
+        &DoubleTroubleDetector,
+        &FullStatusDetector,
+        &MagicFlushDetector,
+        &BlindingFlushDetector,
+        &TripleThreatDetector,
+        &MatchedEdgeDetector,
+    ]
+}
+
+// The major arcana hold each value once, so their sets are cards of one element rather than
+// one value, worth the highest trump among them. Runs read the same as in any deck.
+pub fn arcana(jokers_in_jackpot: bool) -> Vec<&'static dyn HandDetector> {
+    let jackpot: &'static dyn HandDetector = if jokers_in_jackpot {
+        &ArcanaJackpotDetector { jokers: true }
+    } else {
+        &ArcanaJackpotDetector { jokers: false }
+    };
+    vec![
+        jackpot,
+        &ArcanaDoubleTroubleDetector,
+        &ArcanaFullStatusDetector,
+        &MagicFlushDetector,
+        &BlindingFlushDetector,
+        &ArcanaTripleThreatDetector,
+        &ArcanaMatchedEdgeDetector,
+    ]
+}
+
+// The hands in the rules for the kind of deck the cards come from
+pub fn for_deck(deck: &DeckTemplate, jokers_in_jackpot: bool) -> Vec<&'static dyn HandDetector> {
+    match deck.kind {
+        DeckKind::Elemental => built_in(jokers_in_jackpot),
+        DeckKind::MajorArcana => arcana(jokers_in_jackpot),
+    }
+}
+
+// Every hand the detectors find in the cards, each once, best first. The deck bounds runs and
+// says what wild jokers can stand in for.
+pub fn find_hands(cards: &[CardType], deck: &DeckTemplate, detectors: &[&dyn HandDetector]) -> Vec<HandType> {
+    let groups = CardGroups::new(cards, deck);
+    // The same cards turn up as the same hand from more than one search order
+    let mut seen = HashSet::new();
+    let mut hands: Vec<HandType> = detectors.iter()
+        .flat_map(|detector| detector.detect(&groups))
+        .map(HandType::canonical)
+        .filter(|hand| seen.insert(hand.clone()))
+        .collect();
+    // Stable, so hands of the same shape stay in the order their cards sit in the hand
+    hands.sort_by_key(HandType::sort_key);
+    hands
+}
+
+// Everything a search's result depends on
+#[derive(Clone, PartialEq)]
+pub struct DetectionKey {
+    // Each card's ID and label, so assigning a joker counts as a change
+    pub hand: Vec<(CardId, String)>,
+    pub homebrew: Vec<(String, HandPattern)>,
+    pub jokers_in_jackpot: bool,
+    pub deck: DeckTemplate,
+}
+
+// The last search's result, reused until the cards in hand or the guild's rules change.
+// A Mutex rather than a RefCell so a PlayerState can still be shared across awaits.
+#[derive(Default)]
+pub struct DetectionCache(Mutex<Option<(DetectionKey, Vec<HandType>)>>);
+
+impl DetectionCache {
+    pub fn get(&self, key: &DetectionKey) -> Option<Vec<HandType>> {
+        let cached = self.0.lock().ok()?;
+        cached.as_ref()
+            .filter(|(cached_key, _)| cached_key == key)
+            .map(|(_, hands)| hands.clone())
+    }
+
+    pub fn store(&self, key: DetectionKey, hands: Vec<HandType>) {
+        if let Ok(mut cached) = self.0.lock() {
+            *cached = Some((key, hands));
+        }
+    }
+}
+
+impl Clone for DetectionCache {
+    fn clone(&self) -> Self {
+        let cached = self.0.lock().ok().and_then(|cached| cached.clone());
+        DetectionCache(Mutex::new(cached))
+    }
+}
+
+// The cards sorted into the groups hands are made of, so a search only ever combines cards
+// that can go together instead of trying every combination in the hand. Wild cards are kept
+// apart and dealt into each group in turn. Positions are into `cards`.
+pub struct CardGroups<'a> {
+    pub cards: &'a [CardType],
+    pub deck: &'a DeckTemplate,
+    // Cards that can join a set, by value, and the wild ones that join a set of any value
+    set_values: BTreeMap<u8, Vec<usize>>,
+    set_wild: Vec<usize>,
+    // A Jackpot only takes numbered cards
+    numbers: BTreeMap<u8, Vec<usize>>,
+    // Cards with a value, for runs, and the ones without that fill any gap
+    run_values: BTreeMap<u8, Vec<usize>>,
+    run_wild: Vec<usize>,
+    // Cards by element, and the ones without that fit any suit
+    elements: BTreeMap<ElementType, Vec<usize>>,
+    suit_wild: Vec<usize>,
+}
+
+impl<'a> CardGroups<'a> {
+    pub fn new(cards: &'a [CardType], deck: &'a DeckTemplate) -> Self {
+        let mut groups = CardGroups {
+            cards,
+            deck,
+            set_values: BTreeMap::new(),
+            set_wild: Vec::new(),
+            numbers: BTreeMap::new(),
+            run_values: BTreeMap::new(),
+            run_wild: Vec::new(),
+            elements: BTreeMap::new(),
+            suit_wild: Vec::new(),
+        };
+        for (index, card) in cards.iter().enumerate() {
+            if let CardType::Number(Some(value), suit, _) = card {
+                if suit.element != ElementType::None {
+                    groups.numbers.entry(*value).or_default().push(index);
+                }
+            }
+            // Assigned jokers play as the card they were set to
+            let Some((value, element)) = card.face() else {
+                groups.set_wild.push(index);
+                groups.run_wild.push(index);
+                groups.suit_wild.push(index);
+                continue;
+            };
+            match value {
+                // A card with a value but no element can't be part of a set
+                Some(value) if *element != ElementType::None => groups.set_values.entry(value).or_default().push(index),
+                Some(_) => {},
+                None => groups.set_wild.push(index),
+            }
+            match value {
+                Some(value) => groups.run_values.entry(value).or_default().push(index),
+                None => groups.run_wild.push(index),
+            }
+            match element {
+                ElementType::None => groups.suit_wild.push(index),
+                element => groups.elements.entry(element.clone()).or_default().push(index),
+            }
+        }
+        groups
+    }
+
+    // Every set of `size` cards sharing a value, with the value. Sets of nothing but wild
+    // cards play as the deck's highest value.
+    pub fn sets(&self, size: usize) -> Vec<(u8, Vec<usize>)> {
+        let mut sets: Vec<(u8, Vec<usize>)> = self.set_values.iter()
+            .flat_map(|(&value, matching)| with_wild(matching, &self.set_wild, size)
+                .into_iter()
+                .map(move |set| (value, set)))
+            .collect();
+        sets.extend(choose(&self.set_wild, size).into_iter().map(|set| (self.deck.high, set)));
+        sets.sort_by(|a, b| a.1.cmp(&b.1));
+        sets
+    }
+
+    // Every four numbered cards of one value
+    pub fn jackpots(&self) -> Vec<Vec<usize>> {
+        let mut jackpots: Vec<Vec<usize>> = self.numbers.values()
+            .flat_map(|matching| choose(matching, 4))
+            .collect();
+        jackpots.sort();
+        jackpots
+    }
+
+    // Every `length` cards that make a run of consecutive values, wild cards filling the gaps.
+    // Runs are built up from their lowest value, so each one is found once.
+    pub fn runs(&self, length: usize) -> Vec<Vec<usize>> {
+        let mut runs = Vec::new();
+        for (&low, lowest) in &self.run_values {
+            let mut picks: Vec<Vec<usize>> = lowest.iter().map(|&index| vec![index]).collect();
+            for value in low + 1..low.saturating_add(length as u8) {
+                let Some(matching) = self.run_values.get(&value) else {
+                    continue;
+                };
+                // Every run so far either skips this value or takes one of its cards
+                let mut longer = Vec::new();
+                for pick in picks.iter().filter(|pick| pick.len() < length) {
+                    for &index in matching {
+                        longer.push([pick.as_slice(), &[index]].concat());
+                    }
+                }
+                picks.extend(longer);
+            }
+            for pick in picks {
+                for fill in choose(&self.run_wild, length - pick.len()) {
+                    let mut run = [pick.clone(), fill].concat();
+                    run.sort_unstable();
+                    runs.push(run);
+                }
+            }
+        }
+        runs.extend(choose(&self.run_wild, length));
+        runs.sort();
+        runs
+    }
+
+    // Every `size` cards of a single suit, whatever their values
+    pub fn flushes(&self, size: usize) -> Vec<Vec<usize>> {
+        let mut flushes: Vec<Vec<usize>> = self.elements.values()
+            .flat_map(|matching| with_wild(matching, &self.suit_wild, size))
+            .collect();
+        flushes.extend(choose(&self.suit_wild, size));
+        flushes.sort();
+        flushes
+    }
+}
+
+// Every `size` cards from the matching and wild ones that hold at least one matching card
+fn with_wild(matching: &[usize], wild: &[usize], size: usize) -> Vec<Vec<usize>> {
+    let mut pool = [matching, wild].concat();
+    pool.sort_unstable();
+    choose(&pool, size)
+        .into_iter()
+        .filter(|group| group.iter().any(|index| matching.contains(index)))
+        .collect()
+}
+
+fn choose(items: &[usize], size: usize) -> Vec<Vec<usize>> {
+    combinations(items.len(), size)
+        .into_iter()
+        .map(|picked| picked.iter().map(|&i| items[i]).collect())
+        .collect()
+}
+
+fn disjoint(a: &[usize], b: &[usize]) -> bool {
+    !a.iter().any(|index| b.contains(index))
+}
+
+// Four of a kind
+pub struct JackpotDetector {
+    // Whether jokers can stand in, as they can in any other set
+    pub jokers: bool,
+}
+
+impl HandDetector for JackpotDetector {
+    fn detect(&self, groups: &CardGroups) -> Vec<HandType> {
+        let candidates = if self.jokers {
+            groups.sets(4).into_iter().map(|(_, set)| set).collect()
+        } else {
+            groups.jackpots()
+        };
+        candidates.iter()
+            .filter_map(|c| check_jackpot(groups.cards, c[0], c[1], c[2], c[3], self.jokers, groups.deck))
+            .collect()
+    }
+}
+
+// Two pairs of different values
+pub struct DoubleTroubleDetector;
+
+impl HandDetector for DoubleTroubleDetector {
+    fn detect(&self, groups: &CardGroups) -> Vec<HandType> {
+        let pairs = groups.sets(2);
+        let mut hands = Vec::new();
+        for (n, (first_value, first)) in pairs.iter().enumerate() {
+            for (second_value, second) in &pairs[n + 1..] {
+                if first_value != second_value && disjoint(first, second) {
+                    hands.extend(check_double_trouble(groups.cards, first[0], first[1], second[0], second[1], groups.deck));
+                }
+            }
+        }
+        hands
+    }
+}
+
+// A triple and a pair
+pub struct FullStatusDetector;
+
+impl HandDetector for FullStatusDetector {
+    fn detect(&self, groups: &CardGroups) -> Vec<HandType> {
+        let pairs = groups.sets(2);
+        let mut hands = Vec::new();
+        for (triple_value, triple) in groups.sets(3) {
+            for (pair_value, pair) in &pairs {
+                if triple_value != *pair_value && disjoint(&triple, pair) {
+                    hands.extend(check_full_status(groups.cards, &triple, pair, groups.deck));
+                }
+            }
+        }
+        hands
+    }
+}
+
+// Four consecutive values in one suit
+pub struct MagicFlushDetector;
+
+impl HandDetector for MagicFlushDetector {
+    fn detect(&self, groups: &CardGroups) -> Vec<HandType> {
+        groups.runs(4).iter()
+            .filter_map(|c| check_magic_flush(groups.cards, c[0], c[1], c[2], c[3], groups.deck))
+            .collect()
+    }
+}
+
+// Four consecutive values in any suits
+pub struct BlindingFlushDetector;
+
+impl HandDetector for BlindingFlushDetector {
+    fn detect(&self, groups: &CardGroups) -> Vec<HandType> {
+        groups.runs(4).iter()
+            .filter_map(|c| check_blinding_flush(groups.cards, c[0], c[1], c[2], c[3], groups.deck))
+            .collect()
+    }
+}
+
+// Three of a kind
+pub struct TripleThreatDetector;
+
+impl HandDetector for TripleThreatDetector {
+    fn detect(&self, groups: &CardGroups) -> Vec<HandType> {
+        groups.sets(3).iter()
+            .filter_map(|(_, c)| check_triple(groups.cards, c[0], c[1], c[2], groups.deck))
+            .collect()
+    }
+}
+
+// A pair
+pub struct MatchedEdgeDetector;
+
+impl HandDetector for MatchedEdgeDetector {
+    fn detect(&self, groups: &CardGroups) -> Vec<HandType> {
+        groups.sets(2).iter()
+            .filter_map(|(_, c)| check_pair(groups.cards, c[0], c[1], groups.deck))
+            .collect()
+    }
+}
+
+// One of a guild's homebrew hands, searched for in the groups its pattern is made of
+pub struct HomebrewDetector<'a>(pub &'a HomebrewHand);
+
+impl HandDetector for HomebrewDetector<'_> {
+    fn detect(&self, groups: &CardGroups) -> Vec<HandType> {
+        let candidates = match self.0.pattern {
+            HandPattern::OfAKind(size) => groups.sets(size).into_iter().map(|(_, set)| set).collect(),
+            HandPattern::Straight(length) => groups.runs(length),
+            HandPattern::Flush(size) => groups.flushes(size),
+        };
+        candidates.iter()
+            .filter_map(|indices| check_homebrew(groups.cards, self.0, indices, groups.deck))
+            .collect()
+    }
+}
+
+// Four arcana of one element
+pub struct ArcanaJackpotDetector {
+    pub jokers: bool,
+}
+
+impl HandDetector for ArcanaJackpotDetector {
+    fn detect(&self, groups: &CardGroups) -> Vec<HandType> {
+        groups.flushes(4).iter()
+            // As written, a Jackpot takes no jokers
+            .filter(|set| self.jokers || set.iter().all(|&i| matches!(groups.cards[i], CardType::Number(..))))
+            .filter_map(|set| {
+                let (value, suits) = check_element_set(groups.cards, set, groups.deck)?;
+                Some(HandType::Jackpot { value, suits, card_ids: card_ids(groups.cards, set) })
+            })
+            .collect()
+    }
+}
+
+// Two pairs of arcana, each of its own element
+pub struct ArcanaDoubleTroubleDetector;
+
+impl HandDetector for ArcanaDoubleTroubleDetector {
+    fn detect(&self, groups: &CardGroups) -> Vec<HandType> {
+        let pairs: Vec<(Vec<usize>, u8, Vec<ElementType>)> = groups.flushes(2).into_iter()
+            .filter_map(|pair| check_element_set(groups.cards, &pair, groups.deck).map(|(value, suits)| (pair, value, suits)))
+            .collect();
+        let mut hands = Vec::new();
+        for (n, (first, first_value, first_suits)) in pairs.iter().enumerate() {
+            for (second, second_value, second_suits) in &pairs[n + 1..] {
+                // Four of one element are a Jackpot, not two pairs
+                if !disjoint(first, second) || (first_suits.len() == 1 && first_suits == second_suits) {
+                    continue;
+                }
+                let mut suits = first_suits.clone();
+                for element in second_suits {
+                    if !suits.contains(element) {
+                        suits.push(element.clone());
+                    }
+                }
+                hands.push(HandType::DoubleTrouble {
+                    first_pair_value: *first_value,
+                    second_pair_value: *second_value,
+                    suits,
+                    card_ids: card_ids(groups.cards, &[first.as_slice(), second].concat()),
+                });
+            }
+        }
+        hands
+    }
+}
+
+// Three arcana of one element and two of another
+pub struct ArcanaFullStatusDetector;
+
+impl HandDetector for ArcanaFullStatusDetector {
+    fn detect(&self, groups: &CardGroups) -> Vec<HandType> {
+        let pairs: Vec<(Vec<usize>, u8, Vec<ElementType>)> = groups.flushes(2).into_iter()
+            .filter_map(|pair| check_element_set(groups.cards, &pair, groups.deck).map(|(value, suits)| (pair, value, suits)))
+            .collect();
+        let mut hands = Vec::new();
+        for triple in groups.flushes(3) {
+            let Some((triple_value, triple_suits)) = check_element_set(groups.cards, &triple, groups.deck) else {
+                continue;
+            };
+            for (pair, pair_value, pair_suits) in &pairs {
+                if !disjoint(&triple, pair) || (triple_suits.len() == 1 && triple_suits == *pair_suits) {
+                    continue;
+                }
+                let mut suits = triple_suits.clone();
+                for element in pair_suits {
+                    if !suits.contains(element) {
+                        suits.push(element.clone());
+                    }
+                }
+                hands.push(HandType::FullStatus {
+                    triple_value,
+                    pair_value: *pair_value,
+                    suits,
+                    card_ids: card_ids(groups.cards, &[triple.as_slice(), pair].concat()),
+                });
+            }
+        }
+        hands
+    }
+}
+
+// Three arcana of one element
+pub struct ArcanaTripleThreatDetector;
+
+impl HandDetector for ArcanaTripleThreatDetector {
+    fn detect(&self, groups: &CardGroups) -> Vec<HandType> {
+        groups.flushes(3).iter()
+            .filter_map(|set| {
+                let (value, suits) = check_element_set(groups.cards, set, groups.deck)?;
+                Some(HandType::TripleThreat { value, suits, card_ids: card_ids(groups.cards, set) })
+            })
+            .collect()
+    }
+}
+
+// Two arcana of one element
+pub struct ArcanaMatchedEdgeDetector;
+
+impl HandDetector for ArcanaMatchedEdgeDetector {
+    fn detect(&self, groups: &CardGroups) -> Vec<HandType> {
+        groups.flushes(2).iter()
+            .filter_map(|set| {
+                let (value, suits) = check_element_set(groups.cards, set, groups.deck)?;
+                Some(HandType::MatchedEdge { value, suits, card_ids: card_ids(groups.cards, set) })
+            })
+            .collect()
+    }
+}
+
+fn card_ids(cards: &[CardType], indices: &[usize]) -> Vec<CardId> {
+    indices.iter().map(|&i| cards[i].id()).collect()
+}
+
+// Helper function to check if the cards all share one value and return it
+fn check_set_value(cards: &[CardType], indices: &[usize], deck: &DeckTemplate) -> Option<(u8, Vec<ElementType>)> {
+    let group: Vec<&CardType> = indices.iter().map(|&i| &cards[i]).collect();
+    let mut value = None;
+    let mut joker_count = 0;
+    let mut non_joker_suits = Vec::new();
+
+    // First pass to find value and count jokers
+    for card in &group {
+        // Assigned jokers play as the card they were set to
+        match card.face() {
+            Some((Some(num), element)) => {
+                if value.is_none() {
+                    value = Some(num);
+                } else if value != Some(num) {
+                    return None;
+                }
+                if *element != ElementType::None {
+                    non_joker_suits.push(element.clone());
+                }
+            },
+            _ => {
+                joker_count += 1;
+            }
+        }
+    }
+
+    // Use the highest value as default if no value was found (all jokers)
+    let value = value.unwrap_or(deck.high);
+    
+    if joker_count + non_joker_suits.len() == group.len() {
+        Some((value, if joker_count > 0 {
+            deck.wild_suits()
+        } else {
+            non_joker_suits
+        }))
+    } else {
+        None
+    }
+}
+
+fn check_jackpot(cards: &[CardType], i: usize, j: usize, k: usize, l: usize, jokers: bool, deck: &DeckTemplate) -> Option<HandType> {
+    if jokers {
+        let (value, suits) = check_set_value(cards, &[i, j, k, l], deck)?;
+        return Some(HandType::Jackpot {
+            value,
+            suits,
+            card_ids: card_ids(cards, &[i, j, k, l]),
+        });
+    }
+    
+    let group = [&cards[i], &cards[j], &cards[k], &cards[l]];
+    let mut value = None;
+    let mut non_joker_suits = Vec::new();
+
+    // As written, Jackpot requires 4 of a kind with no jokers
+    for card in &group {
+        match card {
+            CardType::Number(v, suit, _) => {
+                if let Some(num) = v {
+                    if value.is_none() {
+                        value = Some(*num);
+                    } else if value != Some(*num) {
+                        return None;
+                    }
+                    if suit.element != ElementType::None {
+                        non_joker_suits.push(suit.element.clone());
+                    }
+                } else {
+                    return None; // No wildcard number in Jackpot
+                }
+            },
+            CardType::Joker { .. } => {
+                return None; // No jokers in Jackpot
+            }
+        }
+    }
+
+    if non_joker_suits.len() == 4 {
+        Some(HandType::Jackpot {
+            value: value.unwrap_or(deck.high),
+            suits: non_joker_suits,
+            card_ids: card_ids(cards, &[i, j, k, l]),
+        })
+    } else {
+        None
+    }
+}
+
+// The low end of the highest run of consecutive values the cards can make, and the elements
+// of the cards that aren't wild. Wild cards fill whatever gaps are left.
+fn check_run(cards: &[CardType], indices: &[usize], deck: &DeckTemplate) -> Option<(u8, Vec<ElementType>)> {
+    let mut values = Vec::new();
+    let mut elements = Vec::new();
+    for &index in indices {
+        // Assigned jokers play as the card they were set to
+        let Some((value, element)) = cards[index].face() else {
+            continue;
+        };
+        if let Some(value) = value {
+            if values.contains(&value) {
+                return None;
+            }
+            values.push(value);
+        }
+        if *element != ElementType::None {
+            elements.push(element.clone());
+        }
+    }
+    
+    // A run has to fit in the deck, so e.g. no run of four starts above 4 when suits run 1 to 7
+    let length = indices.len() as u8;
+    if length == 0 || length > deck.high - deck.low + 1 {
+        return None;
+    }
+    let top_low = deck.high + 1 - length;
+    let (Some(&low), Some(&high)) = (values.iter().min(), values.iter().max()) else {
+        return Some((top_low, elements));
+    };
+    if high - low >= length {
+        return None;
+    }
+    Some((low.min(top_low), elements))
+}
+
+// The highest value among cards sharing one element, and that element. Wild cards take the
+// element of the rest, and a set of nothing but wild cards is worth the deck's highest value.
+fn check_element_set(cards: &[CardType], indices: &[usize], deck: &DeckTemplate) -> Option<(u8, Vec<ElementType>)> {
+    let mut high = None;
+    let mut elements = Vec::new();
+    for &index in indices {
+        // Assigned jokers play as the card they were set to
+        let Some((value, element)) = cards[index].face() else {
+            continue;
+        };
+        high = high.max(value);
+        if *element != ElementType::None {
+            elements.push(element.clone());
+        }
+    }
+    Some((high.unwrap_or(deck.high), check_same_suit(&elements, deck)?))
+}
+
+// The one suit the elements share, with wild cards taking the suit of the rest
+fn check_same_suit(elements: &[ElementType], deck: &DeckTemplate) -> Option<Vec<ElementType>> {
+    match elements.first() {
+        Some(first) if elements.iter().all(|element| element == first) => Some(vec![first.clone()]),
+        Some(_) => None,
+        None => Some(deck.wild_suits()),
+    }
+}
+
+fn check_magic_flush(cards: &[CardType], i: usize, j: usize, k: usize, l: usize, deck: &DeckTemplate) -> Option<HandType> {
+    let (low_value, elements) = check_run(cards, &[i, j, k, l], deck)?;
+    let suits = check_same_suit(&elements, deck)?;
+    Some(HandType::MagicFlush {
+        low_value,
+        suits,
+        card_ids: card_ids(cards, &[i, j, k, l]),
+    })
+}
+
+fn check_blinding_flush(cards: &[CardType], i: usize, j: usize, k: usize, l: usize, deck: &DeckTemplate) -> Option<HandType> {
+    let (low_value, elements) = check_run(cards, &[i, j, k, l], deck)?;
+    // Damage can take the suit of any card in the run, and a wild card could be any suit
+    let suits = if elements.len() < 4 {
+        deck.wild_suits()
+    } else {
+        let mut suits: Vec<ElementType> = Vec::new();
+        for element in elements {
+            if !suits.contains(&element) {
+                suits.push(element);
+            }
+        }
+        suits
+    };
+    Some(HandType::BlindingFlush {
+        low_value,
+        suits,
+        card_ids: card_ids(cards, &[i, j, k, l]),
+    })
+}
+
+fn check_double_trouble(cards: &[CardType], i: usize, j: usize, k: usize, l: usize, deck: &DeckTemplate) -> Option<HandType> {
+    // First pair
+    let first_pair = check_set_value(cards, &[i, j], deck)?;
+    // Second pair
+    let second_pair = check_set_value(cards, &[k, l], deck)?;
+    
+    // Get suits from both pairs
+    let mut all_suits = Vec::new();
+    let mut added_elements = Vec::new();
+    
+    // Process first pair suits
+    for element in &first_pair.1 {
+        if !added_elements.contains(element) {
+            all_suits.push(element.clone());
+            added_elements.push(element.clone());
+        }
+    }
+    
+    // Process second pair suits
+    for element in &second_pair.1 {
+        if !added_elements.contains(element) {
+            all_suits.push(element.clone());
+            added_elements.push(element.clone());
+        }
+    }
+    
+    Some(HandType::DoubleTrouble {
+        first_pair_value: first_pair.0,
+        second_pair_value: second_pair.0,
+        suits: all_suits,
+        card_ids: card_ids(cards, &[i, j, k, l]),
+    })
+}
+
+fn check_full_status(cards: &[CardType], triple: &[usize], pair: &[usize], deck: &DeckTemplate) -> Option<HandType> {
+    let (triple_value, triple_suits) = check_set_value(cards, triple, deck)?;
+    let (pair_value, pair_suits) = check_set_value(cards, pair, deck)?;
+    if triple_value == pair_value {
+        return None;
+    }
+    
+    let mut suits: Vec<ElementType> = Vec::new();
+    for element in triple_suits.into_iter().chain(pair_suits) {
+        if !suits.contains(&element) {
+            suits.push(element);
+        }
+    }
+    Some(HandType::FullStatus {
+        triple_value,
+        pair_value,
+        suits,
+        card_ids: card_ids(cards, &[triple, pair].concat()),
+    })
+}
+
+fn check_homebrew(cards: &[CardType], definition: &HomebrewHand, indices: &[usize], deck: &DeckTemplate) -> Option<HandType> {
+    let (values, suits) = match definition.pattern {
+        HandPattern::OfAKind(_) => {
+            let (value, suits) = check_set_value(cards, indices, deck)?;
+            (vec![value], suits)
+        },
+        HandPattern::Straight(_) => {
+            let (low, elements) = check_run(cards, indices, deck)?;
+            // As with a Blinding Flush, a wild card could be any suit
+            let suits = if elements.len() < indices.len() {
+                deck.wild_suits()
+            } else {
+                elements
+            };
+            ((low..low + indices.len() as u8).collect(), suits)
+        },
+        HandPattern::Flush(_) => {
+            let mut values = Vec::new();
+            let mut elements = Vec::new();
+            for &index in indices {
+                // Assigned jokers play as the card they were set to, unassigned ones as the highest value
+                match cards[index].face() {
+                    Some((value, element)) => {
+                        values.push(value.unwrap_or(deck.high));
+                        if *element != ElementType::None {
+                            elements.push(element.clone());
+                        }
+                    },
+                    None => values.push(deck.high),
+                }
+            }
+            values.sort_unstable();
+            (values, check_same_suit(&elements, deck)?)
+        },
+    };
+    Some(HandType::Homebrew {
+        name: definition.name.clone(),
+        values,
+        suits,
+        card_ids: card_ids(cards, indices),
+    })
+}
+
+fn check_triple(cards: &[CardType], i: usize, j: usize, k: usize, deck: &DeckTemplate) -> Option<HandType> {
+    let group = [&cards[i], &cards[j], &cards[k]];
+    let mut value = None;
+    let mut joker_count = 0;
+    let mut non_joker_suits = Vec::new();
+
+    // First pass to find value and count jokers
+    for card in &group {
+        // Assigned jokers play as the card they were set to
+        match card.face() {
+            Some((Some(num), element)) => {
+                if value.is_none() {
+                    value = Some(num);
+                } else if value != Some(num) {
+                    return None;
+                }
+                if *element != ElementType::None {
+                    non_joker_suits.push(element.clone());
+                }
+            },
+            _ => {
+                joker_count += 1;
+            }
+        }
+    }
+
+    // Use the highest value as default if no value was found (all jokers)
+    let value = value.unwrap_or(deck.high);
+    
+    if joker_count + non_joker_suits.len() == 3 {
+        Some(HandType::TripleThreat {
+            value,
+            suits: if joker_count > 0 {
+                deck.wild_suits()
+            } else {
+                non_joker_suits
+            },
+            card_ids: card_ids(cards, &[i, j, k]),
+        })
+    } else {
+        None
+    }
+}
+
+fn check_pair(cards: &[CardType], i: usize, j: usize, deck: &DeckTemplate) -> Option<HandType> {
+    let group = [&cards[i], &cards[j]];
+    let mut value = None;
+    let mut joker_count = 0;
+    let mut non_joker_suits = Vec::new();
+
+    // First pass to find value and count jokers
+    for card in &group {
+        // Assigned jokers play as the card they were set to
+        match card.face() {
+            Some((Some(num), element)) => {
+                if value.is_none() {
+                    value = Some(num);
+                } else if value != Some(num) {
+                    return None;
+                }
+                if *element != ElementType::None {
+                    non_joker_suits.push(element.clone());
+                }
+            },
+            _ => {
+                joker_count += 1;
+            }
+        }
+    }
+
+    // Use the highest value as default if no value was found (all jokers)
+    let value = value.unwrap_or(deck.high);
+    
+    if joker_count + non_joker_suits.len() == 2 {
+        Some(HandType::MatchedEdge {
+            value,
+            suits: if joker_count > 0 {
+                deck.wild_suits()
+            } else {
+                non_joker_suits
+            },
+            card_ids: card_ids(cards, &[i, j]),
+        })
+    } else {
+        None
+    }
+}
+
+// Every set of `size` positions out of `count`, each in ascending order
+fn combinations(count: usize, size: usize) -> Vec<Vec<usize>> {
+    if size > count {
+        return Vec::new();
+    }
+    let mut all = Vec::new();
+    let mut indices: Vec<usize> = (0..size).collect();
+    loop {
+        all.push(indices.clone());
+        // Move the rightmost index that still has room, and reset the ones after it
+        let Some(slot) = (0..size).rev().find(|&slot| indices[slot] < count - size + slot) else {
+            return all;
+        };
+        indices[slot] += 1;
+        for next in slot + 1..size {
+            indices[next] = indices[next - 1] + 1;
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::seq::SliceRandom;
+    use rand::{Rng, SeedableRng};
+    use crate::types::Deck;
+
+    // Every hand in the rules, found the slow way by checking every combination of cards
+    fn brute_force(cards: &[CardType], deck: &DeckTemplate, jokers_in_jackpot: bool) -> HashSet<HandType> {
+        let mut hands = Vec::new();
+        for c in combinations(cards.len(), 2) {
+            hands.extend(check_pair(cards, c[0], c[1], deck));
+        }
+        for c in combinations(cards.len(), 3) {
+            hands.extend(check_triple(cards, c[0], c[1], c[2], deck));
+        }
+        for c in combinations(cards.len(), 4) {
+            hands.extend(check_jackpot(cards, c[0], c[1], c[2], c[3], jokers_in_jackpot, deck));
+            hands.extend(check_magic_flush(cards, c[0], c[1], c[2], c[3], deck));
+            hands.extend(check_blinding_flush(cards, c[0], c[1], c[2], c[3], deck));
+            for [i, j, k, l] in [[0, 1, 2, 3], [0, 2, 1, 3], [0, 3, 1, 2]] {
+                let values = (check_set_value(cards, &[c[i], c[j]], deck), check_set_value(cards, &[c[k], c[l]], deck));
+                if let (Some((first, _)), Some((second, _))) = values {
+                    if first != second {
+                        hands.extend(check_double_trouble(cards, c[i], c[j], c[k], c[l], deck));
+                    }
+                }
+            }
+        }
+        for c in combinations(cards.len(), 5) {
+            for triple in combinations(5, 3) {
+                let triple: Vec<usize> = triple.iter().map(|&i| c[i]).collect();
+                let pair: Vec<usize> = c.iter().copied().filter(|index| !triple.contains(index)).collect();
+                hands.extend(check_full_status(cards, &triple, &pair, deck));
+            }
+        }
+        hands.into_iter().map(HandType::canonical).collect()
+    }
+
+    // A shuffled hand off the top of the deck, with some of its jokers assigned
+    fn deal(deck: &DeckTemplate, size: usize, rng: &mut StdRng) -> Vec<CardType> {
+        let mut cards = Deck::from_template(deck).cards;
+        cards.shuffle(rng);
+        cards.truncate(size);
+        for card in &mut cards {
+            if matches!(card, CardType::Joker { .. }) && rng.gen_bool(0.5) {
+                let value = rng.gen_range(deck.values());
+                let element = deck.elements.choose(rng).unwrap().clone();
+                card.assign_joker(value, element).unwrap();
+            }
+        }
+        cards
+    }
+
+    #[test]
+    fn grouped_search_matches_brute_force() {
+        let decks = [
+            DeckTemplate::default(),
+            DeckTemplate { jokers: DeckTemplate::MAX_JOKERS, copies: 2, ..DeckTemplate::default() },
+            DeckTemplate { jokers: 3, high: 12, copies: 3, ..DeckTemplate::default() },
+        ];
+        let mut rng = StdRng::seed_from_u64(0xdec7);
+        for deck in &decks {
+            for jokers_in_jackpot in [false, true] {
+                let detectors = built_in(jokers_in_jackpot);
+                for size in 2..=10 {
+                    for _ in 0..20 {
+                        let cards = deal(deck, size, &mut rng);
+                        let found = find_hands(&cards, deck, &detectors);
+                        let expected = brute_force(&cards, deck, jokers_in_jackpot);
+                        assert_eq!(found.len(), expected.len(), "{:?}", cards);
+                        assert_eq!(found.into_iter().collect::<HashSet<_>>(), expected, "{:?}", cards);
+                    }
+                }
+            }
+        }
+    }
+}
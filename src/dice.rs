@@ -0,0 +1,100 @@
+use rand::Rng;
+use serde::{Serialize, Deserialize};
+
+// An attribute die, from d6 for a weak attribute up to d12 for a strong one
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum Die {
+    D6,
+    D8,
+    D10,
+    D12,
+}
+
+impl Die {
+    pub fn sides(&self) -> u32 {
+        match self {
+            Die::D6 => 6,
+            Die::D8 => 8,
+            Die::D10 => 10,
+            Die::D12 => 12,
+        }
+    }
+
+    pub fn from_sides(sides: u32) -> Option<Die> {
+        [Die::D6, Die::D8, Die::D10, Die::D12].into_iter().find(|die| die.sides() == sides)
+    }
+
+    pub fn label(&self) -> String {
+        format!("d{}", self.sides())
+    }
+}
+
+// A check as the rules roll it: two attribute dice plus a flat modifier
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct CheckDice {
+    pub dice: [Die; 2],
+    pub modifier: i64,
+}
+
+impl CheckDice {
+    // Largest modifier a check takes either way, plenty for any character
+    pub const MAX_MODIFIER: i64 = 99;
+
+    pub fn roll(&self, rng: &mut impl Rng) -> CheckRoll {
+        CheckRoll {
+            dice: *self,
+            results: self.dice.map(|die| rng.gen_range(1..=die.sides())),
+        }
+    }
+
+    // Written the way players type it, e.g. "d8+d10+2"
+    pub fn label(&self) -> String {
+        let mut label = format!("{}+{}", self.dice[0].label(), self.dice[1].label());
+        if self.modifier != 0 {
+            label.push_str(&format!("{:+}", self.modifier));
+        }
+        label
+    }
+}
+
+pub struct CheckRoll {
+    pub dice: CheckDice,
+    pub results: [u32; 2],
+}
+
+impl CheckRoll {
+    pub fn total(&self) -> i64 {
+        self.results.iter().map(|&r| r as i64).sum::<i64>() + self.dice.modifier
+    }
+
+    // The higher die, which an attack's damage is built on
+    pub fn high_roll(&self) -> u32 {
+        self.results[0].max(self.results[1])
+    }
+
+    // Both dice showing the same number, 6 or more
+    pub fn is_critical(&self) -> bool {
+        self.results[0] == self.results[1] && self.results[0] >= 6
+    }
+
+    // Both dice showing 1, the check fails whatever the total
+    pub fn is_fumble(&self) -> bool {
+        self.results == [1, 1]
+    }
+
+    pub fn describe(&self) -> String {
+        let mut text = format!("🎲 {} **{}** + {} **{}**",
+            self.dice.dice[0].label(), self.results[0],
+            self.dice.dice[1].label(), self.results[1]);
+        if self.dice.modifier != 0 {
+            text.push_str(&format!(" {} {}", if self.dice.modifier < 0 { "-" } else { "+" }, self.dice.modifier.unsigned_abs()));
+        }
+        text.push_str(&format!(" = **{}**", self.total()));
+        if self.is_fumble() {
+            text.push_str(", a fumble!");
+        } else if self.is_critical() {
+            text.push_str(", a critical success!");
+        }
+        text
+    }
+}
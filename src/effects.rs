@@ -0,0 +1,523 @@
+use serde::{Serialize, Deserialize};
+use crate::types::HandType;
+
+#[derive(Debug, Clone, Copy, PartialEq, poise::ChoiceParameter)]
+pub enum HandKind {
+    #[name = "triple_threat"]
+    TripleThreat,
+    #[name = "matched_edge"]
+    MatchedEdge,
+    #[name = "jackpot"]
+    Jackpot,
+    #[name = "double_trouble"]
+    DoubleTrouble,
+    #[name = "magic_flush"]
+    MagicFlush,
+    #[name = "blinding_flush"]
+    BlindingFlush,
+    #[name = "full_status"]
+    FullStatus,
+    // Any of a guild's own hands; their effects live with their definitions, not here
+    #[name = "homebrew"]
+    Homebrew,
+}
+
+impl HandKind {
+    pub fn of(hand: &HandType) -> Self {
+        match hand {
+            HandType::TripleThreat { .. } => HandKind::TripleThreat,
+            HandType::MatchedEdge { .. } => HandKind::MatchedEdge,
+            HandType::Jackpot { .. } => HandKind::Jackpot,
+            HandType::DoubleTrouble { .. } => HandKind::DoubleTrouble,
+            HandType::MagicFlush { .. } => HandKind::MagicFlush,
+            HandType::BlindingFlush { .. } => HandKind::BlindingFlush,
+            HandType::FullStatus { .. } => HandKind::FullStatus,
+            HandType::Homebrew { .. } => HandKind::Homebrew,
+        }
+    }
+
+    // Key used for the override in the guild config
+    pub fn key(&self) -> &'static str {
+        match self {
+            HandKind::TripleThreat => "triple_threat",
+            HandKind::MatchedEdge => "matched_edge",
+            HandKind::Jackpot => "jackpot",
+            HandKind::DoubleTrouble => "double_trouble",
+            HandKind::MagicFlush => "magic_flush",
+            HandKind::BlindingFlush => "blinding_flush",
+            HandKind::FullStatus => "full_status",
+            HandKind::Homebrew => "homebrew",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            HandKind::TripleThreat => "Triple Threat",
+            HandKind::MatchedEdge => "Matched Edge",
+            HandKind::Jackpot => "Jackpot",
+            HandKind::DoubleTrouble => "Double Trouble",
+            HandKind::MagicFlush => "Magic Flush",
+            HandKind::BlindingFlush => "Blinding Flush",
+            HandKind::FullStatus => "Full Status",
+            HandKind::Homebrew => "Homebrew",
+        }
+    }
+
+    // Names a GM can use in a formula for this hand
+    pub fn variables(&self) -> &'static [&'static str] {
+        match self {
+            HandKind::DoubleTrouble => &["value", "first", "second"],
+            HandKind::MagicFlush | HandKind::BlindingFlush | HandKind::Homebrew => &["value", "low", "high"],
+            HandKind::FullStatus => &["value", "triple", "pair"],
+            _ => &["value"],
+        }
+    }
+}
+
+// A GM's homebrew tweak to one hand's effect
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct EffectOverride {
+    #[serde(default)]
+    pub damage_formula: Option<String>,
+    #[serde(default)]
+    pub heal_formula: Option<String>,
+    // Replaces the whole effect text; {damage}, {heal}, {value} and {elements} are filled in
+    #[serde(default)]
+    pub text: Option<String>,
+    // MP the hand costs at this table instead of its usual cost
+    #[serde(default)]
+    pub mp_cost: Option<u32>,
+}
+
+impl EffectOverride {
+    pub fn is_empty(&self) -> bool {
+        self.damage_formula.is_none() && self.heal_formula.is_none() && self.text.is_none() && self.mp_cost.is_none()
+    }
+}
+
+// How strong card output is at a table, from gritty low-level play to epic campaigns
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, poise::ChoiceParameter)]
+pub enum PowerLevel {
+    Low,
+    #[default]
+    Standard,
+    High,
+    Custom,
+}
+
+impl PowerLevel {
+    pub fn coefficients(&self) -> PowerCoefficients {
+        match self {
+            PowerLevel::Low => PowerCoefficients {
+                value_multiplier: 1,
+                triple_threat_heal: 10,
+                triple_threat_damage: 0,
+                matched_edge_damage: 0,
+                double_trouble_damage: 10,
+                jackpot_recovery: 500,
+                magic_flush_damage: 20,
+                blinding_flush_damage: 10,
+                full_status_heal: 10,
+            },
+            PowerLevel::Standard | PowerLevel::Custom => PowerCoefficients::default(),
+            PowerLevel::High => PowerCoefficients {
+                value_multiplier: 2,
+                triple_threat_heal: 30,
+                triple_threat_damage: 10,
+                matched_edge_damage: 5,
+                double_trouble_damage: 30,
+                jackpot_recovery: 999,
+                magic_flush_damage: 50,
+                blinding_flush_damage: 30,
+                full_status_heal: 40,
+            },
+        }
+    }
+}
+
+// The numbers behind every hand's effect: card values are multiplied, then a flat bonus added.
+// Numbers for hands added later fall back to the rules as written in older custom settings.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct PowerCoefficients {
+    pub value_multiplier: i64,
+    pub triple_threat_heal: i64,
+    pub triple_threat_damage: i64,
+    pub matched_edge_damage: i64,
+    pub double_trouble_damage: i64,
+    pub jackpot_recovery: i64,
+    pub magic_flush_damage: i64,
+    pub blinding_flush_damage: i64,
+    pub full_status_heal: i64,
+}
+
+// The rules as written
+impl Default for PowerCoefficients {
+    fn default() -> Self {
+        PowerCoefficients {
+            value_multiplier: 1,
+            triple_threat_heal: 15,
+            triple_threat_damage: 5,
+            matched_edge_damage: 0,
+            double_trouble_damage: 15,
+            jackpot_recovery: 777,
+            magic_flush_damage: 25,
+            blinding_flush_damage: 15,
+            full_status_heal: 20,
+        }
+    }
+}
+
+impl PowerCoefficients {
+    // Largest custom coefficient a GM can set, well clear of overflowing the damage maths
+    pub const MAX: i64 = 9999;
+
+    fn scaled(&self, value: u8) -> i64 {
+        (value as i64).saturating_mul(self.value_multiplier)
+    }
+
+    fn term(&self, name: &str) -> String {
+        if self.value_multiplier == 1 {
+            name.to_string()
+        } else {
+            format!("{}×{}", self.value_multiplier, name)
+        }
+    }
+
+    // Formulas as shown in /rules
+    pub fn describe(&self) -> Vec<String> {
+        vec![
+            format!("Triple Threat: heals {} + {}, deals {} + {}",
+                self.term("value"), self.triple_threat_heal, self.term("value"), self.triple_threat_damage),
+            format!("Matched Edge: deals {} + {}", self.term("value"), self.matched_edge_damage),
+            format!("Double Trouble: deals {} + {} + {}", self.double_trouble_damage, self.term("first"), self.term("second")),
+            format!("Jackpot: recovers {} HP and MP", self.jackpot_recovery),
+            format!("Magic Flush: deals {} + {} (all four cards) to every enemy", self.magic_flush_damage, self.term("value")),
+            format!("Blinding Flush: deals {} + {} (all four cards) to every enemy", self.blinding_flush_damage, self.term("value")),
+            format!("Full Status: heals {} + {} + {}", self.full_status_heal, self.term("triple"), self.term("pair")),
+        ]
+    }
+}
+
+// How a target reacts to damage of one element
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, poise::ChoiceParameter)]
+pub enum Affinity {
+    Vulnerable,
+    Resistant,
+    Immune,
+    Absorbs,
+}
+
+impl Affinity {
+    // Absorbed damage comes back negative: the target heals that much
+    pub fn apply(&self, damage: i64) -> i64 {
+        match self {
+            Affinity::Vulnerable => damage.saturating_mul(2),
+            Affinity::Resistant => damage / 2,
+            Affinity::Immune => 0,
+            Affinity::Absorbs => damage.saturating_neg(),
+        }
+    }
+
+    pub fn verb(&self) -> &'static str {
+        match self {
+            Affinity::Vulnerable => "is vulnerable to",
+            Affinity::Resistant => "resists",
+            Affinity::Immune => "is immune to",
+            Affinity::Absorbs => "absorbs",
+        }
+    }
+
+    pub fn note(&self) -> &'static str {
+        match self {
+            Affinity::Vulnerable => "weakness!",
+            Affinity::Resistant => "resisted",
+            Affinity::Immune => "immune",
+            Affinity::Absorbs => "absorbed",
+        }
+    }
+}
+
+// The numbers a resolved hand produces, after any table overrides
+#[derive(Clone)]
+pub struct HandEffect {
+    pub damage: Option<i64>,
+    pub heal: Option<i64>,
+    // How the damage was worked out, shown where the rules text calls for it
+    pub breakdown: Option<String>,
+    text: Option<String>,
+}
+
+impl HandEffect {
+    pub fn resolve(hand: &HandType, power: &PowerCoefficients, custom: Option<&EffectOverride>) -> Self {
+        let (damage, heal) = default_numbers(hand, power);
+        let breakdown = match hand {
+            HandType::DoubleTrouble { first_pair_value, second_pair_value, .. } => Some(format!("{} + {} + {}",
+                power.double_trouble_damage,
+                power.scaled(*first_pair_value),
+                power.scaled(*second_pair_value))),
+            _ => None,
+        };
+        let mut effect = HandEffect { damage, heal, breakdown, text: None };
+        let Some(custom) = custom else {
+            return effect;
+        };
+
+        let vars = hand_variables(hand);
+        // A formula that fails at runtime (e.g. dividing by zero) falls back to the rules as written
+        if let Some(formula) = &custom.damage_formula {
+            if let Ok(damage) = evaluate(formula, &vars) {
+                effect.damage = Some(damage.max(0));
+                // The breakdown only holds for the power level's formula
+                effect.breakdown = None;
+            }
+        }
+        if let Some(formula) = &custom.heal_formula {
+            if let Ok(heal) = evaluate(formula, &vars) {
+                effect.heal = Some(heal.max(0));
+            }
+        }
+        effect.text = custom.text.clone();
+        effect
+    }
+
+    // Flat extra damage on top of the hand's own, e.g. from a character's affinity
+    pub fn add_damage(&mut self, bonus: i64) {
+        let Some(damage) = self.damage.as_mut() else {
+            return;
+        };
+        *damage = damage.saturating_add(bonus);
+        if let Some(breakdown) = self.breakdown.as_mut() {
+            breakdown.push_str(&format!(" + {}", bonus));
+        }
+    }
+
+    pub fn damage_against(&self, affinity: Option<Affinity>) -> Option<i64> {
+        self.damage.map(|damage| affinity.map_or(damage, |a| a.apply(damage)))
+    }
+
+    pub fn render(&self, hand: &HandType, elements_str: &str) -> String {
+        let Some(text) = &self.text else {
+            return hand.effect_text(elements_str, self);
+        };
+        let value = hand_variables(hand).iter()
+            .find(|(name, _)| *name == "value")
+            .map(|(_, value)| *value)
+            .unwrap_or(0);
+        text.replace("{damage}", &format_number(self.damage))
+            .replace("{heal}", &format_number(self.heal))
+            .replace("{value}", &value.to_string())
+            .replace("{elements}", elements_str)
+    }
+}
+
+pub fn format_number(number: Option<i64>) -> String {
+    number.map(|n| n.to_string()).unwrap_or_else(|| "-".to_string())
+}
+
+// The damage and healing from the table's power level
+fn default_numbers(hand: &HandType, power: &PowerCoefficients) -> (Option<i64>, Option<i64>) {
+    match hand {
+        HandType::TripleThreat { value, .. } => (
+            Some(power.scaled(*value).saturating_add(power.triple_threat_damage)),
+            Some(power.scaled(*value).saturating_add(power.triple_threat_heal)),
+        ),
+        HandType::MatchedEdge { value, .. } => (Some(power.scaled(*value).saturating_add(power.matched_edge_damage)), None),
+        HandType::Jackpot { .. } => (None, Some(power.jackpot_recovery)),
+        HandType::DoubleTrouble { first_pair_value, second_pair_value, .. } => (
+            Some(power.double_trouble_damage.saturating_add(power.scaled(*first_pair_value)).saturating_add(power.scaled(*second_pair_value))),
+            None,
+        ),
+        HandType::MagicFlush { .. } => {
+            let total: u8 = hand.values().iter().sum();
+            (Some(power.magic_flush_damage.saturating_add(power.scaled(total))), None)
+        },
+        HandType::BlindingFlush { .. } => {
+            let total: u8 = hand.values().iter().sum();
+            (Some(power.blinding_flush_damage.saturating_add(power.scaled(total))), None)
+        },
+        HandType::FullStatus { triple_value, pair_value, .. } => (
+            None,
+            Some(power.full_status_heal.saturating_add(power.scaled(*triple_value)).saturating_add(power.scaled(*pair_value))),
+        ),
+        // Only the guild's formulas give a homebrew hand any numbers
+        HandType::Homebrew { .. } => (None, None),
+    }
+}
+
+fn hand_variables(hand: &HandType) -> Vec<(&'static str, i64)> {
+    match hand {
+        HandType::TripleThreat { value, .. }
+        | HandType::MatchedEdge { value, .. }
+        | HandType::Jackpot { value, .. } => vec![("value", *value as i64)],
+        HandType::DoubleTrouble { first_pair_value, second_pair_value, .. } => vec![
+            ("value", *first_pair_value as i64 + *second_pair_value as i64),
+            ("first", *first_pair_value as i64),
+            ("second", *second_pair_value as i64),
+        ],
+        HandType::FullStatus { triple_value, pair_value, .. } => vec![
+            ("value", *triple_value as i64 * 3 + *pair_value as i64 * 2),
+            ("triple", *triple_value as i64),
+            ("pair", *pair_value as i64),
+        ],
+        HandType::MagicFlush { low_value, .. } | HandType::BlindingFlush { low_value, .. } => vec![
+            ("value", hand.values().iter().map(|&v| v as i64).sum()),
+            ("low", *low_value as i64),
+            ("high", *low_value as i64 + 3),
+        ],
+        HandType::Homebrew { values, .. } => vec![
+            ("value", values.iter().map(|&v| v as i64).sum()),
+            ("low", values.iter().min().copied().unwrap_or(0) as i64),
+            ("high", values.iter().max().copied().unwrap_or(0) as i64),
+        ],
+    }
+}
+
+// Check a formula when the GM sets it, so typos surface then rather than mid-combat
+pub fn validate_formula(formula: &str, kind: HandKind) -> Result<(), String> {
+    // Distinct sample values so a formula like `value / (first - second)` isn't rejected by accident
+    let vars: Vec<(&str, i64)> = kind.variables().iter()
+        .enumerate()
+        .map(|(i, name)| (*name, 10 + i as i64))
+        .collect();
+    evaluate(formula, &vars).map(|_| ())
+}
+
+// Longest formula a GM can set. The parser recurses on every `(` and `-`, so this also
+// bounds how deep it can go.
+pub const MAX_FORMULA_LEN: usize = 200;
+
+// Evaluates integer arithmetic with + - * / and parentheses over named variables
+pub fn evaluate(formula: &str, vars: &[(&str, i64)]) -> Result<i64, String> {
+    if formula.chars().count() > MAX_FORMULA_LEN {
+        return Err(format!("Formulas can be at most {} characters", MAX_FORMULA_LEN));
+    }
+    let tokens = tokenize(formula)?;
+    let mut parser = Parser { tokens: &tokens, position: 0, vars };
+    let value = parser.expression()?;
+    if parser.position != tokens.len() {
+        return Err(format!("Unexpected `{}` in formula", tokens[parser.position].describe()));
+    }
+    Ok(value)
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Op(char),
+    Open,
+    Close,
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Number(n) => n.to_string(),
+            Token::Name(name) => name.clone(),
+            Token::Op(op) => op.to_string(),
+            Token::Open => "(".to_string(),
+            Token::Close => ")".to_string(),
+        }
+    }
+}
+
+fn tokenize(formula: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = formula.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() {
+            let mut number = String::new();
+            while let Some(&d) = chars.peek().filter(|d| d.is_ascii_digit()) {
+                number.push(d);
+                chars.next();
+            }
+            let number = number.parse().map_err(|_| format!("`{}` is too large", number))?;
+            tokens.push(Token::Number(number));
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let mut name = String::new();
+            while let Some(&d) = chars.peek().filter(|d| d.is_ascii_alphanumeric() || **d == '_') {
+                name.push(d);
+                chars.next();
+            }
+            tokens.push(Token::Name(name));
+        } else {
+            tokens.push(match c {
+                '+' | '-' | '*' | '/' => Token::Op(c),
+                '(' => Token::Open,
+                ')' => Token::Close,
+                _ => return Err(format!("Unexpected `{}` in formula", c)),
+            });
+            chars.next();
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+    vars: &'a [(&'a str, i64)],
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.position)
+    }
+
+    // expression := term (('+' | '-') term)*
+    fn expression(&mut self) -> Result<i64, String> {
+        let mut value = self.term()?;
+        while let Some(Token::Op(op @ ('+' | '-'))) = self.peek() {
+            let op = *op;
+            self.position += 1;
+            let rhs = self.term()?;
+            value = if op == '+' { value.checked_add(rhs) } else { value.checked_sub(rhs) }
+                .ok_or("Formula result is too large")?;
+        }
+        Ok(value)
+    }
+
+    // term := factor (('*' | '/') factor)*
+    fn term(&mut self) -> Result<i64, String> {
+        let mut value = self.factor()?;
+        while let Some(Token::Op(op @ ('*' | '/'))) = self.peek() {
+            let op = *op;
+            self.position += 1;
+            let rhs = self.factor()?;
+            value = if op == '*' {
+                value.checked_mul(rhs).ok_or("Formula result is too large")?
+            } else {
+                value.checked_div(rhs).ok_or("Formula divides by zero")?
+            };
+        }
+        Ok(value)
+    }
+
+    // factor := number | name | '-' factor | '(' expression ')'
+    fn factor(&mut self) -> Result<i64, String> {
+        let Some(token) = self.peek() else {
+            return Err("Formula ends unexpectedly".to_string());
+        };
+        self.position += 1;
+        match token {
+            Token::Number(n) => Ok(*n),
+            Token::Name(name) => self.vars.iter()
+                .find(|(var, _)| var == name)
+                .map(|(_, value)| *value)
+                .ok_or_else(|| format!("Unknown name `{}` in formula", name)),
+            Token::Op('-') => self.factor()?.checked_neg().ok_or_else(|| "Formula result is too large".to_string()),
+            Token::Open => {
+                let value = self.expression()?;
+                if self.peek() != Some(&Token::Close) {
+                    return Err("Missing `)` in formula".to_string());
+                }
+                self.position += 1;
+                Ok(value)
+            },
+            other => Err(format!("Unexpected `{}` in formula", other.describe())),
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+use crate::types::{format_element_list, CardType, ElementType, HandType};
+use crate::state::DeckEnd;
+
+// Events kept in memory per combat before older ones are archived to disk
+const MAX_RECENT_EVENTS: usize = 200;
+const ARCHIVE_SEGMENT_SIZE: usize = 100;
+const ARCHIVE_DIR: &str = "archive";
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CombatEvent {
+    pub at: u64,
+    pub kind: EventKind,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub enum EventKind {
+    // A fresh deck, in draw order (last card is drawn first)
+    Dealt { deck: Vec<CardType> },
+    Drew { cards: Vec<CardType> },
+    Discarded { position: usize, card: CardType },
+    Burned { position: usize, card: CardType },
+    // A card discarded as a reaction to soften incoming damage
+    Guarded { position: usize, card: CardType, reduction: u8 },
+    // A card discarded to add its value to a check
+    Boosted { position: usize, card: CardType, bonus: u8 },
+    // A card put back from the hand onto the deck
+    Tucked { position: usize, card: CardType, end: DeckEnd },
+    // A card handed to another player, and one taken from another player's hand
+    Gave { position: usize, card: CardType, to: String },
+    Received { card: CardType, from: String },
+    Reshuffled { deck: Vec<CardType> },
+    Mulligan { count: usize },
+    // The whole hand thrown away; the discards and the draw follow as their own events
+    HandRedrawn { count: usize },
+    Resolved { hand: HandType, element: Option<ElementType>, targets: Vec<String> },
+    ZonesRestored {
+        deck: Vec<CardType>,
+        hand: Vec<CardType>,
+        discard: Vec<CardType>,
+        #[serde(default)]
+        burned: Vec<CardType>,
+    },
+    HandReordered { hand: Vec<CardType> },
+    // A card taken out of the deck into the hand, and the deck as it was shuffled afterwards
+    Searched { card: CardType, deck: Vec<CardType> },
+    // The whole deck after the player rearranged its top, in draw order
+    DeckReordered { deck: Vec<CardType> },
+    JokerAssigned { position: usize, card: CardType },
+    TurnEnded { turn: u32 },
+}
+
+impl CombatEvent {
+    pub fn now(kind: EventKind) -> Self {
+        CombatEvent {
+            at: unix_now(),
+            kind,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match &self.kind {
+            EventKind::Dealt { deck } => format!("Shuffled a fresh deck of {} cards", deck.len()),
+            EventKind::Drew { cards } => format!("Drew {}", format_card_list(cards)),
+            EventKind::Discarded { card, .. } => format!("Discarded {}", card.label()),
+            EventKind::Guarded { card, reduction, .. } => format!("Discarded {} to guard, stopping {} damage", card.label(), reduction),
+            EventKind::Boosted { card, bonus, .. } => format!("Discarded {} for +{} on a check", card.label(), bonus),
+            EventKind::Burned { card, .. } => format!("Burned {}", card.label()),
+            EventKind::Tucked { card, end, .. } => format!("Put {} on the {} of the deck", card.label(), end.label()),
+            EventKind::Gave { card, to, .. } => format!("Gave {} to {}", card.label(), to),
+            EventKind::Received { card, from } => format!("Received {} from {}", card.label(), from),
+            EventKind::Reshuffled { deck } => format!("Shuffled the discard back in ({} cards in deck)", deck.len()),
+            EventKind::Mulligan { count } => format!("Mulliganed {} card(s)", count),
+            EventKind::HandRedrawn { count } => format!("Threw away the whole hand of {} card(s)", count),
+            EventKind::Resolved { hand, element, targets } => {
+                let mut text = format!("Resolved {}", hand.to_string());
+                if let Some(element) = element {
+                    text.push_str(&format!(" as {}", format_element_list(std::slice::from_ref(element))));
+                }
+                if !targets.is_empty() {
+                    text.push_str(&format!(" targeting {}", targets.join(", ")));
+                }
+                text
+            },
+            EventKind::ZonesRestored { .. } => "Re-opened the last resolution".to_string(),
+            EventKind::HandReordered { .. } => "Rearranged hand".to_string(),
+            EventKind::DeckReordered { .. } => "Rearranged the top of the deck".to_string(),
+            EventKind::Searched { card, .. } => format!("Searched the deck for {} and shuffled it", card.label()),
+            EventKind::JokerAssigned { position, card } => format!("Set the joker in position {} to {}", position + 1, card.label()),
+            EventKind::TurnEnded { turn } => format!("Ended turn {}", turn),
+        }
+    }
+}
+
+// Cards drawn from the deck since the last deal, in the order they left it. A card that left
+// more than once is listed each time. Tucked cards and the refill of a re-opened resolution
+// went back without being seen, so they don't count.
+pub fn drawn_since_deal(events: &[CombatEvent]) -> Vec<CardType> {
+    let mut drawn: Vec<CardType> = Vec::new();
+    // Cards drawn before the last reshuffle may be back in the deck without having been undone
+    let mut since_reshuffle = 0;
+    for event in events {
+        match &event.kind {
+            EventKind::Dealt { .. } => {
+                drawn.clear();
+                since_reshuffle = 0;
+            },
+            EventKind::Drew { cards } => drawn.extend(cards.iter().cloned()),
+            EventKind::Searched { card, .. } => drawn.push(card.clone()),
+            EventKind::Reshuffled { .. } => since_reshuffle = drawn.len(),
+            EventKind::Tucked { card, .. } => {
+                if let Some(index) = drawn.iter().rposition(|drawn| drawn.id() == card.id()) {
+                    drawn.remove(index);
+                    if index < since_reshuffle {
+                        since_reshuffle -= 1;
+                    }
+                }
+            },
+            EventKind::ZonesRestored { deck, .. } => {
+                let mut later = drawn.split_off(since_reshuffle);
+                later.retain(|drawn| !deck.iter().any(|card| card.id() == drawn.id()));
+                drawn.extend(later);
+            },
+            _ => {},
+        }
+    }
+    drawn
+}
+
+fn format_card_list(cards: &[CardType]) -> String {
+    cards.iter().map(|card| card.label()).collect::<Vec<_>>().join(", ")
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// Per-combat event history, bounded in memory with older segments on disk
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct EventLog {
+    pub combat_id: String,
+    pub recent: VecDeque<CombatEvent>,
+    // Number of segments already written to disk, oldest first
+    #[serde(default)]
+    pub archived_segments: usize,
+}
+
+impl EventLog {
+    pub fn push(&mut self, event: CombatEvent) {
+        // Combats saved before logging existed have no id yet
+        if self.combat_id.is_empty() {
+            self.combat_id = format!("combat_{}", unix_now());
+        }
+        self.recent.push_back(event);
+        if self.recent.len() > MAX_RECENT_EVENTS {
+            if let Err(e) = self.archive_oldest() {
+                // Keep everything in memory rather than lose history
+                eprintln!("Failed to archive events for {}: {}", self.combat_id, e);
+            }
+        }
+    }
+
+    pub fn total_events(&self) -> usize {
+        self.archived_segments * ARCHIVE_SEGMENT_SIZE + self.recent.len()
+    }
+
+    fn segment_path(&self, segment: usize) -> String {
+        format!("{}/{}_{}.json", ARCHIVE_DIR, self.combat_id, segment)
+    }
+
+    fn archive_oldest(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let segment: Vec<CombatEvent> = self.recent.iter().take(ARCHIVE_SEGMENT_SIZE).cloned().collect();
+        fs::create_dir_all(ARCHIVE_DIR)?;
+        fs::write(self.segment_path(self.archived_segments), serde_json::to_string(&segment)?)?;
+
+        self.recent.drain(..ARCHIVE_SEGMENT_SIZE);
+        self.archived_segments += 1;
+        Ok(())
+    }
+
+    fn load_segment(&self, segment: usize) -> Result<Vec<CombatEvent>, Box<dyn std::error::Error + Send + Sync>> {
+        let json = fs::read_to_string(self.segment_path(segment))?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    // Events in chronological order between two absolute positions, loading archived
+    // segments from disk only when the range reaches into them
+    pub fn events_between(&self, start: usize, end: usize) -> Result<Vec<CombatEvent>, Box<dyn std::error::Error + Send + Sync>> {
+        let end = end.min(self.total_events());
+        let archived_len = self.archived_segments * ARCHIVE_SEGMENT_SIZE;
+        let mut events = Vec::new();
+
+        let mut position = start;
+        while position < end.min(archived_len) {
+            let segment = position / ARCHIVE_SEGMENT_SIZE;
+            let loaded = self.load_segment(segment)?;
+            let from = position % ARCHIVE_SEGMENT_SIZE;
+            let to = (end - segment * ARCHIVE_SEGMENT_SIZE).min(ARCHIVE_SEGMENT_SIZE);
+            events.extend(loaded.into_iter().skip(from).take(to.saturating_sub(from)));
+            position = (segment + 1) * ARCHIVE_SEGMENT_SIZE;
+        }
+
+        let from = start.max(archived_len) - archived_len;
+        let to = end.saturating_sub(archived_len);
+        events.extend(self.recent.iter().skip(from).take(to.saturating_sub(from)).cloned());
+        Ok(events)
+    }
+
+    // One page of history, newest page first
+    pub fn page(&self, page: usize, per_page: usize) -> Result<Vec<CombatEvent>, Box<dyn std::error::Error + Send + Sync>> {
+        let total = self.total_events();
+        let end = total.saturating_sub(page * per_page);
+        let start = end.saturating_sub(per_page);
+        self.events_between(start, end)
+    }
+}
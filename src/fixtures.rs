@@ -0,0 +1,105 @@
+use std::fs;
+use crate::config::GuildConfig;
+use crate::state::{PlayerState, DEFAULT_HAND_SIZE};
+use crate::types::{CardKey, CardType, DeckTemplate, ElementType};
+
+const FIXTURE_DIR: &str = "fixtures";
+
+// Combats in states that are awkward to reach by playing, for development and bug triage
+pub fn all() -> Vec<(&'static str, PlayerState)> {
+    vec![
+        ("all_jokers", all_jokers()),
+        ("near_jackpot", near_jackpot()),
+        ("empty_deck", empty_deck()),
+        ("max_hand", max_hand()),
+    ]
+}
+
+pub fn names() -> Vec<&'static str> {
+    all().into_iter().map(|(name, _)| name).collect()
+}
+
+// Write every fixture as JSON, returning the paths written
+pub fn write_all() -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+    fs::create_dir_all(FIXTURE_DIR)?;
+    let mut paths = Vec::new();
+    for (name, state) in all() {
+        let path = fixture_path(name);
+        fs::write(&path, serde_json::to_string_pretty(&state)?)?;
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+// A fixture from disk if one was written (and maybe hand-edited), otherwise a fresh one
+pub fn load(name: &str) -> Result<PlayerState, String> {
+    match fs::read_to_string(fixture_path(name)) {
+        Ok(json) => {
+            let mut state: PlayerState = serde_json::from_str(&json)
+                .map_err(|e| format!("{} is not a valid fixture: {}", fixture_path(name), e))?;
+            // Hand-edited fixtures tend to leave card IDs out
+            state.assign_missing_card_ids();
+            Ok(state)
+        },
+        Err(_) => all()
+            .into_iter()
+            .find(|(fixture, _)| *fixture == name)
+            .map(|(_, state)| state)
+            .ok_or_else(|| format!("Unknown fixture `{}`, try one of: {}", name, names().join(", "))),
+    }
+}
+
+fn fixture_path(name: &str) -> String {
+    format!("{}/{}.json", FIXTURE_DIR, name)
+}
+
+// Every card in hand is a joker
+fn all_jokers() -> PlayerState {
+    let mut state = PlayerState::new(None, &DeckTemplate { jokers: DEFAULT_HAND_SIZE as u8, ..DeckTemplate::default() });
+    let jokers: [CardKey; DEFAULT_HAND_SIZE] = std::array::from_fn(|_| CardKey::Joker);
+    set_hand(&mut state, &jokers);
+    state
+}
+
+// Three of a kind in hand with the fourth on top of the deck
+fn near_jackpot() -> PlayerState {
+    let mut state = PlayerState::new(None, &DeckTemplate::default());
+    set_hand(&mut state, &[
+        CardKey::Number(4, ElementType::Fire),
+        CardKey::Number(4, ElementType::Ice),
+        CardKey::Number(4, ElementType::Earth),
+        CardKey::Number(1, ElementType::Air),
+        CardKey::Number(6, ElementType::Fire),
+    ]);
+    let fourth = take(&mut state.deck.cards, &CardKey::Number(4, ElementType::Air));
+    state.deck.cards.push(fourth);
+    state
+}
+
+// Nothing left to draw, the next draw has to reshuffle the discard
+fn empty_deck() -> PlayerState {
+    let mut state = PlayerState::new(None, &DeckTemplate::default());
+    state.draw_to_hand(state.hand_size).expect("a fresh deck has a full hand");
+    state.discard.append(&mut state.deck.cards);
+    state
+}
+
+// A hand already at the default limit for extra draws
+fn max_hand() -> PlayerState {
+    let mut state = PlayerState::new(None, &DeckTemplate::default());
+    state.draw_to_hand(GuildConfig::default().max_hand_size()).expect("a fresh deck has enough cards");
+    state
+}
+
+fn set_hand(state: &mut PlayerState, keys: &[CardKey]) {
+    state.deck.cards.append(&mut state.hand);
+    for key in keys {
+        let card = take(&mut state.deck.cards, key);
+        state.hand.push(card);
+    }
+}
+
+fn take(cards: &mut Vec<CardType>, key: &CardKey) -> CardType {
+    let position = cards.iter().position(|card| card.key() == *key).expect("fixture card missing from the deck");
+    cards.remove(position)
+}
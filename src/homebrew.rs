@@ -0,0 +1,58 @@
+use serde::{Serialize, Deserialize};
+use crate::effects::EffectOverride;
+
+// Most homebrew hands a guild can define, since each one is another search over the hand
+pub const MAX_HOMEBREW_HANDS: usize = 10;
+
+// The shape of cards a homebrew hand is made of. Jokers are wild in all of them.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum HandPattern {
+    // This many cards of one value
+    OfAKind(usize),
+    // This many consecutive values, in any suits
+    Straight(usize),
+    // This many cards of one suit, whatever their values
+    Flush(usize),
+}
+
+impl HandPattern {
+    pub fn size(&self) -> usize {
+        match self {
+            HandPattern::OfAKind(size) | HandPattern::Straight(size) | HandPattern::Flush(size) => *size,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            HandPattern::OfAKind(size) => format!("{} of a kind", size),
+            HandPattern::Straight(size) => format!("straight of {}", size),
+            HandPattern::Flush(size) => format!("flush of {}", size),
+        }
+    }
+}
+
+// A hand type a guild made up, detected next to the built-in ones
+#[derive(Serialize, Deserialize, Clone)]
+pub struct HomebrewHand {
+    pub name: String,
+    pub pattern: HandPattern,
+    // Its numbers, text and MP cost, as a built-in hand's house rules would set them
+    #[serde(default)]
+    pub effect: EffectOverride,
+}
+
+impl HomebrewHand {
+    pub fn describe(&self) -> String {
+        let mut text = format!("**{}**: {}", self.name, self.pattern.describe());
+        if let Some(cost) = self.effect.mp_cost {
+            text.push_str(&format!(", {} MP", cost));
+        }
+        if let Some(formula) = &self.effect.damage_formula {
+            text.push_str(&format!(", damage `{}`", formula));
+        }
+        if let Some(formula) = &self.effect.heal_formula {
+            text.push_str(&format!(", healing `{}`", formula));
+        }
+        text
+    }
+}
@@ -0,0 +1,29 @@
+// Everything but the Discord commands, so tests and benches can use it without a bot
+pub mod types;
+pub mod state;
+pub mod metrics;
+pub mod config;
+pub mod api;
+pub mod skills;
+pub mod character;
+pub mod load;
+pub mod theme;
+pub mod events;
+pub mod effects;
+pub mod modifiers;
+pub mod selftest;
+pub mod settings;
+pub mod replay;
+pub mod parse;
+pub mod party;
+pub mod analysis;
+pub mod dice;
+pub mod homebrew;
+pub mod detect;
+pub mod archive;
+pub mod approvals;
+pub mod shuffle;
+pub mod presets;
+pub mod arcana;
+#[cfg(feature = "fixtures")]
+pub mod fixtures;
@@ -0,0 +1,46 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+// Waiting this long for the state lock means commands are queueing up
+pub const QUEUE_LATENCY_LIMIT: Duration = Duration::from_millis(1500);
+pub const STORE_LATENCY_LIMIT: Duration = Duration::from_secs(2);
+
+static QUEUE_OVERLOADED: AtomicBool = AtomicBool::new(false);
+static STORE_OVERLOADED: AtomicBool = AtomicBool::new(false);
+
+pub enum Pressure {
+    CommandQueue,
+    Store,
+}
+
+// Degraded mode drops cosmetic output and postpones heavy jobs
+pub fn is_degraded() -> bool {
+    QUEUE_OVERLOADED.load(Ordering::Relaxed) || STORE_OVERLOADED.load(Ordering::Relaxed)
+}
+
+// Feed a latency sample in. Returns the new mode if degraded mode was entered or left.
+pub fn observe(pressure: Pressure, latency: Duration) -> Option<bool> {
+    let (flag, limit) = match pressure {
+        Pressure::CommandQueue => (&QUEUE_OVERLOADED, QUEUE_LATENCY_LIMIT),
+        Pressure::Store => (&STORE_OVERLOADED, STORE_LATENCY_LIMIT),
+    };
+    
+    let was_degraded = is_degraded();
+    // Only recover once well under the limit so the mode doesn't flap
+    if latency > limit {
+        flag.store(true, Ordering::Relaxed);
+    } else if latency < limit / 2 {
+        flag.store(false, Ordering::Relaxed);
+    }
+    
+    let degraded = is_degraded();
+    (degraded != was_degraded).then_some(degraded)
+}
+
+pub fn mode_change_message(degraded: bool) -> &'static str {
+    if degraded {
+        "⚠️ Entering degraded mode: the bot is under heavy load, so responses are compact and heavy jobs are paused."
+    } else {
+        "✅ Load is back to normal, leaving degraded mode."
+    }
+}
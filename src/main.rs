@@ -1,283 +1,4703 @@
-mod types;
-mod state;
+use destiny_draw::{analysis, api, approvals, arcana, config, events, load, parse, party, replay, selftest, skills};
+#[cfg(feature = "fixtures")]
+use destiny_draw::fixtures;
 
 use poise::serenity_prelude as serenity;
 use dotenv::dotenv;
-use types::{format_element_list, HandType};
-use crate::state::PlayerStateManager;
+use destiny_draw::types::{format_element_list, ElementType, HandType};
+use destiny_draw::state::{ActionKind, Contribution, ContributionKind, Declaration, DeckEnd, ElementPreview, HandDelivery, HandSort, PlayerState, PlayerStateManager, AFFINITY_BONUS_DAMAGE};
+use destiny_draw::config::GuildConfig;
+use destiny_draw::theme::Theme;
 use std::sync::Arc;
 use tokio::sync::Mutex;
-use crate::types::CardType;
+use destiny_draw::types::{CardId, CardType, Deck, DeckKind, DeckTemplate, Suit};
+use destiny_draw::metrics::{CommandMetrics, ProbeStage, Stage};
+use destiny_draw::skills::{FabulaPoints, SkillModule};
+use destiny_draw::load::Pressure;
+use destiny_draw::events::{EventKind, EventLog};
+use destiny_draw::effects::{validate_formula, Affinity, HandKind, PowerCoefficients, PowerLevel};
+use destiny_draw::settings::Settings;
+use destiny_draw::character::{is_affine, sanitize_display_name, JokerSlot, AFFINITY_MARK};
+use destiny_draw::modifiers::{ModifierKind, ValueModifier};
+use destiny_draw::api::{ApiKey, DEFAULT_RATE_LIMIT, MAX_API_KEYS, MAX_RATE_LIMIT};
+use destiny_draw::approvals::{ApprovalAction, ApprovalRequest};
+use destiny_draw::shuffle::ShuffleStrategy;
+use destiny_draw::presets::DeckPreset;
+use destiny_draw::dice::CheckDice;
+use destiny_draw::homebrew::{HomebrewHand, MAX_HOMEBREW_HANDS};
+use std::time::{Duration, Instant};
+use std::collections::BTreeMap;
 
 type Error = Box<dyn std::error::Error + Send + Sync>;
 pub struct Data {
     player_state_manager: Arc<Mutex<PlayerStateManager>>,
+    metrics: Arc<Mutex<CommandMetrics>>,
+    settings: Arc<Mutex<Settings>>,
 }
 
-fn format_hand_display(hand: &[CardType]) -> String {
+// Cards in the character's affine element get a mark, pass None for no highlighting
+fn format_hand_display(hand: &[CardType], affinity: Option<&ElementType>) -> String {
     if hand.is_empty() {
         return String::from("Your hand is empty!");
     }
+    if load::is_degraded() {
+        return format_hand_compact(hand);
+    }
 
     let mut display = String::from("Your hand:\n");
     for (i, card) in hand.iter().enumerate() {
         let card_display = match card {
-            CardType::Number(num, suit) => {
+            CardType::Number(num, suit, _) => {
                 format!("{}. {} {}\n", 
                     i + 1,
                     CardType::number_to_emoji(num.unwrap_or(0)),
                     suit.symbol
                 )
             },
-            CardType::Joker { current_value, current_suit, symbol } => {
+            CardType::Joker { current_value, current_suit, symbol, name, .. } => {
+                let name = name.as_ref().map(|n| format!(" — *{}*", n)).unwrap_or_default();
                 match (current_value, current_suit) {
-                    (Some(val), Some(suit)) => format!("{}. {} {}\n",
+                    (Some(val), Some(suit)) => format!("{}. {} {}{}\n",
                         i + 1,
                         CardType::number_to_emoji(*val),
-                        suit.symbol
+                        suit.symbol,
+                        name
                     ),
-                    _ => format!("{}. :question: {}\n",
+                    _ => format!("{}. :question: {}{}\n",
                         i + 1,
-                        symbol
+                        symbol,
+                        name
                     )
                 }
             }
         };
-        display.push_str(&card_display);
+        if is_affine(card, affinity) {
+            display.push_str(&format!("{} {}\n", card_display.trim_end(), AFFINITY_MARK));
+        } else {
+            display.push_str(&card_display);
+        }
+    }
+    display
+}
+
+// Discard pile grouped by suit and sorted by value, for counting what's left
+fn format_discard_display(discard: &[CardType], elements: &[ElementType]) -> String {
+    let mut display = String::new();
+    for element in elements.iter().cloned() {
+        let mut values: Vec<u8> = discard.iter()
+            .filter_map(|card| match card {
+                CardType::Number(value, suit, _) if suit.element == element => Some(value.unwrap_or(0)),
+                _ => None,
+            })
+            .collect();
+        values.sort_unstable();
+        let cards = if values.is_empty() {
+            "none".to_string()
+        } else {
+            values.iter().map(|&v| CardType::number_to_emoji(v)).collect::<Vec<_>>().join(" ")
+        };
+        display.push_str(&format!("{} {}: {}\n", Suit::of(element.clone()).symbol, element.name(), cards));
+    }
+    let jokers = discard.iter().filter(|card| matches!(card, CardType::Joker { .. })).count();
+    if jokers > 0 {
+        display.push_str(&format!("🃏 Jokers: {}\n", jokers));
     }
     display
 }
 
+// Single-line hand display used while shedding load
+fn format_hand_compact(hand: &[CardType]) -> String {
+    let cards = hand.iter().enumerate().map(|(i, card)| {
+        match card {
+            CardType::Number(num, suit, _) => format!("{}:{}{}", i + 1, num.unwrap_or(0), suit.symbol),
+            // Compact display skips signature names to keep the line short
+            CardType::Joker { current_value: Some(val), current_suit: Some(suit), .. } => {
+                format!("{}:{}{}", i + 1, val, suit.symbol)
+            },
+            CardType::Joker { symbol, .. } => format!("{}:{}", i + 1, symbol),
+        }
+    });
+    format!("Hand: {}", cards.collect::<Vec<_>>().join(" "))
+}
+
 #[poise::command(slash_command)]
 pub async fn start_new_combat(
     ctx: poise::Context<'_, Data, Error>,
+    #[description = "Deal from a ready-made deck instead of the table's, e.g. to match physical cards"] deck: Option<DeckPreset>,
 ) -> Result<(), Error> {
     let user_id = ctx.author().id;
-    let mut player_state_manager = ctx.data().player_state_manager.lock().await;
-    let player = player_state_manager.start_new_combat(user_id);
-    player.draw_to_hand(5)?;
+    let mut player_state_manager = lock_state(ctx).await;
+    let player = player_state_manager.start_new_combat(user_id, ctx.guild_id(), deck);
+    player.draw_to_hand(player.hand_size)?;
     
     // Get the hand before dropping the lock
     let hand = player.hand.clone();
+    let names = format_arcana_names(&hand, &player.deck_template);
+    let affinity = player_state_manager.affinity(user_id);
+    let delivery = player_state_manager.preferences(user_id).hand_delivery;
     drop(player_state_manager);
     
     // Combine both messages into one response
-    let message = format!("Combat started! Drew 5 cards.\n{}", format_hand_display(&hand));
-    ctx.say(message).await?;
+    let mut message = format!("Combat started! Drew {} cards.\n{}{}", hand.len(), format_hand_display(&hand, affinity.as_ref()), names);
+    if let Some(preset) = deck {
+        message.push_str(&format!("\n{}", preset.describe()));
+    }
+    let public = format!("Combat started! Drew {} cards, your hand is in your DMs.", hand.len());
+    deliver_hand(ctx, delivery, &public, message).await
+}
+
+// The trumps' names under a hand dealt from the major arcana, empty for any other deck
+fn format_arcana_names(hand: &[CardType], deck: &DeckTemplate) -> String {
+    if deck.kind != DeckKind::MajorArcana || hand.is_empty() {
+        return String::new();
+    }
+    format!("\nArcana: {}", arcana::card_names(hand))
+}
+
+// Send a message showing the player's hand. Players who want their hand by DM get it
+// there, and the channel only sees the public line.
+async fn deliver_hand(ctx: poise::Context<'_, Data, Error>, delivery: HandDelivery, public: &str, message: String) -> Result<(), Error> {
+    if delivery == HandDelivery::Channel {
+        return post_hand(ctx, message).await;
+    }
+    let mut delivered = true;
+    for chunk in chunk_message(&message) {
+        if ctx.author().direct_message(ctx.serenity_context(), |m| m.content(&chunk)).await.is_err() {
+            delivered = false;
+            break;
+        }
+    }
+    if delivered {
+        ctx.say(public).await?;
+    } else {
+        // Closed DMs mustn't put the hand in the channel, only the player sees this
+        say_chunked(ctx, format!("{}\nI couldn't DM you, so here it is.", message), true).await?;
+    }
+    Ok(())
+}
+
+const HAND_PANEL_TTL: Duration = Duration::from_secs(600);
+const HAND_PANEL_EXPIRED: &str = "This hand may be out of date, use /view_hand to see the current one.";
+
+// Show a hand in the channel. It goes stale as soon as the hand changes, so housekeeping
+// collapses it once it's old, the same as an expired prompt.
+async fn post_hand(ctx: poise::Context<'_, Data, Error>, message: String) -> Result<(), Error> {
+    for chunk in chunk_message(&message) {
+        let reply = ctx.send(|b| b.content(chunk)).await?;
+        let message_id = reply.message().await?.id;
+        lock_state(ctx).await.register_interaction(ctx.channel_id(), message_id, HAND_PANEL_TTL.as_secs(), HAND_PANEL_EXPIRED);
+    }
+    Ok(())
+}
+
+#[poise::command(slash_command)]
+pub async fn end_combat(
+    ctx: poise::Context<'_, Data, Error>,
+) -> Result<(), Error> {
+    let user_id = ctx.author().id;
+    let mut player_state_manager = lock_state(ctx).await;
+    let Some(summary) = player_state_manager.end_combat(user_id) else {
+        ctx.say("You don't have a combat to end. Use /start_new_combat to begin.").await?;
+        return Ok(());
+    };
+    let name = player_state_manager.display_name(user_id, &ctx.author().name);
+    drop(player_state_manager);
+    
+    ctx.say(format!("**{}'s combat is over.** It's kept in /archive as `{}`.\n{}", name, summary.combat_id, summary.describe())).await?;
+    Ok(())
+}
+
+const ARCHIVE_PAGE_SIZE: usize = 10;
+
+#[poise::command(slash_command, subcommands("archive_list", "archive_view"))]
+pub async fn archive(
+    _ctx: poise::Context<'_, Data, Error>,
+) -> Result<(), Error> {
+    Ok(())
+}
+
+#[poise::command(slash_command, rename = "list")]
+pub async fn archive_list(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "Page to show, 1 is the most recent"] page: Option<usize>,
+) -> Result<(), Error> {
+    let player_state_manager = lock_state(ctx).await;
+    let archived = player_state_manager.archived_combats(ctx.author().id);
+    
+    let pages = ((archived.len() + ARCHIVE_PAGE_SIZE - 1) / ARCHIVE_PAGE_SIZE).max(1);
+    let page = page.unwrap_or(1).clamp(1, pages);
+    // Newest first
+    let lines: Vec<String> = archived.iter()
+        .rev()
+        .skip((page - 1) * ARCHIVE_PAGE_SIZE)
+        .take(ARCHIVE_PAGE_SIZE)
+        .map(|summary| summary.headline())
+        .collect();
+    drop(player_state_manager);
+    
+    let mut message = format!("**Past combats** (page {} of {})\n", page, pages);
+    if lines.is_empty() {
+        message.push_str("No combats have ended yet. Finished combats show up here after /end_combat or /start_new_combat.");
+    } else {
+        message.push_str(&lines.join("\n"));
+        message.push_str("\nUse /archive view with an ID for the details.");
+    }
+    ctx.send(|b| b.content(message).ephemeral(true)).await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, rename = "view")]
+pub async fn archive_view(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "Combat ID from /archive list"] id: String,
+) -> Result<(), Error> {
+    let player_state_manager = lock_state(ctx).await;
+    let summary = player_state_manager.archived_combats(ctx.author().id)
+        .iter()
+        .find(|summary| summary.combat_id == id.trim())
+        .cloned();
+    drop(player_state_manager);
+    
+    let message = match summary {
+        Some(summary) => format!("**Combat `{}`**\n{}", summary.combat_id, summary.describe()),
+        None => format!("You don't have an ended combat `{}`. /archive list shows your IDs.", id.trim()),
+    };
+    ctx.send(|b| b.content(message).ephemeral(true)).await?;
     Ok(())
 }
 
 #[poise::command(slash_command)]
 pub async fn mulligan(
     ctx: poise::Context<'_, Data, Error>,
-    #[description = "Card positions to mulligan (1-5, space-separated)"] positions: String,
+    #[description = "Cards to mulligan, e.g. 1 3 5, 1-3 or fire 5, joker; leave empty for a menu"] positions: Option<String>,
 ) -> Result<(), Error> {
+    let Some(positions) = positions else {
+        return run_mulligan_prompt(ctx).await;
+    };
     let user_id = ctx.author().id;
-    let mut player_state_manager = ctx.data().player_state_manager.lock().await;
+    let mut player_state_manager = lock_state(ctx).await;
+    let config = player_state_manager.guild_config(ctx.guild_id());
     
     let Some(player) = player_state_manager.get_player_state(user_id) else {
         ctx.say("You haven't started a combat yet! Use /start_new_combat to begin.").await?;
         return Ok(());
     };
     
-    let mut indices: Vec<usize> = positions
-        .split_whitespace()
-        .filter_map(|s| s.parse::<usize>().ok())
-        .map(|n| n - 1) // Convert to 0-based indexing
-        .collect();
-    indices.sort_unstable_by(|a, b| b.cmp(a)); // Sort in reverse to remove from highest index first
+    let indices = match parse::cards(&positions, &player.hand) {
+        Ok(indices) => indices,
+        Err(e) => {
+            ctx.say(format!("Please give card positions (1-{}) or descriptions like fire 5, {}.", player.hand.len(), e)).await?;
+            return Ok(());
+        }
+    };
     
-    if indices.is_empty() || indices.len() > 5 || indices.iter().any(|&i| i >= player.hand.len()) {
-        ctx.say("Please provide 1-5 valid card positions (1-5)").await?;
+    let count = indices.len();
+    if let Err(e) = player.mulligan(indices, &config) {
+        ctx.say(e).await?;
         return Ok(());
     }
     
-    for &index in &indices {
-        player.discard_from_hand(index)?;
-    }
-    
-    player.draw_to_hand(indices.len())?;
-    
     // Get the hand before dropping the lock
     let hand = player.hand.clone();
+    let affinity = player_state_manager.affinity(user_id);
+    let delivery = player_state_manager.preferences(user_id).hand_delivery;
     drop(player_state_manager);
     
     // Combine both messages into one response
-    let message = format!("Mulligan complete!\n{}", format_hand_display(&hand));
-    ctx.say(message).await?;
+    let started = Instant::now();
+    let message = format!("Mulligan complete!\n{}", format_hand_display(&hand, affinity.as_ref()));
+    record_stage(ctx, Stage::Formatting, started).await;
+    
+    let started = Instant::now();
+    deliver_hand(ctx, delivery, &format!("Mulligan complete! Redrew {} card(s), your hand is in your DMs.", count), message).await?;
+    record_stage(ctx, Stage::Send, started).await;
+    Ok(())
+}
+
+const MULLIGAN_PROMPT_TIMEOUT: Duration = Duration::from_secs(120);
+const MULLIGAN_EXPIRED: &str = "Mulligan prompt expired, your hand wasn't changed.";
+
+// Pick cards to mulligan from a menu instead of typing positions
+async fn run_mulligan_prompt(ctx: poise::Context<'_, Data, Error>) -> Result<(), Error> {
+    let user_id = ctx.author().id;
+    let player_state_manager = lock_state(ctx).await;
+    let Some(player) = player_state_manager.players.get(&user_id) else {
+        ctx.say("You haven't started a combat yet! Use /start_new_combat to begin.").await?;
+        return Ok(());
+    };
+    let hand = player.hand.clone();
+    let affinity = player_state_manager.affinity(user_id);
+    // The prompt shows the hand, so players who keep it out of the channel get a private one
+    let private = player_state_manager.preferences(user_id).hand_delivery == HandDelivery::DirectMessage;
+    drop(player_state_manager);
+    if hand.is_empty() {
+        ctx.say("Your hand is empty, there's nothing to mulligan.").await?;
+        return Ok(());
+    }
+    if hand.len() > MAX_MENU_OPTIONS {
+        ctx.send(|b| b.content(format!("Your {} cards don't fit in a menu. Give /mulligan the positions instead, e.g. 1 3 5 or 1-3.", hand.len())).ephemeral(true)).await?;
+        return Ok(());
+    }
+    
+    let cards_id = format!("{}_cards", ctx.id());
+    let confirm_id = format!("{}_confirm", ctx.id());
+    let cancel_id = format!("{}_cancel", ctx.id());
+    let reply = ctx.send(|b| b
+        .content(format!("Pick the cards to mulligan, then confirm.\n{}", format_hand_display(&hand, affinity.as_ref())))
+        .ephemeral(private)
+        .components(|c| c
+            .create_action_row(|r| r.create_select_menu(|m| m
+                .custom_id(&cards_id)
+                .placeholder("Cards to toss")
+                .min_values(1)
+                .max_values(hand.len() as u64)
+                .options(|o| {
+                    for (i, card) in hand.iter().enumerate() {
+                        o.create_option(|opt| opt
+                            .label(truncate_label(&format!("{}. {}", i + 1, card.label())))
+                            .value(i.to_string()));
+                    }
+                    o
+                })))
+            .create_action_row(|r| r
+                .create_button(|b| b.custom_id(&confirm_id).label("Mulligan").style(serenity::ButtonStyle::Primary))
+                .create_button(|b| b.custom_id(&cancel_id).label("Cancel").style(serenity::ButtonStyle::Secondary))))
+    ).await?;
+    let message_id = reply.message().await?.id;
+    // Housekeeping can't edit ephemeral messages, they fade on their own
+    if !private {
+        lock_state(ctx).await.register_interaction(
+            ctx.channel_id(),
+            message_id,
+            MULLIGAN_PROMPT_TIMEOUT.as_secs() + INTERACTION_CLEANUP_GRACE.as_secs(),
+            MULLIGAN_EXPIRED,
+        );
+    }
+    
+    let hand_labels: Vec<String> = hand.iter().map(|card| card.label()).collect();
+    let mut chosen: Vec<usize> = Vec::new();
+    while let Some(mci) = serenity::CollectComponentInteraction::new(ctx.serenity_context())
+        .author_id(user_id)
+        .message_id(message_id)
+        .timeout(MULLIGAN_PROMPT_TIMEOUT)
+        .await
+    {
+        let custom_id = mci.data.custom_id.as_str();
+        
+        if custom_id == cards_id {
+            chosen = mci.data.values.iter().filter_map(|v| v.parse().ok()).collect();
+            mci.create_interaction_response(ctx.serenity_context(), |r| r
+                .kind(serenity::InteractionResponseType::DeferredUpdateMessage)
+            ).await?;
+            continue;
+        }
+        
+        let content = if custom_id == confirm_id {
+            // Discard and redraw under one lock, against the hand the player picked from
+            let mut player_state_manager = lock_state(ctx).await;
+            let config = player_state_manager.guild_config(ctx.guild_id());
+            let result = match player_state_manager.get_player_state(user_id) {
+                None => Err("Your combat has ended.".to_string()),
+                Some(_) if chosen.is_empty() => Err("Pick at least one card first.".to_string()),
+                Some(player) if player.hand.iter().map(|card| card.label()).collect::<Vec<_>>() != hand_labels => {
+                    Err("Your hand has changed since this menu opened. Run /mulligan again.".to_string())
+                },
+                Some(player) => player.mulligan(chosen.clone(), &config).map(|()| player.hand.clone()),
+            };
+            drop(player_state_manager);
+            
+            match result {
+                Ok(hand) => format!("Mulligan complete!\n{}", format_hand_display(&hand, affinity.as_ref())),
+                Err(e) => {
+                    mci.create_interaction_response(ctx.serenity_context(), |r| r
+                        .kind(serenity::InteractionResponseType::ChannelMessageWithSource)
+                        .interaction_response_data(|d| d.content(e).ephemeral(true))
+                    ).await?;
+                    continue;
+                }
+            }
+        } else {
+            "Mulligan cancelled.".to_string()
+        };
+        
+        mci.create_interaction_response(ctx.serenity_context(), |r| r
+            .kind(serenity::InteractionResponseType::UpdateMessage)
+            .interaction_response_data(|d| d.content(content).components(|c| c))
+        ).await?;
+        lock_state(ctx).await.finish_interaction(message_id);
+        return Ok(());
+    }
+    
+    reply.edit(ctx, |b| b
+        .content(MULLIGAN_EXPIRED)
+        .components(|c| c)
+    ).await?;
+    lock_state(ctx).await.finish_interaction(message_id);
     Ok(())
 }
 
+// Double or nothing: the whole hand goes, the same number of cards come back
 #[poise::command(slash_command)]
-pub async fn view_hand(
+pub async fn redraw_hand(
     ctx: poise::Context<'_, Data, Error>,
 ) -> Result<(), Error> {
     let user_id = ctx.author().id;
-    let player_state_manager = ctx.data().player_state_manager.lock().await;
+    let mut player_state_manager = lock_state(ctx).await;
+    let config = player_state_manager.guild_config(ctx.guild_id());
     
-    let Some(player) = player_state_manager.players.get(&user_id) else {
+    let Some(player) = player_state_manager.get_player_state(user_id) else {
         ctx.say("You haven't started a combat yet! Use /start_new_combat to begin.").await?;
         return Ok(());
     };
+    let count = match player.redraw_hand(&config) {
+        Ok(count) => count,
+        Err(e) => {
+            ctx.say(e).await?;
+            return Ok(());
+        }
+    };
+    
+    let hand = player.hand.clone();
+    let affinity = player_state_manager.affinity(user_id);
+    let delivery = player_state_manager.preferences(user_id).hand_delivery;
+    drop(player_state_manager);
+    
+    let message = format!("Threw away all {} cards and drew fresh ones!\n{}", count, format_hand_display(&hand, affinity.as_ref()));
+    let public = format!("Threw away all {} cards and drew fresh ones, your hand is in your DMs.", count);
+    deliver_hand(ctx, delivery, &public, message).await
+}
 
+#[poise::command(slash_command)]
+pub async fn draw_extra(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "How many extra cards your skill lets you draw"] count: usize,
+) -> Result<(), Error> {
+    let user_id = ctx.author().id;
+    let mut player_state_manager = lock_state(ctx).await;
+    let config = player_state_manager.guild_config(ctx.guild_id());
+    
+    // Extra cards come from skills, so tables opt in rather than everyone drawing for free
+    if !config.feature_enabled("draw_extra") {
+        ctx.say("Extra draws aren't enabled on this server. A GM can turn them on with /configure_feature.").await?;
+        return Ok(());
+    }
+    let Some(player) = player_state_manager.get_player_state(user_id) else {
+        ctx.say("You haven't started a combat yet! Use /start_new_combat to begin.").await?;
+        return Ok(());
+    };
+    if count == 0 {
+        ctx.say("Draw at least one card.").await?;
+        return Ok(());
+    }
+    
+    if let Err(e) = player.draw_extra(count, config.max_hand_size()) {
+        ctx.say(e).await?;
+        return Ok(());
+    }
     let hand = player.hand.clone();
+    let extra = player.extra_cards();
+    let affinity = player_state_manager.affinity(user_id);
+    let delivery = player_state_manager.preferences(user_id).hand_delivery;
     drop(player_state_manager);
     
-    ctx.say(format_hand_display(&hand)).await?;
-    Ok(())
+    let drew = format!("Drew {} extra card(s), {} above the normal hand size.", count, extra);
+    let public = format!("{} Your hand is in your DMs.", drew);
+    deliver_hand(ctx, delivery, &public, format!("{}\n{}", drew, format_hand_display(&hand, affinity.as_ref()))).await
+}
+
+// Every card gets its own row of buttons, and Discord allows five rows including Done/Cancel
+const MAX_PEEK_CARDS: usize = 4;
+const PEEK_TIMEOUT: Duration = Duration::from_secs(120);
+const PEEK_INSTRUCTIONS: &str = "The top of your deck, next draw first. Move cards up or to the bottom, then press Done.";
+
+// A row per peeked card, the ones staying on top first, then Done/Cancel
+fn peek_components<'a>(c: &'a mut serenity::CreateComponents, prefix: &str, top: &[CardType], bottom: &[CardType]) -> &'a mut serenity::CreateComponents {
+    for (i, card) in top.iter().enumerate() {
+        let id = card.id().0;
+        c.create_action_row(|r| r
+            .create_button(|b| b.custom_id(format!("{}_card_{}", prefix, id)).label(format!("{}. {}", i + 1, card.label())).style(serenity::ButtonStyle::Secondary).disabled(true))
+            .create_button(|b| b.custom_id(format!("{}_up_{}", prefix, id)).label("Up").style(serenity::ButtonStyle::Secondary).disabled(i == 0))
+            .create_button(|b| b.custom_id(format!("{}_bottom_{}", prefix, id)).label("To bottom").style(serenity::ButtonStyle::Secondary)));
+    }
+    for card in bottom {
+        let id = card.id().0;
+        c.create_action_row(|r| r
+            .create_button(|b| b.custom_id(format!("{}_card_{}", prefix, id)).label(format!("Bottom: {}", card.label())).style(serenity::ButtonStyle::Secondary).disabled(true))
+            .create_button(|b| b.custom_id(format!("{}_top_{}", prefix, id)).label("Back on top").style(serenity::ButtonStyle::Secondary)));
+    }
+    c.create_action_row(|r| r
+        .create_button(|b| b.custom_id(format!("{}_done", prefix)).label("Done").style(serenity::ButtonStyle::Primary))
+        .create_button(|b| b.custom_id(format!("{}_cancel", prefix)).label("Cancel").style(serenity::ButtonStyle::Secondary)))
+}
+
+// Apply one of the peek buttons to the arrangement being built
+fn move_peeked(kind: &str, id: Option<CardId>, top: &mut Vec<CardType>, bottom: &mut Vec<CardType>) {
+    let Some(id) = id else {
+        return;
+    };
+    let in_top = top.iter().position(|card| card.id() == id);
+    let in_bottom = bottom.iter().position(|card| card.id() == id);
+    match (kind, in_top, in_bottom) {
+        ("up", Some(i), _) if i > 0 => top.swap(i - 1, i),
+        ("bottom", Some(i), _) => bottom.push(top.remove(i)),
+        ("top", _, Some(i)) => top.push(bottom.remove(i)),
+        _ => {},
+    }
 }
 
 #[poise::command(slash_command)]
-pub async fn view_possible_resolutions(
+pub async fn peek_deck(
     ctx: poise::Context<'_, Data, Error>,
+    #[description = "How many cards from the top to look at (1-4)"] count: usize,
 ) -> Result<(), Error> {
     let user_id = ctx.author().id;
-    let player_state_manager = ctx.data().player_state_manager.lock().await;
+    let player_state_manager = lock_state(ctx).await;
+    let config = player_state_manager.guild_config(ctx.guild_id());
     
+    // Only some skills let a character scry, so tables opt in
+    if !config.feature_enabled("peek_deck") {
+        ctx.say("Peeking at the deck isn't enabled on this server. A GM can turn it on with /configure_feature.").await?;
+        return Ok(());
+    }
     let Some(player) = player_state_manager.players.get(&user_id) else {
         ctx.say("You haven't started a combat yet! Use /start_new_combat to begin.").await?;
         return Ok(());
     };
-
-    let possible_hands = player.find_possible_hands();
-    if possible_hands.is_empty() {
-        ctx.say("No valid hands available.").await?;
+    if count == 0 || count > MAX_PEEK_CARDS {
+        ctx.say(format!("You can look at 1 to {} cards.", MAX_PEEK_CARDS)).await?;
         return Ok(());
     }
-
-    let mut response = String::from("Available hands:\n");
-    for (i, hand) in possible_hands.iter().enumerate() {
-        // Get the card indices (positions) for this hand
-        let card_positions = match hand {
-            HandType::TripleThreat { card_indices, .. } => card_indices,
-            HandType::MatchedEdge { card_indices, .. } => card_indices,
-            HandType::Jackpot { card_indices, .. } => card_indices,
-            HandType::DoubleTrouble { card_indices, .. } => card_indices,
+    let mut top = player.peek(count);
+    drop(player_state_manager);
+    if top.is_empty() {
+        ctx.say("Your deck is empty, the discard only goes back in when you draw.").await?;
+        return Ok(());
+    }
+    
+    let prefix = ctx.id().to_string();
+    let peeked: Vec<CardId> = top.iter().map(|card| card.id()).collect();
+    let mut bottom: Vec<CardType> = Vec::new();
+    // The top of the deck is as private as the hand
+    let reply = ctx.send(|b| b
+        .content(PEEK_INSTRUCTIONS)
+        .ephemeral(true)
+        .components(|c| peek_components(c, &prefix, &top, &bottom))
+    ).await?;
+    let message_id = reply.message().await?.id;
+    
+    while let Some(mci) = serenity::CollectComponentInteraction::new(ctx.serenity_context())
+        .author_id(user_id)
+        .message_id(message_id)
+        .timeout(PEEK_TIMEOUT)
+        .await
+    {
+        let Some(action) = mci.data.custom_id.strip_prefix(&prefix).and_then(|a| a.strip_prefix('_')) else {
+            continue;
         };
         
-        // Get MP cost based on hand type
-        let mp_cost = match hand {
-            HandType::TripleThreat { .. } => 10,
-            HandType::MatchedEdge { .. } => 5,
-            HandType::Jackpot { .. } => 20,
-            HandType::DoubleTrouble { .. } => 20,
+        let finished = match action {
+            "done" => {
+                let top_ids: Vec<CardId> = top.iter().map(|card| card.id()).collect();
+                let bottom_ids: Vec<CardId> = bottom.iter().map(|card| card.id()).collect();
+                let mut player_state_manager = lock_state(ctx).await;
+                let result = match player_state_manager.get_player_state(user_id) {
+                    // Nothing moved, nothing to record
+                    Some(_) if top_ids == peeked => Ok(()),
+                    Some(player) => player.arrange_peeked(&top_ids, &bottom_ids),
+                    None => Err("Your combat has ended.".to_string()),
+                };
+                drop(player_state_manager);
+                Some(match result {
+                    Ok(()) => "Put the cards back.".to_string(),
+                    Err(e) => format!("{} Nothing was moved.", e),
+                })
+            },
+            "cancel" => Some("Left the deck as it was.".to_string()),
+            _ => {
+                if let Some((kind, id)) = action.split_once('_') {
+                    let id = id.parse().map(CardId).ok();
+                    move_peeked(kind, id, &mut top, &mut bottom);
+                }
+                None
+            }
         };
         
-        // Convert to 1-based indexing for display and sort for readability
-        let mut display_positions: Vec<usize> = card_positions.iter().map(|&idx| idx + 1).collect();
-        display_positions.sort();
-        
-        // Format the positions as a string like "Cards: 1, 3, 5"
-        let positions_str = format!("Cards: {}", display_positions.iter()
-            .map(|pos| pos.to_string())
-            .collect::<Vec<_>>()
-            .join(", "));
-        
-        response.push_str(&format!("{}. {} (MP Cost: {}, {})\n", i + 1, hand.to_string(), mp_cost, positions_str));
+        match finished {
+            Some(content) => {
+                mci.create_interaction_response(ctx.serenity_context(), |r| r
+                    .kind(serenity::InteractionResponseType::UpdateMessage)
+                    .interaction_response_data(|d| d.content(content).components(|c| c))
+                ).await?;
+                return Ok(());
+            },
+            None => {
+                mci.create_interaction_response(ctx.serenity_context(), |r| r
+                    .kind(serenity::InteractionResponseType::UpdateMessage)
+                    .interaction_response_data(|d| d.components(|c| peek_components(c, &prefix, &top, &bottom)))
+                ).await?;
+            }
+        }
     }
     
-    ctx.say(response).await?;
+    reply.edit(ctx, |b| b
+        .content("Stopped looking, the deck order is unchanged.")
+        .components(|c| c)
+    ).await?;
     Ok(())
 }
 
-
 #[poise::command(slash_command)]
-pub async fn resolve_hand(
+pub async fn discard(
     ctx: poise::Context<'_, Data, Error>,
-    #[description = "Hand number from the list"] hand_number: usize,
+    #[description = "Cards to discard without drawing, e.g. 1 3 5, 1-3 or fire 5, joker"] positions: String,
 ) -> Result<(), Error> {
     let user_id = ctx.author().id;
-    let mut player_state_manager = ctx.data().player_state_manager.lock().await;
+    let mut player_state_manager = lock_state(ctx).await;
     
     let Some(player) = player_state_manager.get_player_state(user_id) else {
         ctx.say("You haven't started a combat yet! Use /start_new_combat to begin.").await?;
         return Ok(());
     };
+    
+    let mut indices = match parse::cards(&positions, &player.hand) {
+        Ok(indices) => indices,
+        Err(e) => {
+            ctx.say(format!("Please give card positions (1-{}) or descriptions like fire 5, {}.", player.hand.len(), e)).await?;
+            return Ok(());
+        }
+    };
+    indices.sort_unstable_by(|a, b| b.cmp(a)); // Sort in reverse to remove from highest index first
+    
+    // Abilities that toss cards don't refill the hand, so this never draws
+    let mut discarded = Vec::new();
+    for &index in &indices {
+        discarded.push(player.hand[index].label());
+        player.discard_from_hand(index)?;
+    }
+    discarded.reverse();
+    let hand = player.hand.clone();
+    let affinity = player_state_manager.affinity(user_id);
+    let delivery = player_state_manager.preferences(user_id).hand_delivery;
+    drop(player_state_manager);
+    
+    let summary = format!("Discarded {}. Your hand now holds {} card(s).", discarded.join(", "), hand.len());
+    let public = format!("{} It's in your DMs.", summary);
+    deliver_hand(ctx, delivery, &public, format!("{}\n{}", summary, format_hand_display(&hand, affinity.as_ref()))).await
+}
 
-    let possible_hands = player.find_possible_hands();
-    if hand_number == 0 || hand_number > possible_hands.len() {
-        ctx.say("Invalid hand number.").await?;
+#[poise::command(slash_command)]
+pub async fn card_guard(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "Card position in your hand to discard"] position: usize,
+) -> Result<(), Error> {
+    let user_id = ctx.author().id;
+    let mut player_state_manager = lock_state(ctx).await;
+    let config = player_state_manager.guild_config(ctx.guild_id());
+    let name = player_state_manager.display_name(user_id, &ctx.author().name);
+    
+    let Some(player) = player_state_manager.get_player_state(user_id) else {
+        ctx.say("You haven't started a combat yet! Use /start_new_combat to begin.").await?;
+        return Ok(());
+    };
+    if position == 0 || position > player.hand.len() {
+        ctx.say(format!("Please give a card position from 1 to {}.", player.hand.len())).await?;
         return Ok(());
     }
+    
+    let (card, reduction) = match player.guard_with(position - 1, &config.action_budget) {
+        Ok(guarded) => guarded,
+        Err(e) => {
+            ctx.say(e).await?;
+            return Ok(());
+        }
+    };
+    let hand = player.hand.clone();
+    let affinity = player_state_manager.affinity(user_id);
+    let delivery = player_state_manager.preferences(user_id).hand_delivery;
+    drop(player_state_manager);
+    
+    let guard = format!("🛡️ {} discards {} to guard, reducing the incoming damage by **{}**.", name, card.label(), reduction);
+    let public = format!("{}\n{}", guard, NEW_HAND_IN_DMS);
+    deliver_hand(ctx, delivery, &public, format!("{}\n{}", guard, format_hand_display(&hand, affinity.as_ref()))).await
+}
 
-    let hand = &possible_hands[hand_number - 1];
+#[poise::command(slash_command)]
+pub async fn card_boost(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "Card position in your hand to discard"] position: usize,
+    #[description = "Roll the check too: two attribute dice and a modifier, e.g. d8+d10+1"] roll: Option<String>,
+) -> Result<(), Error> {
+    let dice = match roll.as_deref().map(parse::check_dice).transpose() {
+        Ok(dice) => dice,
+        Err(e) => {
+            ctx.say(format!("Couldn't read the roll, {}.", e)).await?;
+            return Ok(());
+        }
+    };
+    let user_id = ctx.author().id;
+    let mut player_state_manager = lock_state(ctx).await;
+    let name = player_state_manager.display_name(user_id, &ctx.author().name);
     
-    // Get the available elements for this hand
-    let available_elements = match hand {
-        HandType::TripleThreat { suits, .. } => suits,
-        HandType::MatchedEdge { suits, .. } => suits,
-        HandType::Jackpot { suits, .. } => suits,
-        HandType::DoubleTrouble { suits, .. } => suits,
+    let Some(player) = player_state_manager.get_player_state(user_id) else {
+        ctx.say("You haven't started a combat yet! Use /start_new_combat to begin.").await?;
+        return Ok(());
     };
+    if position == 0 || position > player.hand.len() {
+        ctx.say(format!("Please give a card position from 1 to {}.", player.hand.len())).await?;
+        return Ok(());
+    }
     
-    // Format elements as a string with square brackets
-    let elements_str = format_element_list(available_elements);
+    let (card, bonus) = player.boost_with(position - 1)?;
+    let hand = player.hand.clone();
+    let affinity = player_state_manager.affinity(user_id);
+    let delivery = player_state_manager.preferences(user_id).hand_delivery;
+    drop(player_state_manager);
     
-    // Discard the used cards
-    match hand {
-        HandType::TripleThreat { card_indices, .. } |
-        HandType::MatchedEdge { card_indices, .. } |
-        HandType::Jackpot { card_indices, .. } |
-        HandType::DoubleTrouble { card_indices, .. } => {
-            for &index in card_indices.iter().rev() {
-                player.discard_from_hand(index)?;
-            }
-            // Draw back up to 5
-            let cards_needed = 5 - player.hand.len();
-            if cards_needed > 0 {
-                player.draw_to_hand(cards_needed)?;
-            }
-        }
+    let mut message = format!("✨ {} discards {} for **+{}** on the check.", name, card.label(), bonus);
+    if let Some(dice) = dice {
+        // The card's value rides along with the check's own modifier
+        let boosted = CheckDice { modifier: dice.modifier.saturating_add(bonus as i64), ..dice };
+        message.push_str(&format!("\n{}", boosted.roll(&mut rand::thread_rng()).describe()));
     }
+    let public = format!("{}\n{}", message, NEW_HAND_IN_DMS);
+    deliver_hand(ctx, delivery, &public, format!("{}\n{}", message, format_hand_display(&hand, affinity.as_ref()))).await
+}
+
+#[poise::command(slash_command)]
+pub async fn view_hand(
+    ctx: poise::Context<'_, Data, Error>,
+) -> Result<(), Error> {
+    let user_id = ctx.author().id;
+    let player_state_manager = lock_state(ctx).await;
+    let theme = player_state_manager.guild_config(ctx.guild_id()).theme;
+    
+    let Some(player) = player_state_manager.players.get(&user_id) else {
+        ctx.say("You haven't started a combat yet! Use /start_new_combat to begin.").await?;
+        return Ok(());
+    };
 
-    let hand_clone = player.hand.clone();
+    let hand = player.hand.clone();
+    let names = format_arcana_names(&hand, &player.deck_template);
+    let affinity = player_state_manager.affinity(user_id);
+    let delivery = player_state_manager.preferences(user_id).hand_delivery;
     drop(player_state_manager);
     
-    // Format the effect message with bracketed elements
-    let effect_message = match hand {
-        HandType::TripleThreat { value, .. } => {
-            format!("Triple Threat resolved! Three targets of your choice recover or suffer from **dazed, shaken, slow or weak**. If the target recovers then it also heals Hit Points equal to {}. If the target suffers it also takes {} {} damage.", 
-                value + 15, 
-                value + 5, 
-                elements_str)
-        },
-        HandType::MatchedEdge { value, .. } => {
-            format!("Matched Edge resolved! Your weapon strike deals {} bonus {} damage!", 
-                value, 
-                elements_str)
-        },
-        HandType::Jackpot { value, .. } => {
-            format!("Jackpot resolved! You and every ally present on the scene recover 777 Hit Points, 777 Mind Points, and recover from all status effects; any PCs who have surrendered but are still part of the scene immediately regain consciousness (this does not cancel the effects of their Surrender).")
-        },
-        HandType::DoubleTrouble { first_pair_value, second_pair_value, .. } => {
-            format!("Double Trouble resolved! You deal damage equal to {} (15 + {} + {}) to each of up to two different enemies you can see that are present on the scene; the type of this damage is one of your choice among those matching the suits of the resolved cards: {}",
-                15 + first_pair_value + second_pair_value,
-                first_pair_value,
-                second_pair_value,
-                elements_str)
-        }
+    if delivery == HandDelivery::DirectMessage {
+        return deliver_hand(ctx, delivery, "Your hand is in your DMs.", format!("{}{}", format_hand_display(&hand, affinity.as_ref()), names)).await;
+    }
+    
+    // Embeds are cosmetic, fall back to plain text while shedding load
+    let degraded = load::is_degraded();
+    let started = Instant::now();
+    let display = if degraded {
+        format!("{}{}", format_hand_display(&hand, affinity.as_ref()), names)
+    } else {
+        format!("{}{}", theme.format_hand(&hand, affinity.as_ref()), names)
     };
+    record_stage(ctx, Stage::Formatting, started).await;
     
-    let message = format!("{}\n{}", 
-        effect_message,
-        format_hand_display(&hand_clone));
-    ctx.say(message).await?;
+    let started = Instant::now();
+    if degraded {
+        post_hand(ctx, display).await?;
+    } else {
+        let reply = ctx.send(|b| b.embed(|e| e
+            .title("Your hand")
+            .description(display)
+            .color(theme.embed_color())
+        )).await?;
+        let message_id = reply.message().await?.id;
+        lock_state(ctx).await.register_interaction(ctx.channel_id(), message_id, HAND_PANEL_TTL.as_secs(), HAND_PANEL_EXPIRED);
+    }
+    record_stage(ctx, Stage::Send, started).await;
     Ok(())
 }
 
-// Define the commands list as a static
-static COMMANDS: &[fn() -> poise::Command<Data, Error>] = &[
-    start_new_combat,
-    mulligan,
-    view_hand,
-    view_possible_resolutions,
-    resolve_hand,
-];
+#[poise::command(slash_command)]
+pub async fn hand_delivery(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "Show your hand in the channel, or send it to you by DM"] mode: HandDelivery,
+) -> Result<(), Error> {
+    lock_state(ctx).await.set_hand_delivery(ctx.author().id, mode);
+    
+    let message = match mode {
+        HandDelivery::Channel => "Your hand will be shown in the channel again.",
+        HandDelivery::DirectMessage => "Your hand will be sent to you by DM whenever a command shows it. The channel only sees what happened, not the cards you hold.",
+    };
+    ctx.send(|b| b.content(message).ephemeral(true)).await?;
+    Ok(())
+}
 
-// Update your main() function to include the GameState
-#[tokio::main]
-async fn main() -> Result<(), Error> {
-    dotenv().ok();
+#[poise::command(slash_command)]
+pub async fn sort_hand(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "Sort by value or by suit"] by: HandSort,
+    #[description = "Keep your hand sorted this way after every draw, in later combats too"] keep_sorted: Option<bool>,
+) -> Result<(), Error> {
+    let user_id = ctx.author().id;
+    let mut player_state_manager = lock_state(ctx).await;
+    match keep_sorted {
+        Some(true) => player_state_manager.set_hand_sort(user_id, Some(by)),
+        Some(false) => player_state_manager.set_hand_sort(user_id, None),
+        None => {},
+    }
+    
+    let Some(player) = player_state_manager.get_player_state(user_id) else {
+        drop(player_state_manager);
+        let message = match keep_sorted {
+            Some(true) => format!("Your hand will be sorted by {} from your next combat on.", by.label()),
+            Some(false) => "Your hand will stay in the order you draw it.".to_string(),
+            None => "You haven't started a combat yet! Use /start_new_combat to begin.".to_string(),
+        };
+        ctx.send(|b| b.content(message).ephemeral(true)).await?;
+        return Ok(());
+    };
+    player.sort_hand(by);
+    let hand = player.hand.clone();
+    let affinity = player_state_manager.affinity(user_id);
+    let delivery = player_state_manager.preferences(user_id).hand_delivery;
+    drop(player_state_manager);
+    
+    let mut message = format!("Sorted your hand by {}.", by.label());
+    match keep_sorted {
+        Some(true) => message.push_str(" It will stay sorted after every draw."),
+        Some(false) => message.push_str(" New cards will go at the end again."),
+        None => {},
+    }
+    let public = format!("{} Your hand is in your DMs.", message);
+    deliver_hand(ctx, delivery, &public, format!("{}\n{}", message, format_hand_display(&hand, affinity.as_ref()))).await
+}
+
+#[poise::command(slash_command)]
+pub async fn view_discard(
+    ctx: poise::Context<'_, Data, Error>,
+) -> Result<(), Error> {
+    let user_id = ctx.author().id;
+    let player_state_manager = lock_state(ctx).await;
+    
+    let Some(player) = player_state_manager.players.get(&user_id) else {
+        ctx.say("You haven't started a combat yet! Use /start_new_combat to begin.").await?;
+        return Ok(());
+    };
+    
+    let discard = player.discard.clone();
+    let burned = player.burned.clone();
+    let deck_len = player.deck.cards.len();
+    let elements = player.deck_template.elements.clone();
+    drop(player_state_manager);
+    
+    let mut message = format!("**Discard pile** ({} cards, {} left in the deck)\n{}",
+        discard.len(),
+        deck_len,
+        format_discard_display(&discard, &elements));
+    // Burned cards never come back, so they're listed apart from the discard that does
+    if !burned.is_empty() {
+        message.push_str(&format!("\n**Burned for good**: {}", format_card_labels(&burned)));
+    }
+    ctx.send(|b| b.content(message).ephemeral(true)).await?;
+    Ok(())
+}
+
+#[poise::command(slash_command)]
+pub async fn deck_status(
+    ctx: poise::Context<'_, Data, Error>,
+) -> Result<(), Error> {
+    let user_id = ctx.author().id;
+    let player_state_manager = lock_state(ctx).await;
+    
+    let Some(player) = player_state_manager.players.get(&user_id) else {
+        ctx.say("You haven't started a combat yet! Use /start_new_combat to begin.").await?;
+        return Ok(());
+    };
+    
+    let deck = player.deck.clone();
+    let template = player.deck_template.clone();
+    let preset = player.deck_preset;
+    let discard_len = player.discard.len();
+    // Counted from the combat's own cards, which keep the deck it started with if the guild's changed since
+    let total_jokers = player.all_cards().filter(|card| matches!(card, CardType::Joker { .. })).count();
+    drop(player_state_manager);
+    
+    let mut message = format!("**Deck status**\n{} cards left to draw, {} in the discard\n{}",
+        deck.cards.len(),
+        discard_len,
+        format_deck_table(&deck, &template, total_jokers));
+    if let Some(preset) = preset {
+        message.push_str(&format!("\n{}", preset.describe()));
+    }
+    ctx.send(|b| b.content(message).ephemeral(true)).await?;
+    Ok(())
+}
+
+#[poise::command(slash_command)]
+pub async fn hand_value(
+    ctx: poise::Context<'_, Data, Error>,
+) -> Result<(), Error> {
+    let user_id = ctx.author().id;
+    let player_state_manager = lock_state(ctx).await;
+    let config = player_state_manager.guild_config(ctx.guild_id());
+    
+    let Some(player) = player_state_manager.players.get(&user_id) else {
+        ctx.say("You haven't started a combat yet! Use /start_new_combat to begin.").await?;
+        return Ok(());
+    };
+    let hand = player.hand.clone();
+    let elements = player.deck_template.elements.clone();
+    let near = player.hands_one_card_away(&config);
+    drop(player_state_manager);
+    
+    let mut message = format!("**Hand value** ({} cards)\n{}", hand.len(), format_hand_counts(&hand, &elements));
+    message.push_str("\n**One card away**\n");
+    if near.is_empty() {
+        message.push_str("No new hands are one card away.\n");
+    }
+    for n in &near {
+        let needs = n.needs.iter().map(|card| card.label()).collect::<Vec<_>>().join(", ");
+        message.push_str(&format!("• {}: {} ({} left in deck)\n", n.hand.shape(), needs, n.left_in_deck));
+    }
+    say_chunked(ctx, message, true).await?;
+    Ok(())
+}
+
+// How many cards of each value and each suit the hand holds, with wild jokers on their own
+fn format_hand_counts(hand: &[CardType], elements: &[ElementType]) -> String {
+    let mut values = BTreeMap::new();
+    let mut wild = 0;
+    for card in hand {
+        match card.face() {
+            Some((Some(value), _)) => *values.entry(value).or_insert(0) += 1,
+            Some(_) => {},
+            None => wild += 1,
+        }
+    }
+    let values = values.iter()
+        .map(|(value, count)| format!("{}×{}", value, count))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let suits = elements.iter()
+        .map(|element| {
+            let count = hand.iter().filter(|card| card.face().is_some_and(|(_, e)| e == element)).count();
+            format!("{} {}", element.name(), count)
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    
+    let mut text = format!("Values: {}\nSuits: {}\n", if values.is_empty() { "none".to_string() } else { values }, suits);
+    if wild > 0 {
+        text.push_str(&format!("Wild jokers: {}\n", wild));
+    }
+    text
+}
+
+// Best plays listed by /advise
+const ADVICE_HANDS_SHOWN: usize = 5;
+
+#[poise::command(slash_command)]
+pub async fn advise(
+    ctx: poise::Context<'_, Data, Error>,
+) -> Result<(), Error> {
+    let user_id = ctx.author().id;
+    let player_state_manager = lock_state(ctx).await;
+    let config = player_state_manager.guild_config(ctx.guild_id());
+    
+    let Some(player) = player_state_manager.players.get(&user_id) else {
+        ctx.say("You haven't started a combat yet! Use /start_new_combat to begin.").await?;
+        return Ok(());
+    };
+    let ranked = analysis::rank_hands(player, &config);
+    let advice = analysis::advise(player, &config);
+    drop(player_state_manager);
+    
+    let mut message = String::new();
+    if !ranked.is_empty() {
+        message.push_str("**Best plays**\n");
+        for (i, evaluation) in ranked.iter().take(ADVICE_HANDS_SHOWN).enumerate() {
+            message.push_str(&format!("{}. {}: {}\n", i + 1, evaluation.hand.shape(), evaluation.summary()));
+        }
+    }
+    match advice {
+        analysis::Advice::Resolve(best) => {
+            message.push_str(&format!("Resolve the {} with /resolve_hand.", best.hand.shape()));
+        },
+        analysis::Advice::Mulligan { positions, aiming_for } => {
+            let positions = positions.iter().map(|p| (p + 1).to_string()).collect::<Vec<_>>().join(", ");
+            message.push_str(&format!("Nothing resolves yet. Mulligan cards {} and keep the rest for a {}.", positions, aiming_for.join(" or ")));
+        },
+        analysis::Advice::Nothing => {
+            message.push_str("Nothing resolves yet and there's no clear card to let go, draw when you can.");
+        },
+    }
+    ctx.send(|b| b.content(message).ephemeral(true)).await?;
+    Ok(())
+}
+
+#[poise::command(slash_command)]
+pub async fn odds(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "Cards you'd mulligan first, e.g. 1 3 or fire 5"] mulligan: Option<String>,
+    #[description = "Cards drawn (defaults to one per mulliganed card, or 1)"] draws: Option<usize>,
+) -> Result<(), Error> {
+    let user_id = ctx.author().id;
+    let player_state_manager = lock_state(ctx).await;
+    let config = player_state_manager.guild_config(ctx.guild_id());
+    
+    let Some(player) = player_state_manager.players.get(&user_id) else {
+        ctx.send(|b| b.content("You haven't started a combat yet! Use /start_new_combat to begin.").ephemeral(true)).await?;
+        return Ok(());
+    };
+    let indices = match mulligan.as_deref().map(|cards| parse::cards(cards, &player.hand)) {
+        None => Vec::new(),
+        Some(Ok(indices)) => indices,
+        Some(Err(e)) => {
+            ctx.send(|b| b.content(format!("Please give card positions (1-{}) or descriptions like fire 5, {}.", player.hand.len(), e)).ephemeral(true)).await?;
+            return Ok(());
+        }
+    };
+    let state = player.clone();
+    drop(player_state_manager);
+    
+    let draws = draws.unwrap_or(indices.len().max(1));
+    let mulliganed = indices.len();
+    // Tens of thousands of hand scans for big draws, off the async runtime
+    let odds = tokio::task::spawn_blocking(move || analysis::draw_odds(&state, &config, &indices, draws)).await?;
+    let odds = match odds {
+        Ok(odds) => odds,
+        Err(e) => {
+            ctx.send(|b| b.content(e).ephemeral(true)).await?;
+            return Ok(());
+        }
+    };
+    
+    let message = format_odds(&odds, mulliganed, draws, &format!("{} possible draws", odds.trials));
+    ctx.send(|b| b.content(message).ephemeral(true)).await?;
+    Ok(())
+}
+
+#[poise::command(slash_command)]
+pub async fn simulate(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "Random draws to run (default 10000)"]
+    #[min = 1]
+    #[max = 100000]
+    n: Option<u64>,
+    #[description = "Cards you'd mulligan first, e.g. 1 3 or fire 5"] mulligan: Option<String>,
+    #[description = "Cards drawn (defaults to one per mulliganed card, or 1)"] draws: Option<usize>,
+) -> Result<(), Error> {
+    let user_id = ctx.author().id;
+    let player_state_manager = lock_state(ctx).await;
+    let config = player_state_manager.guild_config(ctx.guild_id());
+    
+    let Some(player) = player_state_manager.players.get(&user_id) else {
+        ctx.send(|b| b.content("You haven't started a combat yet! Use /start_new_combat to begin.").ephemeral(true)).await?;
+        return Ok(());
+    };
+    let indices = match mulligan.as_deref().map(|cards| parse::cards(cards, &player.hand)) {
+        None => Vec::new(),
+        Some(Ok(indices)) => indices,
+        Some(Err(e)) => {
+            ctx.send(|b| b.content(format!("Please give card positions (1-{}) or descriptions like fire 5, {}.", player.hand.len(), e)).ephemeral(true)).await?;
+            return Ok(());
+        }
+    };
+    let state = player.clone();
+    drop(player_state_manager);
+    
+    let runs = n.unwrap_or(analysis::DEFAULT_SIMULATIONS).clamp(1, analysis::MAX_SIMULATIONS);
+    let draws = draws.unwrap_or(indices.len().max(1));
+    let mulliganed = indices.len();
+    let odds = tokio::task::spawn_blocking(move || analysis::simulate(&state, &config, &indices, draws, runs)).await?;
+    let odds = match odds {
+        Ok(odds) => odds,
+        Err(e) => {
+            ctx.send(|b| b.content(e).ephemeral(true)).await?;
+            return Ok(());
+        }
+    };
+    
+    let message = format_odds(&odds, mulliganed, draws, &format!("{} simulated draws", odds.trials));
+    ctx.send(|b| b.content(message).ephemeral(true)).await?;
+    Ok(())
+}
+
+fn format_odds(odds: &analysis::Odds, mulliganed: usize, draws: usize, basis: &str) -> String {
+    let mut message = if mulliganed > 0 {
+        format!("**Odds** after a mulligan of {} card(s) and drawing {} ({})\n", mulliganed, draws, basis)
+    } else {
+        format!("**Odds** after drawing {} card(s) ({})\n", draws, basis)
+    };
+    for (kind, chance) in &odds.by_kind {
+        message.push_str(&format!("• {}: {:.1}%\n", kind.label(), chance * 100.0));
+    }
+    message.push_str(&format!("Any hand: {:.1}%", odds.any * 100.0));
+    message
+}
+
+#[poise::command(slash_command)]
+pub async fn simulate_hand(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "A hand to try out, e.g. 5🔥 5❄️ 3🪨 joker 7💨"] cards: String,
+) -> Result<(), Error> {
+    let config = lock_state(ctx).await.guild_config(ctx.guild_id());
+    let hand = match parse::hand(&cards, config.max_hand_size(), &config.deck_template) {
+        Ok(hand) => hand,
+        Err(e) => {
+            ctx.send(|b| b.content(format!("I couldn't read that hand, {}.", e)).ephemeral(true)).await?;
+            return Ok(());
+        }
+    };
+    
+    // Nothing here touches a real combat
+    let labels = hand.iter().map(|card| card.label()).collect::<Vec<_>>().join(" ");
+    let ranked = analysis::rank_cards(hand, &config);
+    let mut message = format!("**Hypothetical hand**: {}\n", labels);
+    if ranked.is_empty() {
+        message.push_str("No hands can be made from these cards.");
+    }
+    for (i, evaluation) in ranked.iter().enumerate() {
+        message.push_str(&format!("{}. {}\n   {}\n", i + 1, evaluation.hand.to_string(), evaluation.summary()));
+    }
+    say_chunked(ctx, message, true).await?;
+    Ok(())
+}
+
+#[poise::command(slash_command)]
+pub async fn reshuffle(
+    ctx: poise::Context<'_, Data, Error>,
+) -> Result<(), Error> {
+    let user_id = ctx.author().id;
+    let mut player_state_manager = lock_state(ctx).await;
+    
+    let Some(player) = player_state_manager.get_player_state(user_id) else {
+        ctx.say("You haven't started a combat yet! Use /start_new_combat to begin.").await?;
+        return Ok(());
+    };
+    
+    if player.discard.is_empty() {
+        ctx.say("Your discard pile is empty, there's nothing to shuffle back in.").await?;
+        return Ok(());
+    }
+    let count = player.reshuffle_discard();
+    let deck_len = player.deck.cards.len();
+    drop(player_state_manager);
+    
+    ctx.say(format!("Shuffled {} card(s) from the discard back into the deck. {} cards left to draw.", count, deck_len)).await?;
+    Ok(())
+}
+
+// Undrawn cards as a suit-by-value grid in a code block so the columns line up
+fn format_deck_table(deck: &Deck, template: &DeckTemplate, total_jokers: usize) -> String {
+    if template.kind == DeckKind::MajorArcana {
+        return format_arcana_table(deck, template, total_jokers);
+    }
+    // Two-digit values need wider columns
+    let width = template.high.to_string().len();
+    let header = template.values().map(|value| format!("{:>width$}", value)).collect::<Vec<_>>().join(" ");
+    let mut table = format!("```\n       {} | Total\n", header);
+    for element in &template.elements {
+        let counts = deck.value_counts(element, template.values());
+        let cells = counts.iter().map(|c| format!("{:>width$}", c)).collect::<Vec<_>>().join(" ");
+        table.push_str(&format!("{:<6} {} | {}\n", element.name(), cells, deck.suit_count(element)));
+    }
+    table.push_str(&format!("Jokers {} of {} left\n```", deck.joker_count(), total_jokers));
+    table
+}
+
+// Each element's trumps still in the deck by numeral, a value grid would be nearly all zeros
+fn format_arcana_table(deck: &Deck, template: &DeckTemplate, total_jokers: usize) -> String {
+    let mut table = String::from("```\n");
+    for element in &template.elements {
+        let left = template.values()
+            .zip(deck.value_counts(element, template.values()))
+            .filter(|(_, count)| *count > 0)
+            .filter_map(|(value, _)| arcana::MAJOR_ARCANA.get(value as usize - 1).map(|(numeral, ..)| *numeral))
+            .collect::<Vec<_>>();
+        table.push_str(&format!("{:<6} {} | {}\n", element.name(), left.join(" "), deck.suit_count(element)));
+    }
+    table.push_str(&format!("{} {} of {} left\n```", arcana::FOOL, deck.joker_count(), total_jokers));
+    table
+}
+
+// The hand's cards as 1-based positions in the player's current hand, like "Cards: 1, 3, 5"
+fn format_hand_positions(hand: &HandType, cards: &[CardType]) -> String {
+    format!("Cards: {}", hand.positions_in(cards).iter()
+        .map(|pos| (pos + 1).to_string())
+        .collect::<Vec<_>>()
+        .join(", "))
+}
+
+#[poise::command(slash_command)]
+pub async fn view_possible_resolutions(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "Only hands that can deal this element's damage, e.g. Fire or Bolt"] element: Option<String>,
+) -> Result<(), Error> {
+    let element = match element.as_deref().map(parse::element).transpose() {
+        Ok(element) => element,
+        Err(e) => {
+            ctx.say(format!("Unknown element, {}.", e)).await?;
+            return Ok(());
+        }
+    };
+    let user_id = ctx.author().id;
+    let mut player_state_manager = lock_state(ctx).await;
+    let config = player_state_manager.guild_config(ctx.guild_id());
+    
+    let Some(player) = player_state_manager.get_player_state(user_id) else {
+        ctx.say("You haven't started a combat yet! Use /start_new_combat to begin.").await?;
+        return Ok(());
+    };
+
+    let started = Instant::now();
+    // Remembered so /resolve_hand's number means a hand from this list
+    let possible_hands = player.present_resolutions(&config, element.as_ref());
+    record_stage(ctx, Stage::Detection, started).await;
+    if possible_hands.is_empty() {
+        match &element {
+            Some(element) => ctx.say(format!("No hands can deal {} damage right now.", element.name())).await?,
+            None => ctx.say("No valid hands available.").await?,
+        };
+        return Ok(());
+    }
+
+    let started = Instant::now();
+    // Modifiers head every page, so a changed value doesn't read like a misdetection
+    let header: String = player.modifiers.iter().map(|modifier| format!("*{}*\n", modifier.describe())).collect();
+    let lines: Vec<String> = possible_hands.iter().enumerate()
+        .map(|(i, (hand, applied))| {
+            let modified = if applied.is_empty() {
+                String::new()
+            } else {
+                format!(" — modified by {}", applied.join(", "))
+            };
+            format!("{}. {} (MP Cost: {}, {}){}", i + 1, hand.to_string(), config.mp_cost(hand), format_hand_positions(hand, &player.hand), modified)
+        })
+        .collect();
+    drop(player_state_manager);
+    let pages: Vec<String> = lines.chunks(RESOLUTIONS_PER_PAGE).map(|chunk| format!("{}{}", header, chunk.join("\n"))).collect();
+    record_stage(ctx, Stage::Formatting, started).await;
+    
+    // Embeds and buttons are cosmetic, fall back to plain text while shedding load
+    let started = Instant::now();
+    if load::is_degraded() {
+        say_followup(ctx, format!("Available hands:\n{}{}", header, lines.join("\n"))).await?;
+        record_stage(ctx, Stage::Send, started).await;
+        return Ok(());
+    }
+    if pages.len() == 1 {
+        ctx.send(|b| b.embed(|e| resolution_page(e, &pages, 0, config.theme))).await?;
+        record_stage(ctx, Stage::Send, started).await;
+        return Ok(());
+    }
+    
+    let prev_id = format!("{}_prev", ctx.id());
+    let next_id = format!("{}_next", ctx.id());
+    let mut page = 0;
+    let reply = ctx.send(|b| b
+        .embed(|e| resolution_page(e, &pages, page, config.theme))
+        .components(|c| page_buttons(c, &prev_id, &next_id, page, pages.len()))
+    ).await?;
+    record_stage(ctx, Stage::Send, started).await;
+    let message_id = reply.message().await?.id;
+    lock_state(ctx).await.register_interaction(
+        ctx.channel_id(),
+        message_id,
+        RESOLUTION_PAGES_TIMEOUT.as_secs() + INTERACTION_CLEANUP_GRACE.as_secs(),
+        RESOLUTION_PAGES_EXPIRED,
+    );
+    
+    while let Some(mci) = serenity::CollectComponentInteraction::new(ctx.serenity_context())
+        .author_id(user_id)
+        .message_id(message_id)
+        .timeout(RESOLUTION_PAGES_TIMEOUT)
+        .await
+    {
+        if mci.data.custom_id == prev_id {
+            page = page.saturating_sub(1);
+        } else if mci.data.custom_id == next_id {
+            page = (page + 1).min(pages.len() - 1);
+        }
+        mci.create_interaction_response(ctx.serenity_context(), |r| r
+            .kind(serenity::InteractionResponseType::UpdateMessage)
+            .interaction_response_data(|d| d
+                .embed(|e| resolution_page(e, &pages, page, config.theme))
+                .components(|c| page_buttons(c, &prev_id, &next_id, page, pages.len())))
+        ).await?;
+    }
+    
+    // Keep the page that was showing, just without the buttons
+    reply.edit(ctx, |b| b
+        .embed(|e| resolution_page(e, &pages, page, config.theme))
+        .components(|c| c)
+    ).await?;
+    lock_state(ctx).await.finish_interaction(message_id);
+    Ok(())
+}
+
+const RESOLUTIONS_PER_PAGE: usize = 10;
+const RESOLUTION_PAGES_TIMEOUT: Duration = Duration::from_secs(300);
+const RESOLUTION_PAGES_EXPIRED: &str = "Page buttons expired, use /view_possible_resolutions to browse again.";
+
+fn resolution_page<'a>(e: &'a mut serenity::CreateEmbed, pages: &[String], page: usize, theme: Theme) -> &'a mut serenity::CreateEmbed {
+    e.title("Available hands")
+        .description(&pages[page])
+        .footer(|f| f.text(format!("Page {} of {} · resolve one with /resolve_hand", page + 1, pages.len())))
+        .color(theme.embed_color())
+}
+
+// Previous/Next buttons for a paged message, disabled at either end
+fn page_buttons<'a>(c: &'a mut serenity::CreateComponents, prev_id: &str, next_id: &str, page: usize, pages: usize) -> &'a mut serenity::CreateComponents {
+    c.create_action_row(|r| r
+        .create_button(|b| b.custom_id(prev_id).label("Previous").style(serenity::ButtonStyle::Secondary).disabled(page == 0))
+        .create_button(|b| b.custom_id(next_id).label("Next").style(serenity::ButtonStyle::Secondary).disabled(page + 1 >= pages)))
+}
+
+
+#[poise::command(slash_command)]
+pub async fn resolve_hand(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "Hand number from /view_possible_resolutions, or leave empty to pick from a menu"] hand_number: Option<usize>,
+    #[description = "Targets for Triple Threat or Double Trouble, comma-separated"] targets: Option<String>,
+    #[description = "Roll the attack with a Matched Edge: two attribute dice and a modifier, e.g. d8+d10+1"] attack: Option<String>,
+) -> Result<(), Error> {
+    let targets = parse_targets(targets);
+    let attack = match attack.as_deref().map(parse::check_dice).transpose() {
+        Ok(attack) => attack,
+        Err(e) => {
+            ctx.say(format!("Couldn't read the attack roll, {}.", e)).await?;
+            return Ok(());
+        }
+    };
+    let Some(hand_number) = hand_number else {
+        return run_hand_picker(ctx, targets, attack).await;
+    };
+    let user_id = ctx.author().id;
+    let mut player_state_manager = lock_state(ctx).await;
+    let config = player_state_manager.guild_config(ctx.guild_id());
+    
+    let Some(player) = player_state_manager.get_player_state(user_id) else {
+        ctx.say("You haven't started a combat yet! Use /start_new_combat to begin.").await?;
+        return Ok(());
+    };
+
+    // The number refers to the list the player last viewed, not whatever the hand holds now
+    let hand = match player.presented_hand(hand_number) {
+        Ok(hand) => hand,
+        Err(e) => {
+            ctx.say(e).await?;
+            return Ok(());
+        }
+    };
+    // A mistyped number can't be taken back, so tables can ask for a look first
+    let confirm = config.feature_enabled("confirm_resolutions");
+    let mut declaration = Declaration::new(hand, targets);
+    declaration.attack = attack;
+    let result = declare_hand(&mut player_state_manager, &config, user_id, &ctx.author().name, declaration, confirm);
+    drop(player_state_manager);
+    
+    match result {
+        Ok(Declared::Committed(resolved)) => {
+            announce_resolution(ctx, resolved).await?;
+        },
+        Ok(Declared::NeedsElement { preview, elements }) => return run_declaration_prompt(ctx, preview, elements).await,
+        Err(e) => {
+            ctx.say(e).await?;
+        }
+    }
+    Ok(())
+}
+
+#[poise::command(slash_command)]
+pub async fn resolve_best(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "Pick the most valuable hand or the cheapest one (defaults to value)"] prefer: Option<analysis::BestBy>,
+    #[description = "Targets, so Triple Threat and Double Trouble can be picked too"] targets: Option<String>,
+) -> Result<(), Error> {
+    let targets = parse_targets(targets);
+    let user_id = ctx.author().id;
+    let mut player_state_manager = lock_state(ctx).await;
+    let config = player_state_manager.guild_config(ctx.guild_id());
+    
+    let Some(player) = player_state_manager.players.get(&user_id) else {
+        ctx.say("You haven't started a combat yet! Use /start_new_combat to begin.").await?;
+        return Ok(());
+    };
+    let can_target = !targets.is_empty() || !config.encounter_targets.is_empty();
+    let Some(best) = analysis::best_hand(player, &config, prefer.unwrap_or_default(), can_target) else {
+        let message = if !can_target && !player.find_available_hands(&config).is_empty() {
+            "Your only hands need targets. Add some, e.g. `targets: Goblin, Harpy`."
+        } else {
+            "No valid hands available."
+        };
+        ctx.say(message).await?;
+        return Ok(());
+    };
+    // Picking the best hand is the whole point, so it skips the confirm step
+    let result = declare_hand(&mut player_state_manager, &config, user_id, &ctx.author().name, Declaration::new(best.hand, targets), false);
+    drop(player_state_manager);
+    
+    match result {
+        Ok(Declared::Committed(resolved)) => {
+            announce_resolution(ctx, resolved).await?;
+        },
+        Ok(Declared::NeedsElement { preview, elements }) => return run_declaration_prompt(ctx, preview, elements).await,
+        Err(e) => {
+            ctx.say(e).await?;
+        }
+    }
+    Ok(())
+}
+
+fn parse_targets(targets: Option<String>) -> Vec<String> {
+    targets
+        .unwrap_or_default()
+        .split(',')
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+        .collect()
+}
+
+enum Declared {
+    // A hand that resolved straight away
+    Committed(Resolved),
+    // A hand waiting on the commit prompt, to pick its element or to be confirmed
+    NeedsElement { preview: String, elements: Vec<ElementPreview> },
+}
+
+// A committed resolution: the announcement for the table, and the hand it left for the player
+struct Resolved {
+    announcement: String,
+    hand: String,
+    delivery: HandDelivery,
+}
+
+const NEW_HAND_IN_DMS: &str = "Your new hand is in your DMs.";
+
+// Announce a resolution to the table, then show the hand it left wherever the player keeps it
+async fn announce_resolution(ctx: poise::Context<'_, Data, Error>, resolved: Resolved) -> Result<(), Error> {
+    say_followup(ctx, resolved.announcement).await?;
+    deliver_hand(ctx, resolved.delivery, NEW_HAND_IN_DMS, resolved.hand).await
+}
+
+// Declare one of the player's detected hands. Hands without targets or an element to pick
+// resolve straight away unless the player should confirm first.
+fn declare_hand(
+    player_state_manager: &mut PlayerStateManager,
+    config: &GuildConfig,
+    user_id: serenity::UserId,
+    fallback_name: &str,
+    mut declaration: Declaration,
+    confirm: bool,
+) -> Result<Declared, String> {
+    let affinity = player_state_manager.affinity(user_id);
+    let Some(player) = player_state_manager.get_player_state(user_id) else {
+        return Err("You haven't started a combat yet! Use /start_new_combat to begin.".to_string());
+    };
+    
+    if !player.has_action(ActionKind::Resolution, &config.action_budget) {
+        return Err("You've already used your resolution action this turn".to_string());
+    }
+    let hand = &declaration.hand;
+    let targets = &declaration.targets;
+    // With the scene's enemies tracked, targets can be picked in the prompt instead
+    let can_pick_targets = !config.encounter_targets.is_empty();
+    if hand.needs_targets() && ((targets.is_empty() && !can_pick_targets) || targets.len() > hand.max_targets()) {
+        return Err(format!("This hand needs 1-{} targets, e.g. `targets: Goblin, Harpy`.", hand.max_targets()));
+    }
+    if declaration.attack.is_some() && HandKind::of(hand) != HandKind::MatchedEdge {
+        return Err("Only a Matched Edge comes with an attack roll.".to_string());
+    }
+    
+    declaration.affinity = affinity;
+    
+    if !declaration.hand.needs_targets() && !declaration.needs_element(config) && !confirm {
+        player.declaration = Some(declaration);
+        return commit_declaration(player_state_manager, config, user_id, fallback_name).map(Declared::Committed);
+    }
+    
+    let preview = declaration_preview(&declaration, &player.hand, config);
+    let elements = declaration.element_previews(config);
+    player.declaration = Some(declaration);
+    Ok(Declared::NeedsElement { preview, elements })
+}
+
+fn declaration_preview(declaration: &Declaration, cards: &[CardType], config: &GuildConfig) -> String {
+    let consumed = declaration.hand.positions_in(cards).iter()
+        .map(|&i| cards[i].label())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let next_step = if declaration.hand.needs_targets() && declaration.targets.is_empty() {
+        "Pick targets and an element, then commit."
+    } else {
+        "Choose an element if needed, then commit."
+    };
+    let mut preview = format!("Declared: {}\nUses {} ({})\n{}\n",
+        declaration.hand.to_string(),
+        consumed,
+        format_hand_positions(&declaration.hand, cards),
+        declaration.effect_text(config));
+    if let Some(dice) = &declaration.attack {
+        preview.push_str(&format!("The attack ({}) is rolled when you commit.\n", dice.label()));
+    }
+    preview.push_str(next_step);
+    preview
+}
+
+const HAND_PICKER_TIMEOUT: Duration = Duration::from_secs(120);
+// Discord allows at most 25 options per menu
+const MAX_MENU_OPTIONS: usize = 25;
+const HAND_PICKER_EXPIRED: &str = "Hand picker expired, nothing was resolved.";
+
+// Pick one of the detected hands from a menu instead of typing its number
+async fn run_hand_picker(ctx: poise::Context<'_, Data, Error>, targets: Vec<String>, attack: Option<CheckDice>) -> Result<(), Error> {
+    let user_id = ctx.author().id;
+    let player_state_manager = lock_state(ctx).await;
+    let config = player_state_manager.guild_config(ctx.guild_id());
+    let Some(player) = player_state_manager.players.get(&user_id) else {
+        ctx.say("You haven't started a combat yet! Use /start_new_combat to begin.").await?;
+        return Ok(());
+    };
+    let started = Instant::now();
+    let hands = player.find_available_hands(&config);
+    record_stage(ctx, Stage::Detection, started).await;
+    let positions: Vec<String> = hands.iter().map(|hand| format_hand_positions(hand, &player.hand)).collect();
+    drop(player_state_manager);
+    if hands.is_empty() {
+        ctx.say("No valid hands available.").await?;
+        return Ok(());
+    }
+    
+    let mut content = String::from("Pick a hand to resolve.");
+    if hands.len() > MAX_MENU_OPTIONS {
+        content.push_str(&format!("\nOnly the best {} of your {} hands fit in the menu. Use /view_possible_resolutions and /resolve_hand with a hand number for the rest.",
+            MAX_MENU_OPTIONS, hands.len()));
+    }
+    
+    let hand_id = format!("{}_hand", ctx.id());
+    let confirm_id = format!("{}_confirm", ctx.id());
+    let cancel_id = format!("{}_cancel", ctx.id());
+    let reply = ctx.send(|b| b
+        .content(content)
+        .components(|c| c
+            .create_action_row(|r| r.create_select_menu(|m| m
+                .custom_id(&hand_id)
+                .placeholder("Detected hands")
+                .options(|o| {
+                    for (i, hand) in hands.iter().enumerate().take(MAX_MENU_OPTIONS) {
+                        o.create_option(|opt| opt
+                            .label(truncate_label(&format!("{}. {} — MP {}", i + 1, hand.to_string(), config.mp_cost(hand))))
+                            .description(&positions[i])
+                            .value(i.to_string()));
+                    }
+                    o
+                })))
+            .create_action_row(|r| r
+                .create_button(|b| b.custom_id(&confirm_id).label("Resolve").style(serenity::ButtonStyle::Success))
+                .create_button(|b| b.custom_id(&cancel_id).label("Cancel").style(serenity::ButtonStyle::Secondary))))
+    ).await?;
+    let message_id = reply.message().await?.id;
+    lock_state(ctx).await.register_interaction(
+        ctx.channel_id(),
+        message_id,
+        HAND_PICKER_TIMEOUT.as_secs() + INTERACTION_CLEANUP_GRACE.as_secs(),
+        HAND_PICKER_EXPIRED,
+    );
+    
+    let mut chosen: Option<&HandType> = None;
+    while let Some(mci) = serenity::CollectComponentInteraction::new(ctx.serenity_context())
+        .author_id(user_id)
+        .message_id(message_id)
+        .timeout(HAND_PICKER_TIMEOUT)
+        .await
+    {
+        let custom_id = mci.data.custom_id.as_str();
+        
+        if custom_id == hand_id {
+            chosen = mci.data.values.first()
+                .and_then(|v| v.parse::<usize>().ok())
+                .and_then(|i| hands.get(i));
+            mci.create_interaction_response(ctx.serenity_context(), |r| r
+                .kind(serenity::InteractionResponseType::DeferredUpdateMessage)
+            ).await?;
+            continue;
+        }
+        
+        let declared = if custom_id == confirm_id {
+            let mut player_state_manager = lock_state(ctx).await;
+            let config = player_state_manager.guild_config(ctx.guild_id());
+            // The menu was built from the hand as it was, make sure the pick still holds
+            let available = player_state_manager.players.get(&user_id)
+                .map(|player| player.find_available_hands(&config))
+                .unwrap_or_default();
+            let result = match chosen {
+                None => Err("Pick a hand first.".to_string()),
+                Some(hand) if !available.contains(hand) => {
+                    Err("Your hand has changed since this menu opened. Run /resolve_hand again.".to_string())
+                },
+                // Picking from the menu and pressing Resolve is confirmation enough
+                Some(hand) => {
+                    let mut declaration = Declaration::new(hand.clone(), targets.clone());
+                    declaration.attack = attack;
+                    declare_hand(&mut player_state_manager, &config, user_id, &ctx.author().name, declaration, false)
+                },
+            };
+            drop(player_state_manager);
+            
+            match result {
+                Ok(declared) => Some(declared),
+                Err(e) => {
+                    mci.create_interaction_response(ctx.serenity_context(), |r| r
+                        .kind(serenity::InteractionResponseType::ChannelMessageWithSource)
+                        .interaction_response_data(|d| d.content(e).ephemeral(true))
+                    ).await?;
+                    continue;
+                }
+            }
+        } else {
+            None
+        };
+        
+        let content = match &declared {
+            Some(Declared::Committed(resolved)) => resolved.announcement.clone(),
+            Some(Declared::NeedsElement { preview, .. }) => preview.lines().next().unwrap_or_default().to_string(),
+            None => "Resolution cancelled.".to_string(),
+        };
+        mci.create_interaction_response(ctx.serenity_context(), |r| r
+            .kind(serenity::InteractionResponseType::UpdateMessage)
+            .interaction_response_data(|d| d.content(content).components(|c| c))
+        ).await?;
+        lock_state(ctx).await.finish_interaction(message_id);
+        
+        // Targeted hands carry on in the element prompt
+        return match declared {
+            Some(Declared::NeedsElement { preview, elements }) => run_declaration_prompt(ctx, preview, elements).await,
+            Some(Declared::Committed(resolved)) => deliver_hand(ctx, resolved.delivery, NEW_HAND_IN_DMS, resolved.hand).await,
+            None => Ok(()),
+        };
+    }
+    
+    reply.edit(ctx, |b| b
+        .content(HAND_PICKER_EXPIRED)
+        .components(|c| c)
+    ).await?;
+    lock_state(ctx).await.finish_interaction(message_id);
+    Ok(())
+}
+
+// Apply the player's declared resolution: spend the action, discard the cards and build the announcement
+fn commit_declaration(
+    player_state_manager: &mut PlayerStateManager,
+    config: &GuildConfig,
+    user_id: serenity::UserId,
+    fallback_name: &str,
+) -> Result<Resolved, String> {
+    let resolver_name = player_state_manager.display_name(user_id, fallback_name);
+    let affinity = player_state_manager.affinity(user_id);
+    let delivery = player_state_manager.preferences(user_id).hand_delivery;
+    
+    let Some(player) = player_state_manager.get_player_state(user_id) else {
+        return Err("You haven't started a combat yet! Use /start_new_combat to begin.".to_string());
+    };
+    let Some(declaration) = player.declaration.take() else {
+        return Err("You don't have a declared resolution to commit.".to_string());
+    };
+    
+    // The hand may have changed while the declaration was pending
+    if !player.find_available_hands(config).contains(&declaration.hand) {
+        return Err("Your hand has changed since you declared this resolution. Please resolve again.".to_string());
+    }
+    if declaration.hand.needs_targets() && declaration.targets.is_empty() {
+        player.declaration = Some(declaration);
+        return Err("Pick at least one target before committing.".to_string());
+    }
+    if declaration.needs_element(config) {
+        let message = "Choose an element before committing.".to_string();
+        player.declaration = Some(declaration);
+        return Err(message);
+    }
+    
+    let named_jokers: Vec<String> = declaration.hand.positions_in(&player.hand).iter()
+        .filter_map(|&i| player.hand[i].joker_name().map(str::to_string))
+        .collect();
+    let burned_jokers = if config.feature_enabled("burn_used_jokers") {
+        declaration.hand.positions_in(&player.hand).iter()
+            .filter(|&&i| matches!(player.hand[i], CardType::Joker { .. }))
+            .count()
+    } else {
+        0
+    };
+    // Resolve on a copy, so if the refill can't draw the cards, action and declaration all stay put
+    let mut resolved = player.clone();
+    let applied = resolved.use_action(ActionKind::Resolution, &config.action_budget).and_then(|()| {
+        resolved.last_resolution = Some(resolved.snapshot_zones());
+        resolved.consume_hand(&declaration.hand, config)
+    });
+    if let Err(e) = applied {
+        player.declaration = Some(declaration);
+        return Err(e);
+    }
+    *player = resolved;
+    player.record(EventKind::Resolved {
+        hand: declaration.hand.clone(),
+        element: declaration.element.clone(),
+        targets: declaration.targets.clone(),
+    });
+    
+    let hand_display = format_hand_display(&player.hand, affinity.as_ref());
+    let contributions = std::mem::take(&mut player.pending_contributions);
+    player_state_manager.record_contributions(&contributions);
+    
+    let mut message = declaration.effect_text(config);
+    message.push_str(&format!("\nCosts {} MP.", config.mp_cost(&declaration.hand)));
+    if let Some(dice) = &declaration.attack {
+        let roll = dice.roll(&mut rand::thread_rng());
+        message.push_str(&format!("\n**Attack**: {}", roll.describe()));
+        // A fumble misses whatever the total, otherwise the target's defense decides
+        if !roll.is_fumble() {
+            let bonus = declaration.effect(config).damage.unwrap_or(0);
+            message.push_str(&format!("\nOn a hit: {} (high roll) + {} = **{}** damage, plus your weapon's damage.",
+                roll.high_roll(), bonus, roll.high_roll() as i64 + bonus));
+        }
+    }
+    if let Some(affinity) = declaration.affinity.as_ref().filter(|_| declaration.matches_affinity()) {
+        message.push_str(&format!("\n{} {}'s bond with {} flares up!", AFFINITY_MARK, resolver_name, affinity.name()));
+    }
+    for name in named_jokers {
+        message.push_str(&format!("\n🃏 {} played its part.", name));
+    }
+    if burned_jokers > 0 {
+        message.push_str(&format!("\n🔥 {} joker(s) burned, out of the combat for good.", burned_jokers));
+    }
+    if !contributions.is_empty() {
+        let helpers = contributions.iter()
+            .map(|c| format!("{} ({})", c.contributor_name, c.kind.label()))
+            .collect::<Vec<_>>()
+            .join(", ");
+        message.push_str(&format!("\n{} was helped by {}!", resolver_name, helpers));
+    }
+    Ok(Resolved { announcement: message, hand: hand_display, delivery })
+}
+
+// Discord caps select option labels at 100 characters
+fn truncate_label(label: &str) -> String {
+    if label.chars().count() <= 100 {
+        return label.to_string();
+    }
+    let mut truncated: String = label.chars().take(99).collect();
+    truncated.push('…');
+    truncated
+}
+
+// Show the target and element pickers and commit/cancel buttons for a declared resolution
+async fn run_declaration_prompt(
+    ctx: poise::Context<'_, Data, Error>,
+    preview: String,
+    elements: Vec<ElementPreview>,
+) -> Result<(), Error> {
+    let user_id = ctx.author().id;
+    let targets_id = format!("{}_targets", ctx.id());
+    let element_id = format!("{}_element", ctx.id());
+    let commit_id = format!("{}_commit", ctx.id());
+    let cancel_id = format!("{}_cancel", ctx.id());
+    
+    // Targets typed with the command are kept, otherwise they're picked from the scene
+    let player_state_manager = lock_state(ctx).await;
+    let max_targets = player_state_manager.players.get(&user_id)
+        .and_then(|p| p.declaration.as_ref())
+        .filter(|d| d.hand.needs_targets() && d.targets.is_empty())
+        .map(|d| d.hand.max_targets())
+        .unwrap_or(0);
+    let mut choices = if max_targets > 0 { player_state_manager.target_choices(ctx.guild_id()) } else { Vec::new() };
+    drop(player_state_manager);
+    // Discord allows at most 25 options per menu
+    choices.truncate(25);
+    
+    let reply = ctx.send(|b| b
+        .content(preview)
+        .components(|c| {
+            if !choices.is_empty() {
+                c.create_action_row(|r| r.create_select_menu(|m| m
+                    .custom_id(&targets_id)
+                    .placeholder(format!("Choose up to {} target(s)", max_targets.min(choices.len())))
+                    .min_values(1)
+                    .max_values(max_targets.min(choices.len()) as u64)
+                    .options(|o| {
+                        for (i, target) in choices.iter().enumerate() {
+                            o.create_option(|opt| opt
+                                .label(truncate_label(target))
+                                .value(i.to_string()));
+                        }
+                        o
+                    })));
+            }
+            if elements.len() > 1 {
+                c.create_action_row(|r| r.create_select_menu(|m| m
+                    .custom_id(&element_id)
+                    .placeholder("Choose the damage element")
+                    .options(|o| {
+                        for option in &elements {
+                            let label = format!("{} {} — {}",
+                                format_element_list(std::slice::from_ref(&option.element)),
+                                option.element.name(),
+                                option.summary);
+                            o.create_option(|opt| opt
+                                .label(truncate_label(&label))
+                                .value(option.element.name()));
+                        }
+                        o
+                    })));
+            }
+            c.create_action_row(|r| r
+                .create_button(|b| b.custom_id(&commit_id).label("Commit").style(serenity::ButtonStyle::Success))
+                .create_button(|b| b.custom_id(&cancel_id).label("Cancel").style(serenity::ButtonStyle::Secondary)))
+        })
+    ).await?;
+    let message_id = reply.message().await?.id;
+    lock_state(ctx).await.register_interaction(
+        ctx.channel_id(),
+        message_id,
+        DECLARATION_PROMPT_TIMEOUT.as_secs() + INTERACTION_CLEANUP_GRACE.as_secs(),
+        DECLARATION_SAVED,
+    );
+    
+    while let Some(mci) = serenity::CollectComponentInteraction::new(ctx.serenity_context())
+        .author_id(user_id)
+        .message_id(message_id)
+        .timeout(DECLARATION_PROMPT_TIMEOUT)
+        .await
+    {
+        let custom_id = mci.data.custom_id.as_str();
+        
+        if custom_id == targets_id {
+            let targets: Vec<String> = mci.data.values.iter()
+                .filter_map(|v| v.parse::<usize>().ok())
+                .filter_map(|i| choices.get(i).cloned())
+                .collect();
+            let mut player_state_manager = lock_state(ctx).await;
+            let config = player_state_manager.guild_config(ctx.guild_id());
+            let preview = player_state_manager.get_player_state(user_id).and_then(|player| {
+                let declaration = player.declaration.as_mut()?;
+                declaration.targets = targets;
+                Some(declaration_preview(declaration, &player.hand, &config))
+            });
+            drop(player_state_manager);
+            
+            // Show the chosen targets in the preview, leaving the menus as they are
+            mci.create_interaction_response(ctx.serenity_context(), |r| match preview {
+                Some(preview) => r
+                    .kind(serenity::InteractionResponseType::UpdateMessage)
+                    .interaction_response_data(|d| d.content(preview)),
+                None => r.kind(serenity::InteractionResponseType::DeferredUpdateMessage),
+            }).await?;
+            continue;
+        }
+        
+        if custom_id == element_id {
+            let element = mci.data.values.first().and_then(|v| ElementType::from_name(v));
+            let mut player_state_manager = lock_state(ctx).await;
+            if let Some(declaration) = player_state_manager.get_player_state(user_id).and_then(|p| p.declaration.as_mut()) {
+                declaration.element = element;
+            }
+            drop(player_state_manager);
+            
+            mci.create_interaction_response(ctx.serenity_context(), |r| r
+                .kind(serenity::InteractionResponseType::DeferredUpdateMessage)
+            ).await?;
+            continue;
+        }
+        
+        let (content, resolved) = if custom_id == commit_id {
+            let mut player_state_manager = lock_state(ctx).await;
+            let config = player_state_manager.guild_config(ctx.guild_id());
+            let result = commit_declaration(&mut player_state_manager, &config, user_id, &ctx.author().name);
+            drop(player_state_manager);
+            
+            match result {
+                Ok(resolved) => (resolved.announcement.clone(), Some(resolved)),
+                // Leave the prompt open so the player can fix the declaration
+                Err(e) => {
+                    mci.create_interaction_response(ctx.serenity_context(), |r| r
+                        .kind(serenity::InteractionResponseType::ChannelMessageWithSource)
+                        .interaction_response_data(|d| d.content(e).ephemeral(true))
+                    ).await?;
+                    continue;
+                }
+            }
+        } else {
+            let mut player_state_manager = lock_state(ctx).await;
+            if let Some(player) = player_state_manager.get_player_state(user_id) {
+                player.declaration = None;
+            }
+            drop(player_state_manager);
+            ("Resolution cancelled.".to_string(), None)
+        };
+        
+        mci.create_interaction_response(ctx.serenity_context(), |r| r
+            .kind(serenity::InteractionResponseType::UpdateMessage)
+            .interaction_response_data(|d| d.content(content).components(|c| c))
+        ).await?;
+        lock_state(ctx).await.finish_interaction(message_id);
+        if let Some(resolved) = resolved {
+            deliver_hand(ctx, resolved.delivery, NEW_HAND_IN_DMS, resolved.hand).await?;
+        }
+        return Ok(());
+    }
+    
+    // Timed out, the declaration stays stored for /commit_resolution
+    reply.edit(ctx, |b| b
+        .content(DECLARATION_SAVED)
+        .components(|c| c)
+    ).await?;
+    lock_state(ctx).await.finish_interaction(message_id);
+    Ok(())
+}
+
+const DECLARATION_PROMPT_TIMEOUT: Duration = Duration::from_secs(120);
+const DECLARATION_SAVED: &str = "Declaration saved. Use /commit_resolution or /cancel_resolution to finish it.";
+
+// Leave a live collector time to clean up its own message before housekeeping does
+const INTERACTION_CLEANUP_GRACE: Duration = Duration::from_secs(60);
+
+// Strip the buttons from prompts whose collector never finished, e.g. across a restart,
+// and the embeds from old hand panels
+async fn collapse_expired_interactions(player_state_manager: &Mutex<PlayerStateManager>, settings: &Mutex<Settings>) {
+    let expired = player_state_manager.lock().await.take_expired_interactions(events::unix_now());
+    if expired.is_empty() {
+        return;
+    }
+    let http = serenity::Http::new(&settings.lock().await.token);
+    for pending in expired {
+        let result = pending.channel_id.edit_message(&http, pending.message_id, |m| m
+            .content(&pending.expired_text)
+            .set_embeds(Vec::new())
+            .components(|c| c)
+        ).await;
+        // The message may have been deleted already, which is just as good
+        if let Err(e) = result {
+            eprintln!("Couldn't collapse message {}: {}", pending.message_id, e);
+        }
+    }
+}
+
+#[poise::command(slash_command)]
+pub async fn commit_resolution(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "Damage element to use, if the hand allows several"] element: Option<String>,
+) -> Result<(), Error> {
+    let user_id = ctx.author().id;
+    let mut player_state_manager = lock_state(ctx).await;
+    let config = player_state_manager.guild_config(ctx.guild_id());
+    
+    if let Some(element) = element {
+        let element = match parse::element(&element) {
+            Ok(element) => element,
+            Err(e) => {
+                ctx.say(format!("Unknown element, {}.", e)).await?;
+                return Ok(());
+            }
+        };
+        let declaration = player_state_manager.get_player_state(user_id).and_then(|p| p.declaration.as_mut());
+        match declaration {
+            Some(declaration) if declaration.hand.suits().contains(&element) => declaration.element = Some(element),
+            Some(_) => {
+                ctx.say("That element isn't available for the declared hand.").await?;
+                return Ok(());
+            },
+            None => {}
+        }
+    }
+    
+    let result = commit_declaration(&mut player_state_manager, &config, user_id, &ctx.author().name);
+    drop(player_state_manager);
+    
+    match result {
+        Ok(resolved) => announce_resolution(ctx, resolved).await?,
+        Err(e) => {
+            ctx.say(e).await?;
+        }
+    };
+    Ok(())
+}
+
+#[poise::command(slash_command)]
+pub async fn cancel_resolution(
+    ctx: poise::Context<'_, Data, Error>,
+) -> Result<(), Error> {
+    let user_id = ctx.author().id;
+    let mut player_state_manager = lock_state(ctx).await;
+    
+    let cancelled = player_state_manager
+        .get_player_state(user_id)
+        .and_then(|p| p.declaration.take())
+        .is_some();
+    drop(player_state_manager);
+    
+    if cancelled {
+        ctx.say("Resolution cancelled.").await?;
+    } else {
+        ctx.say("You don't have a declared resolution.").await?;
+    }
+    Ok(())
+}
+
+#[poise::command(slash_command)]
+pub async fn deck_diff(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "Whose deck to look at (defaults to you)"] player: Option<serenity::User>,
+) -> Result<(), Error> {
+    let user = player.as_ref().unwrap_or_else(|| ctx.author());
+    let player_state_manager = lock_state(ctx).await;
+    let name = player_state_manager.display_name(user.id, &user.name);
+    
+    let Some(state) = player_state_manager.players.get(&user.id) else {
+        drop(player_state_manager);
+        ctx.say(format!("{} isn't in a combat right now.", name)).await?;
+        return Ok(());
+    };
+    let log = state.log.clone();
+    let hand = state.hand.clone();
+    let burned = state.burned.clone();
+    let reshuffles = state.reshuffles;
+    drop(player_state_manager);
+    
+    // Older events may be archived to disk, so they're read after unlocking
+    let history = match log.events_between(0, log.total_events()) {
+        Ok(history) => history,
+        Err(e) => {
+            eprintln!("Failed to load the history of {}: {}", log.combat_id, e);
+            ctx.send(|b| b.content("That combat's history couldn't be loaded.").ephemeral(true)).await?;
+            return Ok(());
+        }
+    };
+    let drawn = events::drawn_since_deal(&history);
+    
+    // Cards still in hand are private, so they're only counted. Each one was its latest draw.
+    let mut gone: Vec<&CardType> = drawn.iter().collect();
+    for held in &hand {
+        if let Some(index) = gone.iter().rposition(|card| card.id() == held.id()) {
+            gone.remove(index);
+        }
+    }
+    gone.sort_by_key(|card| card_sort_key(card));
+    let gone = if gone.is_empty() {
+        "nothing yet".to_string()
+    } else {
+        gone.iter().map(|card| card.label()).collect::<Vec<_>>().join(", ")
+    };
+    
+    let mut message = format!("**{}'s deck since the deal**\nLeft the deck: {} card(s)\nIn hand: {} (hidden)\nGone: {}\n",
+        name,
+        drawn.len(),
+        hand.len(),
+        gone);
+    if !burned.is_empty() {
+        message.push_str(&format!("Burned for good: {}\n", format_card_labels(&burned)));
+    }
+    if reshuffles > 0 {
+        message.push_str(&format!("The discard has been shuffled back in {} time(s).\n", reshuffles));
+    }
+    
+    ctx.say(message).await?;
+    Ok(())
+}
+
+fn format_card_labels(cards: &[CardType]) -> String {
+    cards.iter().map(|card| card.label()).collect::<Vec<_>>().join(", ")
+}
+
+// Order cards by suit then value, with jokers last
+fn card_sort_key(card: &CardType) -> (u8, u8) {
+    match card {
+        CardType::Number(value, suit, _) => (suit.element.sort_order(), value.unwrap_or(0)),
+        CardType::Joker { .. } => (u8::MAX, 0),
+    }
+}
+
+const HISTORY_PAGE_SIZE: usize = 15;
+
+#[poise::command(slash_command)]
+pub async fn history(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "Page to show, 1 is the most recent"] page: Option<usize>,
+) -> Result<(), Error> {
+    let user_id = ctx.author().id;
+    let player_state_manager = lock_state(ctx).await;
+    
+    let Some(state) = player_state_manager.players.get(&user_id) else {
+        ctx.say("You haven't started a combat yet! Use /start_new_combat to begin.").await?;
+        return Ok(());
+    };
+    let log = state.log.clone();
+    drop(player_state_manager);
+    
+    match format_history_page(&log, page, "Combat history") {
+        Some(message) => say_chunked(ctx, message, true).await?,
+        None => {
+            ctx.send(|b| b.content("That part of your history couldn't be loaded.").ephemeral(true)).await?;
+        },
+    }
+    Ok(())
+}
+
+// One page of a combat's timeline, 1 being the most recent. None if the page couldn't be read.
+fn format_history_page(log: &EventLog, page: Option<usize>, title: &str) -> Option<String> {
+    let total = log.total_events();
+    let pages = ((total + HISTORY_PAGE_SIZE - 1) / HISTORY_PAGE_SIZE).max(1);
+    let page = page.unwrap_or(1).clamp(1, pages);
+    
+    // Older pages may need to be read back from the archive on disk
+    let events = match log.page(page - 1, HISTORY_PAGE_SIZE) {
+        Ok(events) => events,
+        Err(e) => {
+            eprintln!("Failed to load history for {}: {}", log.combat_id, e);
+            return None;
+        }
+    };
+    
+    let mut message = format!("**{}** (page {} of {})\n", title, page, pages);
+    if events.is_empty() {
+        message.push_str("Nothing has happened yet.");
+    }
+    for event in &events {
+        message.push_str(&format!("<t:{}:T> {}\n", event.at, event.describe()));
+    }
+    Some(message)
+}
+
+#[poise::command(slash_command)]
+pub async fn burn(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "Cards to remove from the combat, e.g. 2, 1-3 or fire 5"] cards: String,
+) -> Result<(), Error> {
+    let user_id = ctx.author().id;
+    let mut player_state_manager = lock_state(ctx).await;
+    let config = player_state_manager.guild_config(ctx.guild_id());
+    
+    // Players only burn their own cards when an ability says so, the GM can always use /gm burn
+    if !config.feature_enabled("burn") {
+        ctx.say("Burning cards isn't enabled on this server. Ask your GM to use /gm burn.").await?;
+        return Ok(());
+    }
+    
+    let Some(player) = player_state_manager.get_player_state(user_id) else {
+        ctx.say("You haven't started a combat yet! Use /start_new_combat to begin.").await?;
+        return Ok(());
+    };
+    
+    let mut indices = match parse::cards(&cards, &player.hand) {
+        Ok(indices) => indices,
+        Err(e) => {
+            ctx.say(format!("Please give card positions (1-{}) or descriptions like fire 5, {}.", player.hand.len(), e)).await?;
+            return Ok(());
+        }
+    };
+    indices.sort_unstable_by(|a, b| b.cmp(a)); // Sort in reverse to remove from highest index first
+    let mut burned = Vec::new();
+    for index in indices {
+        burned.push(player.burn_from_hand(index)?.label());
+    }
+    burned.reverse();
+    let hand = player.hand.clone();
+    let affinity = player_state_manager.affinity(user_id);
+    let delivery = player_state_manager.preferences(user_id).hand_delivery;
+    drop(player_state_manager);
+    
+    let summary = format!("Burned {}. They won't come back this combat.", burned.join(", "));
+    let public = format!("{} Your hand is in your DMs.", summary);
+    deliver_hand(ctx, delivery, &public, format!("{}\n{}", summary, format_hand_display(&hand, affinity.as_ref()))).await
+}
+
+#[poise::command(slash_command)]
+pub async fn tuck(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "Card to put back, by position or e.g. fire 5"] card: String,
+    #[description = "Top or bottom of the deck (defaults to top)"] end: Option<DeckEnd>,
+) -> Result<(), Error> {
+    let user_id = ctx.author().id;
+    let end = end.unwrap_or_default();
+    let mut player_state_manager = lock_state(ctx).await;
+    let config = player_state_manager.guild_config(ctx.guild_id());
+    
+    // Only house rules and certain skills put cards back
+    if !config.feature_enabled("tuck") {
+        ctx.say("Tucking cards isn't enabled on this server. A GM can turn it on with /configure_feature.").await?;
+        return Ok(());
+    }
+    
+    let Some(player) = player_state_manager.get_player_state(user_id) else {
+        ctx.say("You haven't started a combat yet! Use /start_new_combat to begin.").await?;
+        return Ok(());
+    };
+    
+    let index = match parse::cards(&card, &player.hand).as_deref() {
+        Ok(&[index]) => index,
+        Ok(_) => {
+            ctx.say("Tuck one card at a time.").await?;
+            return Ok(());
+        },
+        Err(e) => {
+            ctx.say(format!("Please give a card position (1-{}) or a description like fire 5, {}.", player.hand.len(), e)).await?;
+            return Ok(());
+        }
+    };
+    let card = player.tuck_from_hand(index, end)?;
+    let hand = player.hand.clone();
+    let affinity = player_state_manager.affinity(user_id);
+    let delivery = player_state_manager.preferences(user_id).hand_delivery;
+    drop(player_state_manager);
+    
+    let tucked = format!("Put {} on the {} of your deck.", card.label(), end.label());
+    let public = format!("{} Your hand is in your DMs.", tucked);
+    deliver_hand(ctx, delivery, &public, format!("{}\n{}", tucked, format_hand_display(&hand, affinity.as_ref()))).await
+}
+
+const GIFT_OFFER_TIMEOUT: Duration = Duration::from_secs(120);
+const GIFT_OFFER_EXPIRED: &str = "Nobody took the card in time, it stayed where it was.";
+
+#[poise::command(slash_command, guild_only)]
+pub async fn give_card(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "Player to give the card to"] player: serenity::User,
+    #[description = "Card to give, by position or e.g. fire 5"] card: String,
+) -> Result<(), Error> {
+    let user_id = ctx.author().id;
+    if player.id == user_id {
+        ctx.say("You can't give a card to yourself.").await?;
+        return Ok(());
+    }
+    
+    let player_state_manager = lock_state(ctx).await;
+    let config = player_state_manager.guild_config(ctx.guild_id());
+    if !config.feature_enabled("give_card") {
+        ctx.say("Giving cards isn't enabled on this server. A GM can turn it on with /configure_feature.").await?;
+        return Ok(());
+    }
+    
+    let giver_name = player_state_manager.display_name(user_id, &ctx.author().name);
+    let receiver_name = player_state_manager.display_name(player.id, &player.name);
+    if !player_state_manager.players.contains_key(&player.id) {
+        ctx.say(format!("{} isn't in a combat right now.", receiver_name)).await?;
+        return Ok(());
+    }
+    let Some(giver) = player_state_manager.players.get(&user_id) else {
+        ctx.say("You haven't started a combat yet! Use /start_new_combat to begin.").await?;
+        return Ok(());
+    };
+    
+    let offered = match parse::cards(&card, &giver.hand).as_deref() {
+        Ok(&[index]) => giver.hand[index].clone(),
+        Ok(_) => {
+            ctx.say("Give one card at a time.").await?;
+            return Ok(());
+        },
+        Err(e) => {
+            ctx.say(format!("Please give a card position (1-{}) or a description like fire 5, {}.", giver.hand.len(), e)).await?;
+            return Ok(());
+        }
+    };
+    drop(player_state_manager);
+    
+    let accept_id = format!("{}_accept", ctx.id());
+    let decline_id = format!("{}_decline", ctx.id());
+    let reply = ctx.send(|b| b
+        .content(format!("{} offers {} to {}. <@{}>, will you take it?", giver_name, offered.label(), receiver_name, player.id))
+        .components(|c| c.create_action_row(|r| r
+            .create_button(|b| b.custom_id(&accept_id).label("Accept").style(serenity::ButtonStyle::Success))
+            .create_button(|b| b.custom_id(&decline_id).label("Decline").style(serenity::ButtonStyle::Secondary))))
+    ).await?;
+    let message_id = reply.message().await?.id;
+    lock_state(ctx).await.register_interaction(
+        ctx.channel_id(),
+        message_id,
+        GIFT_OFFER_TIMEOUT.as_secs() + INTERACTION_CLEANUP_GRACE.as_secs(),
+        GIFT_OFFER_EXPIRED,
+    );
+    
+    // Only the player being offered the card answers
+    if let Some(mci) = serenity::CollectComponentInteraction::new(ctx.serenity_context())
+        .author_id(player.id)
+        .message_id(message_id)
+        .timeout(GIFT_OFFER_TIMEOUT)
+        .await
+    {
+        let content = if mci.data.custom_id == accept_id {
+            let mut player_state_manager = lock_state(ctx).await;
+            let max_hand_size = player_state_manager.guild_config(ctx.guild_id()).max_hand_size();
+            let result = player_state_manager.give_card((user_id, &giver_name), (player.id, &receiver_name), offered.id(), max_hand_size);
+            drop(player_state_manager);
+            
+            match result {
+                Ok(card) => format!("{} gave {} to {}.", giver_name, card.label(), receiver_name),
+                Err(e) => format!("{} couldn't take {}. {}", receiver_name, offered.label(), e),
+            }
+        } else {
+            format!("{} turned down {} from {}.", receiver_name, offered.label(), giver_name)
+        };
+        mci.create_interaction_response(ctx.serenity_context(), |r| r
+            .kind(serenity::InteractionResponseType::UpdateMessage)
+            .interaction_response_data(|d| d.content(content).components(|c| c))
+        ).await?;
+        lock_state(ctx).await.finish_interaction(message_id);
+        return Ok(());
+    }
+    
+    reply.edit(ctx, |b| b
+        .content(GIFT_OFFER_EXPIRED)
+        .components(|c| c)
+    ).await?;
+    lock_state(ctx).await.finish_interaction(message_id);
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only)]
+pub async fn search_deck(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "Value of the card to find"]
+    #[min = 1]
+    #[max = 21]
+    value: u8,
+    #[description = "An element, e.g. Fire, Ice or Bolt"] element: String,
+    #[description = "Why, for the GM if the search needs approval"] note: Option<String>,
+) -> Result<(), Error> {
+    let user_id = ctx.author().id;
+    let element = match parse::element(&element) {
+        Ok(element) => element,
+        Err(e) => {
+            ctx.send(|b| b.content(format!("Unknown element, {}.", e)).ephemeral(true)).await?;
+            return Ok(());
+        }
+    };
+    if !(1..=DeckTemplate::MAX_VALUE).contains(&value) {
+        ctx.send(|b| b.content(format!("Value must be between 1 and {}.", DeckTemplate::MAX_VALUE)).ephemeral(true)).await?;
+        return Ok(());
+    }
+    
+    let mut player_state_manager = lock_state(ctx).await;
+    let config = player_state_manager.guild_config(ctx.guild_id());
+    // Tutoring is strong, so unless the table allows it outright every search goes past the GM
+    if !config.feature_enabled("search_deck") {
+        drop(player_state_manager);
+        return request_approval(ctx, ApprovalAction::SearchDeck(value, element), note).await;
+    }
+    
+    let Some(player) = player_state_manager.get_player_state(user_id) else {
+        ctx.say("You haven't started a combat yet! Use /start_new_combat to begin.").await?;
+        return Ok(());
+    };
+    let card = match player.search_deck(value, &element) {
+        Ok(card) => card,
+        Err(e) => {
+            ctx.say(format!("{}.", e)).await?;
+            return Ok(());
+        }
+    };
+    let hand = player.hand.clone();
+    let affinity = player_state_manager.affinity(user_id);
+    let delivery = player_state_manager.preferences(user_id).hand_delivery;
+    drop(player_state_manager);
+    
+    let searched = format!("Searched your deck for {} and shuffled it.", card.label());
+    let public = format!("{} Your hand is in your DMs.", searched);
+    deliver_hand(ctx, delivery, &public, format!("{}\n{}", searched, format_hand_display(&hand, affinity.as_ref()))).await
+}
+
+#[poise::command(slash_command)]
+pub async fn set_joker(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "Position of the joker in your hand, 1 for the first card"] position: usize,
+    #[description = "Value the joker stands for"]
+    #[min = 1]
+    #[max = 21]
+    value: u8,
+    #[description = "An element, e.g. Fire, Ice or Bolt"] element: String,
+) -> Result<(), Error> {
+    let user_id = ctx.author().id;
+    let element = match parse::element(&element) {
+        Ok(element) => element,
+        Err(e) => {
+            ctx.send(|b| b.content(format!("Unknown element, {}.", e)).ephemeral(true)).await?;
+            return Ok(());
+        }
+    };
+    if !(1..=DeckTemplate::MAX_VALUE).contains(&value) {
+        ctx.send(|b| b.content(format!("Value must be between 1 and {}.", DeckTemplate::MAX_VALUE)).ephemeral(true)).await?;
+        return Ok(());
+    }
+    
+    let mut player_state_manager = lock_state(ctx).await;
+    let Some(player) = player_state_manager.get_player_state(user_id) else {
+        ctx.say("You haven't started a combat yet! Use /start_new_combat to begin.").await?;
+        return Ok(());
+    };
+    
+    let Some(index) = position.checked_sub(1).filter(|&i| i < player.hand.len()) else {
+        ctx.say(format!("Please provide a valid card position (1-{})", player.hand.len())).await?;
+        return Ok(());
+    };
+    let card = match player.assign_joker(index, value, element) {
+        Ok(card) => card,
+        Err(e) => {
+            ctx.say(e).await?;
+            return Ok(());
+        }
+    };
+    let hand = player.hand.clone();
+    let affinity = player_state_manager.affinity(user_id);
+    let delivery = player_state_manager.preferences(user_id).hand_delivery;
+    drop(player_state_manager);
+    
+    // What the joker stands for is as private as the rest of the hand
+    let message = format!("Your joker now counts as {} until it leaves your hand.\n{}", card.label(), format_hand_display(&hand, affinity.as_ref()));
+    deliver_hand(ctx, delivery, "Joker set, your hand is in your DMs.", message).await
+}
+
+#[poise::command(slash_command)]
+pub async fn status(
+    ctx: poise::Context<'_, Data, Error>,
+) -> Result<(), Error> {
+    let user_id = ctx.author().id;
+    let player_state_manager = lock_state(ctx).await;
+    let config = player_state_manager.guild_config(ctx.guild_id());
+    let fabula_points = player_state_manager.fabula_points(user_id);
+    
+    let Some(player) = player_state_manager.players.get(&user_id) else {
+        ctx.say("You haven't started a combat yet! Use /start_new_combat to begin.").await?;
+        return Ok(());
+    };
+    
+    let actions = [ActionKind::Draw, ActionKind::Resolution, ActionKind::Reaction]
+        .iter()
+        .map(|&kind| format!("{} {}/{}", kind.label(), player.actions_used.get(kind), config.action_budget.limit(kind)))
+        .collect::<Vec<_>>()
+        .join(", ");
+    
+    let mut message = format!("**Turn {}**\nActions used: {}\nDeck: {} | Hand: {} | Discard: {}\n",
+        player.turn,
+        actions,
+        player.deck.cards.len(),
+        player.hand.len(),
+        player.discard.len());
+    if !player.burned.is_empty() {
+        message.push_str(&format!("Burned: {}\n", format_card_labels(&player.burned)));
+    }
+    if let Some(limit) = config.mulligan_limit {
+        message.push_str(&format!("Mulligans: {}/{}\n", player.mulligans, limit));
+    }
+    if player.extra_cards() > 0 {
+        message.push_str(&format!("Holding {} extra card(s), they won't be replaced when used.\n", player.extra_cards()));
+    }
+    if config.module_enabled(FabulaPoints.name()) {
+        message.push_str(&format!("Fabula Points: {}\n", fabula_points));
+    }
+    drop(player_state_manager);
+    
+    ctx.say(message).await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only)]
+pub async fn party_status(
+    ctx: poise::Context<'_, Data, Error>,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+    let player_state_manager = lock_state(ctx).await;
+    let config = player_state_manager.guild_config(Some(guild_id));
+    
+    // Counts only, so the whole table can see it without giving hands away
+    let mut lines: Vec<(String, String)> = player_state_manager.players.iter()
+        .filter(|(user_id, state)| state.guild_id == Some(guild_id)
+            && !state.orphaned
+            && (config.table_members.is_empty() || config.table_members.contains(user_id)))
+        .map(|(user_id, state)| {
+            let name = player_state_manager.display_name(*user_id, &format!("<@{}>", user_id));
+            let mut line = format!("• **{}**: Hand {} | Deck {} | Discard {}", name, state.hand.len(), state.deck.cards.len(), state.discard.len());
+            if !state.burned.is_empty() {
+                line.push_str(&format!(" | Burned {}", state.burned.len()));
+            }
+            (name, line)
+        })
+        .collect();
+    drop(player_state_manager);
+    
+    if lines.is_empty() {
+        ctx.say("Nobody is in a combat on this server right now.").await?;
+        return Ok(());
+    }
+    lines.sort_by(|a, b| a.0.to_lowercase().cmp(&b.0.to_lowercase()));
+    let mut message = format!("**Party status** ({} in combat)\n", lines.len());
+    for (_, line) in &lines {
+        message.push_str(line);
+        message.push('\n');
+    }
+    say_chunked(ctx, message, false).await?;
+    Ok(())
+}
+
+#[poise::command(slash_command)]
+pub async fn contribute(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "Player whose next resolution you are helping"] player: serenity::User,
+    #[description = "How you are helping"] kind: ContributionKind,
+) -> Result<(), Error> {
+    if player.id == ctx.author().id {
+        ctx.say("You can't contribute to your own resolution.").await?;
+        return Ok(());
+    }
+    
+    let mut player_state_manager = lock_state(ctx).await;
+    let config = player_state_manager.guild_config(ctx.guild_id());
+    let contributor_name = player_state_manager.display_name(ctx.author().id, &ctx.author().name);
+    let target_name = player_state_manager.display_name(player.id, &player.name);
+    
+    let Some(target) = player_state_manager.players.get(&player.id) else {
+        ctx.say(format!("{} isn't in a combat right now.", target_name)).await?;
+        return Ok(());
+    };
+    // Each contribution is counted in the campaign stats, so one per helper per resolution
+    if target.pending_contributions.iter().any(|c| c.contributor == ctx.author().id) {
+        ctx.say(format!("You're already helping with {}'s next resolution.", target_name)).await?;
+        return Ok(());
+    }
+    let target_guild = target.guild_id;
+    
+    // Only players fighting at the same table can help, and it costs them a reaction
+    let Some(contributor) = player_state_manager.get_player_state(ctx.author().id) else {
+        ctx.say("You need to be in a combat yourself to help with a resolution.").await?;
+        return Ok(());
+    };
+    if contributor.guild_id != target_guild {
+        ctx.say(format!("{} is fighting at another table.", target_name)).await?;
+        return Ok(());
+    }
+    if let Err(e) = contributor.use_action(ActionKind::Reaction, &config.action_budget) {
+        ctx.say(e).await?;
+        return Ok(());
+    }
+    
+    let Some(target) = player_state_manager.get_player_state(player.id) else {
+        ctx.say(format!("{} isn't in a combat right now.", target_name)).await?;
+        return Ok(());
+    };
+    
+    target.pending_contributions.push(Contribution {
+        contributor: ctx.author().id,
+        contributor_name: contributor_name.clone(),
+        kind,
+    });
+    drop(player_state_manager);
+    
+    ctx.say(format!("{}'s {} will count toward {}'s next resolution.", contributor_name, kind.label(), target_name)).await?;
+    Ok(())
+}
+
+#[derive(Debug, Default, PartialEq, poise::ChoiceParameter)]
+pub enum QuickDrawSource {
+    #[default]
+    #[name = "A fresh deck"]
+    FreshDeck,
+    #[name = "My combat deck (uses your draw action)"]
+    MyDeck,
+}
+
+#[poise::command(slash_command)]
+pub async fn quick_draw(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "Where the card comes from (defaults to a fresh deck)"] from: Option<QuickDrawSource>,
+) -> Result<(), Error> {
+    let user_id = ctx.author().id;
+    let mut player_state_manager = lock_state(ctx).await;
+    let config = player_state_manager.guild_config(ctx.guild_id());
+    let name = player_state_manager.display_name(user_id, &ctx.author().name);
+    
+    let card = match from.unwrap_or_default() {
+        // Built and thrown away again, nothing is saved
+        QuickDrawSource::FreshDeck => {
+            let mut deck = Deck::from_template(&config.deck_template);
+            deck.shuffle_with(config.shuffle.shuffler().as_ref());
+            deck.cards.pop().ok_or("The deck template has no cards")?
+        },
+        QuickDrawSource::MyDeck => {
+            let Some(player) = player_state_manager.get_player_state(user_id) else {
+                ctx.say("You haven't started a combat yet! Use /start_new_combat to begin, or draw from a fresh deck.").await?;
+                return Ok(());
+            };
+            // The card leaves the combat deck for good, so it costs the turn's draw like any other
+            if let Err(e) = player.use_action(ActionKind::Draw, &config.action_budget) {
+                ctx.say(e).await?;
+                return Ok(());
+            }
+            match player.reveal_from_deck() {
+                Ok(card) => card,
+                Err(e) => {
+                    player.refund_action(ActionKind::Draw);
+                    ctx.say(format!("{}.", e)).await?;
+                    return Ok(());
+                }
+            }
+        },
+    };
+    drop(player_state_manager);
+    
+    ctx.say(format!("{} draws a card... **{}**", name, card.label())).await?;
+    Ok(())
+}
+
+#[derive(Debug, poise::ChoiceParameter)]
+pub enum FabulaSpend {
+    #[name = "Reroll a card"]
+    Reroll,
+    #[name = "Re-open last resolution"]
+    Reopen,
+}
+
+#[poise::command(slash_command, subcommands("fp_add", "fp_spend"))]
+pub async fn fp(
+    _ctx: poise::Context<'_, Data, Error>,
+) -> Result<(), Error> {
+    Ok(())
+}
+
+// Fabula Points are the GM's to hand out, players only spend them
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "add")]
+pub async fn fp_add(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "Number of Fabula Points to add"] amount: u32,
+    #[description = "Player to award (defaults to you)"] player: Option<serenity::User>,
+) -> Result<(), Error> {
+    let user = player.as_ref().unwrap_or_else(|| ctx.author());
+    let mut player_state_manager = lock_state(ctx).await;
+    
+    if !player_state_manager.guild_config(ctx.guild_id()).module_enabled(FabulaPoints.name()) {
+        ctx.say("Fabula Points are disabled on this server.").await?;
+        return Ok(());
+    }
+    
+    let total = player_state_manager.add_fabula_points(user.id, amount);
+    let name = player_state_manager.display_name(user.id, &user.name);
+    drop(player_state_manager);
+    
+    ctx.say(format!("{} gained {} Fabula Point(s) and now has {}.", name, amount, total)).await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, rename = "spend")]
+pub async fn fp_spend(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "What to spend the point on"] action: FabulaSpend,
+    #[description = "Card position to reroll"] position: Option<usize>,
+) -> Result<(), Error> {
+    let user_id = ctx.author().id;
+    let mut player_state_manager = lock_state(ctx).await;
+    
+    if !player_state_manager.guild_config(ctx.guild_id()).module_enabled(FabulaPoints.name()) {
+        ctx.say("Fabula Points are disabled on this server.").await?;
+        return Ok(());
+    }
+    if player_state_manager.fabula_points(user_id) == 0 {
+        ctx.say("You don't have any Fabula Points to spend.").await?;
+        return Ok(());
+    }
+    
+    let Some(player) = player_state_manager.get_player_state(user_id) else {
+        ctx.say("You haven't started a combat yet! Use /start_new_combat to begin.").await?;
+        return Ok(());
+    };
+    
+    // Apply the effect first so a failed action doesn't cost a point
+    let outcome = match action {
+        FabulaSpend::Reroll => {
+            let Some(position) = position.filter(|&p| p >= 1 && p <= player.hand.len()) else {
+                ctx.say("Please provide a valid card position to reroll.").await?;
+                return Ok(());
+            };
+            player.reroll_card(position - 1)
+                .map(|_| format!("Rerolled card {}.", position))
+        },
+        FabulaSpend::Reopen => {
+            player.reopen_last_resolution()
+                .map(|_| "Your last resolution has been re-opened.".to_string())
+        }
+    };
+    let outcome = match outcome {
+        Ok(outcome) => outcome,
+        Err(e) => {
+            ctx.say(e).await?;
+            return Ok(());
+        }
+    };
+    
+    let hand = player.hand.clone();
+    let remaining = player_state_manager.spend_fabula_point(user_id)?;
+    let affinity = player_state_manager.affinity(user_id);
+    let delivery = player_state_manager.preferences(user_id).hand_delivery;
+    drop(player_state_manager);
+    
+    let spent = format!("Spent a Fabula Point ({} left). {}", remaining, outcome);
+    let public = format!("{} Your hand is in your DMs.", spent);
+    deliver_hand(ctx, delivery, &public, format!("{}\n{}", spent, format_hand_display(&hand, affinity.as_ref()))).await
+}
+
+#[poise::command(slash_command, guild_only, subcommands("table_join", "table_leave"))]
+pub async fn table(
+    _ctx: poise::Context<'_, Data, Error>,
+) -> Result<(), Error> {
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, rename = "join")]
+pub async fn table_join(
+    ctx: poise::Context<'_, Data, Error>,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+    let user_id = ctx.author().id;
+    
+    let mut player_state_manager = lock_state(ctx).await;
+    let members = &mut player_state_manager.guild_config_mut(guild_id).table_members;
+    let joined = !members.contains(&user_id);
+    if joined {
+        members.push(user_id);
+    }
+    let name = player_state_manager.display_name(user_id, &ctx.author().name);
+    drop(player_state_manager);
+    
+    if joined {
+        ctx.say(format!("{} joined the table.", name)).await?;
+    } else {
+        ctx.send(|b| b.content("You're already at this table.").ephemeral(true)).await?;
+    }
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, rename = "leave")]
+pub async fn table_leave(
+    ctx: poise::Context<'_, Data, Error>,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+    let user_id = ctx.author().id;
+    
+    let mut player_state_manager = lock_state(ctx).await;
+    player_state_manager.guild_config_mut(guild_id).table_members.retain(|&m| m != user_id);
+    let name = player_state_manager.display_name(user_id, &ctx.author().name);
+    drop(player_state_manager);
+    
+    ctx.say(format!("{} left the table.", name)).await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, subcommands("ask_gm_undo", "ask_gm_extra_action"))]
+pub async fn ask_gm(
+    _ctx: poise::Context<'_, Data, Error>,
+) -> Result<(), Error> {
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, rename = "undo")]
+pub async fn ask_gm_undo(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "Why, for the GM"] note: Option<String>,
+) -> Result<(), Error> {
+    request_approval(ctx, ApprovalAction::ReopenResolution, note).await
+}
+
+#[poise::command(slash_command, guild_only, rename = "extra_action")]
+pub async fn ask_gm_extra_action(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "Kind of action you'd like another of"] kind: ActionKind,
+    #[description = "Why, for the GM"] note: Option<String>,
+) -> Result<(), Error> {
+    request_approval(ctx, ApprovalAction::ExtraAction(kind), note).await
+}
+
+// Queue a request and post it to the GM channel. The answer comes back through on_event.
+async fn request_approval(ctx: poise::Context<'_, Data, Error>, action: ApprovalAction, note: Option<String>) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+    let user_id = ctx.author().id;
+    let mut player_state_manager = lock_state(ctx).await;
+    let Some(gm_channel) = player_state_manager.guild_config(Some(guild_id)).gm_channel else {
+        ctx.send(|b| b.content("This server has no GM channel yet. A GM can set one with /configure_gm_channel.").ephemeral(true)).await?;
+        return Ok(());
+    };
+    let Some(player) = player_state_manager.players.get(&user_id).filter(|p| !p.orphaned) else {
+        ctx.say("You haven't started a combat yet! Use /start_new_combat to begin.").await?;
+        return Ok(());
+    };
+    if let Err(e) = action.check(player) {
+        ctx.send(|b| b.content(e).ephemeral(true)).await?;
+        return Ok(());
+    }
+    
+    let request = ApprovalRequest {
+        id: ctx.id(),
+        guild_id,
+        requester: user_id,
+        action,
+        note,
+        requested_at: events::unix_now(),
+        reply_channel: ctx.channel_id(),
+        gm_channel,
+        gm_message: None,
+    };
+    if let Err(e) = player_state_manager.queue_approval(request.clone()) {
+        ctx.send(|b| b.content(e).ephemeral(true)).await?;
+        return Ok(());
+    }
+    let name = player_state_manager.display_name(user_id, &ctx.author().name);
+    drop(player_state_manager);
+    
+    let posted = gm_channel.send_message(ctx, |m| m
+        .content(request.describe(&name))
+        .components(|c| c.create_action_row(|r| r
+            .create_button(|b| b.custom_id(approvals::button_id(request.id, true)).label("Approve").style(serenity::ButtonStyle::Success))
+            .create_button(|b| b.custom_id(approvals::button_id(request.id, false)).label("Deny").style(serenity::ButtonStyle::Danger))))
+    ).await;
+    match posted {
+        Ok(message) => {
+            lock_state(ctx).await.set_approval_message(request.id, message.id);
+            ctx.send(|b| b.content(format!("Asked the GM to let you {}. Their answer will show up here.", request.action.describe())).ephemeral(true)).await?;
+        },
+        Err(e) => {
+            lock_state(ctx).await.take_approval(request.id);
+            ctx.send(|b| b.content(format!("Couldn't reach the GM channel, nothing was sent: {}", e)).ephemeral(true)).await?;
+        }
+    }
+    Ok(())
+}
+
+#[poise::command(slash_command, subcommands("character_rename", "character_affinity"))]
+pub async fn character(
+    _ctx: poise::Context<'_, Data, Error>,
+) -> Result<(), Error> {
+    Ok(())
+}
+
+#[poise::command(slash_command, rename = "rename")]
+pub async fn character_rename(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "Name shown in public announcements"] name: String,
+) -> Result<(), Error> {
+    let mut player_state_manager = lock_state(ctx).await;
+    let result = player_state_manager.rename_character(ctx.author().id, ctx.guild_id(), &name);
+    drop(player_state_manager);
+    
+    match result {
+        Ok(name) => ctx.say(format!("Your character will now be announced as **{}**.", name)).await?,
+        Err(e) => ctx.send(|b| b.content(e).ephemeral(true)).await?,
+    };
+    Ok(())
+}
+
+#[poise::command(slash_command, rename = "affinity")]
+pub async fn character_affinity(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "An element, e.g. Fire, Ice or Bolt (leave empty to clear)"] element: Option<String>,
+) -> Result<(), Error> {
+    let element = match element.as_deref().map(parse::element).transpose() {
+        Ok(element) => element,
+        Err(e) => {
+            ctx.send(|b| b.content(format!("Unknown element, {}.", e)).ephemeral(true)).await?;
+            return Ok(());
+        }
+    };
+    
+    let mut player_state_manager = lock_state(ctx).await;
+    player_state_manager.set_affinity(ctx.author().id, element.clone());
+    let bonus = player_state_manager.guild_config(ctx.guild_id()).feature_enabled("affinity_bonus");
+    drop(player_state_manager);
+    
+    let message = match element {
+        Some(element) if bonus => format!("Your character is now attuned to {}. {} cards are marked {} in your hand, and {} resolutions deal +{} damage here.",
+            element.name(), element.name(), AFFINITY_MARK, element.name(), AFFINITY_BONUS_DAMAGE),
+        Some(element) => format!("Your character is now attuned to {}. {} cards are marked {} in your hand.",
+            element.name(), element.name(), AFFINITY_MARK),
+        None => "Your character no longer has an elemental affinity.".to_string(),
+    };
+    ctx.send(|b| b.content(message).ephemeral(true)).await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, subcommands("joker_name", "joker_list"))]
+pub async fn joker(
+    _ctx: poise::Context<'_, Data, Error>,
+) -> Result<(), Error> {
+    Ok(())
+}
+
+#[poise::command(slash_command, rename = "name")]
+pub async fn joker_name(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "Which of your jokers to name"] slot: JokerSlot,
+    #[description = "Name shown whenever this joker turns up"] name: String,
+) -> Result<(), Error> {
+    let mut player_state_manager = lock_state(ctx).await;
+    let result = player_state_manager.name_joker(ctx.author().id, slot, &name);
+    drop(player_state_manager);
+    
+    match result {
+        Ok(name) => ctx.say(format!("Your {} joker is now **{}**.", slot.label(), name)).await?,
+        Err(e) => ctx.send(|b| b.content(e).ephemeral(true)).await?,
+    };
+    Ok(())
+}
+
+#[poise::command(slash_command, rename = "list")]
+pub async fn joker_list(
+    ctx: poise::Context<'_, Data, Error>,
+) -> Result<(), Error> {
+    let user_id = ctx.author().id;
+    let player_state_manager = lock_state(ctx).await;
+    let profile = player_state_manager.characters.get(&user_id).cloned().unwrap_or_default();
+    let table_jokers = player_state_manager.guild_config(ctx.guild_id()).deck_template.jokers as usize;
+    
+    let mut message = String::from("**Your jokers**\n");
+    for slot in JokerSlot::ALL {
+        let name = profile.joker(slot).and_then(|joker| joker.name.as_deref());
+        let appearances = player_state_manager.joker_appearances(user_id, slot);
+        // Slots this table's deck doesn't use only show up once they've been named or drawn
+        if slot.index() >= table_jokers && name.is_none() && appearances == 0 {
+            continue;
+        }
+        let name = name.unwrap_or("unnamed");
+        message.push_str(&format!("{}: {} (drawn {} time(s) this campaign)\n", slot.label(), name, appearances));
+    }
+    drop(player_state_manager);
+    
+    ctx.say(message).await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD", subcommands("modifiers_add", "modifiers_remove"))]
+pub async fn modifiers(
+    _ctx: poise::Context<'_, Data, Error>,
+) -> Result<(), Error> {
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "add")]
+pub async fn modifiers_add(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "Player whose hands it changes"] player: serenity::User,
+    #[description = "What grants it, e.g. Lucky Charm"] source: String,
+    #[description = "Floor raises lower values to it, Ceiling lowers higher values to it"] kind: ModifierKind,
+    #[description = "The value hands count as"]
+    #[min = 1]
+    #[max = 21]
+    value: u8,
+) -> Result<(), Error> {
+    // The source shows up in public hand listings, so it gets the same cleanup as names
+    let source = match sanitize_display_name(&source) {
+        Ok(source) => source,
+        Err(e) => {
+            ctx.send(|b| b.content(format!("{}.", e)).ephemeral(true)).await?;
+            return Ok(());
+        }
+    };
+    let mut player_state_manager = lock_state(ctx).await;
+    let name = player_state_manager.display_name(player.id, &player.name);
+    
+    let Some(state) = player_state_manager.get_player_state(player.id).filter(|s| s.guild_id == ctx.guild_id()) else {
+        drop(player_state_manager);
+        ctx.say(format!("{} isn't in a combat at this table right now.", name)).await?;
+        return Ok(());
+    };
+    if state.modifiers.iter().any(|m| m.source.eq_ignore_ascii_case(&source)) {
+        drop(player_state_manager);
+        ctx.say(format!("{} already has a modifier from {}. Remove it first to change it.", name, source)).await?;
+        return Ok(());
+    }
+    let modifier = ValueModifier { source, kind, value };
+    let description = modifier.describe();
+    state.modifiers.push(modifier);
+    drop(player_state_manager);
+    
+    ctx.say(format!("{}'s hands now count with {}.", name, description)).await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "remove")]
+pub async fn modifiers_remove(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "Player whose modifier is removed"] player: serenity::User,
+    #[description = "What granted it, as it was added"] source: String,
+) -> Result<(), Error> {
+    let mut player_state_manager = lock_state(ctx).await;
+    let name = player_state_manager.display_name(player.id, &player.name);
+    
+    let Some(state) = player_state_manager.get_player_state(player.id).filter(|s| s.guild_id == ctx.guild_id()) else {
+        drop(player_state_manager);
+        ctx.say(format!("{} isn't in a combat at this table right now.", name)).await?;
+        return Ok(());
+    };
+    let before = state.modifiers.len();
+    state.modifiers.retain(|m| !m.source.eq_ignore_ascii_case(source.trim()));
+    let removed = state.modifiers.len() < before;
+    drop(player_state_manager);
+    
+    if removed {
+        ctx.say(format!("Removed {}'s modifier from {}.", name, source.trim())).await?;
+    } else {
+        ctx.say(format!("{} has no modifier from {}.", name, source.trim())).await?;
+    }
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD")]
+pub async fn configure_module(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "Module name, e.g. fabula_points"] module: String,
+    #[description = "Whether the module is enabled for this server"] enabled: bool,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+    
+    let Some(module) = skills::find_module(&module) else {
+        let available = skills::SKILL_MODULES.iter()
+            .map(|m| format!("`{}` - {}", m.name(), m.description()))
+            .collect::<Vec<_>>()
+            .join("\n");
+        ctx.say(format!("Unknown module. Available modules:\n{}", available)).await?;
+        return Ok(());
+    };
+    
+    let mut player_state_manager = lock_state(ctx).await;
+    player_state_manager.guild_config_mut(guild_id).set_module_enabled(module.name(), enabled);
+    drop(player_state_manager);
+    
+    let status = if enabled { "enabled" } else { "disabled" };
+    ctx.say(format!("Module `{}` is now {}.", module.name(), status)).await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD")]
+pub async fn configure_feature(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "Experimental feature name"] feature: String,
+    #[description = "Whether the feature is enabled for this server"] enabled: bool,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+    
+    let Some(feature) = config::find_feature(&feature) else {
+        let available = if config::FEATURES.is_empty() {
+            "There are no experimental features right now.".to_string()
+        } else {
+            config::FEATURES.iter()
+                .map(|f| format!("`{}` - {}", f.name, f.description))
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        ctx.say(format!("Unknown feature. {}", available)).await?;
+        return Ok(());
+    };
+    
+    let mut player_state_manager = lock_state(ctx).await;
+    player_state_manager.guild_config_mut(guild_id).set_feature_enabled(feature.name, enabled);
+    drop(player_state_manager);
+    
+    let status = if enabled { "enabled" } else { "disabled" };
+    ctx.say(format!("Feature `{}` is now {} for this server.", feature.name, status)).await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD")]
+pub async fn configure_theme(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "Look for hand panels on this server"] theme: Theme,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+    
+    let mut player_state_manager = lock_state(ctx).await;
+    player_state_manager.guild_config_mut(guild_id).theme = theme;
+    drop(player_state_manager);
+    
+    ctx.send(|b| b.embed(|e| e
+        .title(format!("Theme set to {:?}", theme))
+        .description(theme.format_hand(&Deck::new().cards[..5], None))
+        .color(theme.embed_color())
+    )).await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD")]
+pub async fn configure_effect(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "Hand whose effect to change"] hand: HandKind,
+    #[description = "Damage dealt, e.g. value+5 (names: value, first, second, low, high, triple, pair)"] damage_formula: Option<String>,
+    #[description = "Healing done, e.g. value*2"] heal_formula: Option<String>,
+    #[description = "Replacement text, with {damage}, {heal}, {value} and {elements}"] text: Option<String>,
+    #[description = "MP the hand costs to resolve"] mp_cost: Option<u32>,
+    #[description = "Go back to the rules as written for this hand"] reset: Option<bool>,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+    if hand == HandKind::Homebrew {
+        ctx.send(|b| b.content("Homebrew hands are set up with /configure_homebrew_hand.").ephemeral(true)).await?;
+        return Ok(());
+    }
+    
+    for formula in damage_formula.iter().chain(heal_formula.iter()) {
+        if let Err(e) = validate_formula(formula, hand) {
+            ctx.send(|b| b.content(format!("`{}`: {}", formula, e)).ephemeral(true)).await?;
+            return Ok(());
+        }
+    }
+    
+    let mut player_state_manager = lock_state(ctx).await;
+    let config = player_state_manager.guild_config_mut(guild_id);
+    if reset.unwrap_or(false) {
+        config.effect_overrides.remove(hand.key());
+        drop(player_state_manager);
+        ctx.say(format!("{} uses the rules as written again.", hand.label())).await?;
+        return Ok(());
+    }
+    
+    let custom = config.effect_overrides.entry(hand.key().to_string()).or_default();
+    if damage_formula.is_some() {
+        custom.damage_formula = damage_formula;
+    }
+    if heal_formula.is_some() {
+        custom.heal_formula = heal_formula;
+    }
+    if text.is_some() {
+        custom.text = text;
+    }
+    if mp_cost.is_some() {
+        custom.mp_cost = mp_cost;
+    }
+    let summary = format!("Damage: {}\nHealing: {}\nText: {}\nMP cost: {}",
+        custom.damage_formula.as_deref().unwrap_or("as written"),
+        custom.heal_formula.as_deref().unwrap_or("as written"),
+        custom.text.as_deref().unwrap_or("as written"),
+        custom.mp_cost.map_or("as written".to_string(), |cost| cost.to_string()));
+    if custom.is_empty() {
+        config.effect_overrides.remove(hand.key());
+    }
+    drop(player_state_manager);
+    
+    ctx.say(format!("**{}** effect for this server:\n{}", hand.label(), summary)).await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD")]
+pub async fn configure_homebrew_hand(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "Name of the hand, e.g. Ice Storm"] name: String,
+    #[description = "Cards it takes: \"N of a kind\", \"straight of N\" or \"flush of N\""] pattern: Option<String>,
+    #[description = "MP the hand costs to resolve"] mp_cost: Option<u32>,
+    #[description = "Damage dealt, e.g. value+5 (names: value, low, high)"] damage_formula: Option<String>,
+    #[description = "Healing done, e.g. high*2"] heal_formula: Option<String>,
+    #[description = "Effect text, with {damage}, {heal}, {value} and {elements}"] text: Option<String>,
+    #[description = "Remove this hand from the server"] remove: Option<bool>,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+    let name = match sanitize_display_name(&name) {
+        Ok(name) => name,
+        Err(e) => {
+            ctx.send(|b| b.content(e).ephemeral(true)).await?;
+            return Ok(());
+        }
+    };
+    let pattern = match pattern.as_deref().map(parse::hand_pattern).transpose() {
+        Ok(pattern) => pattern,
+        Err(e) => {
+            ctx.send(|b| b.content(format!("Couldn't read that pattern: {}", e)).ephemeral(true)).await?;
+            return Ok(());
+        }
+    };
+    for formula in damage_formula.iter().chain(heal_formula.iter()) {
+        if let Err(e) = validate_formula(formula, HandKind::Homebrew) {
+            ctx.send(|b| b.content(format!("`{}`: {}", formula, e)).ephemeral(true)).await?;
+            return Ok(());
+        }
+    }
+    
+    let mut player_state_manager = lock_state(ctx).await;
+    let config = player_state_manager.guild_config_mut(guild_id);
+    let existing = config.homebrew_hands.iter().position(|homebrew| homebrew.name.eq_ignore_ascii_case(&name));
+    if remove.unwrap_or(false) {
+        let reply = match existing {
+            Some(index) => format!("**{}** is no longer a hand on this server.", config.homebrew_hands.remove(index).name),
+            None => format!("There's no homebrew hand called {}.", name),
+        };
+        drop(player_state_manager);
+        ctx.say(reply).await?;
+        return Ok(());
+    }
+    
+    let problem = if [HandKind::TripleThreat, HandKind::MatchedEdge, HandKind::Jackpot, HandKind::DoubleTrouble, HandKind::MagicFlush, HandKind::BlindingFlush, HandKind::FullStatus]
+        .iter()
+        .any(|kind| kind.label().eq_ignore_ascii_case(&name))
+    {
+        Some(format!("{} is already a hand, use /configure_effect to change it.", name))
+    } else if existing.is_none() && pattern.is_none() {
+        Some("A new homebrew hand needs a pattern, e.g. \"3 of a kind\".".to_string())
+    } else if existing.is_none() && config.homebrew_hands.len() >= MAX_HOMEBREW_HANDS {
+        Some(format!("A server can have at most {} homebrew hands.", MAX_HOMEBREW_HANDS))
+    } else {
+        None
+    };
+    if let Some(problem) = problem {
+        drop(player_state_manager);
+        ctx.send(|b| b.content(problem).ephemeral(true)).await?;
+        return Ok(());
+    }
+    
+    let homebrew = match existing {
+        Some(index) => &mut config.homebrew_hands[index],
+        None => {
+            config.homebrew_hands.push(HomebrewHand {
+                name,
+                pattern: pattern.expect("checked above"),
+                effect: Default::default(),
+            });
+            config.homebrew_hands.last_mut().expect("just pushed")
+        }
+    };
+    if let Some(pattern) = pattern {
+        homebrew.pattern = pattern;
+    }
+    if damage_formula.is_some() {
+        homebrew.effect.damage_formula = damage_formula;
+    }
+    if heal_formula.is_some() {
+        homebrew.effect.heal_formula = heal_formula;
+    }
+    if text.is_some() {
+        homebrew.effect.text = text;
+    }
+    if mp_cost.is_some() {
+        homebrew.effect.mp_cost = mp_cost;
+    }
+    let summary = homebrew.describe();
+    drop(player_state_manager);
+    
+    ctx.say(format!("Homebrew hand for this server:\n{}", summary)).await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD")]
+pub async fn configure_power(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "How strong card effects are at this table"] level: PowerLevel,
+    #[description = "Custom: card values are multiplied by this"] value_multiplier: Option<i64>,
+    #[description = "Custom: Triple Threat healing bonus"] triple_threat_heal: Option<i64>,
+    #[description = "Custom: Triple Threat damage bonus"] triple_threat_damage: Option<i64>,
+    #[description = "Custom: Matched Edge damage bonus"] matched_edge_damage: Option<i64>,
+    #[description = "Custom: Double Trouble damage bonus"] double_trouble_damage: Option<i64>,
+    #[description = "Custom: Jackpot HP and MP recovery"] jackpot_recovery: Option<i64>,
+    #[description = "Custom: Magic Flush damage bonus"] magic_flush_damage: Option<i64>,
+    #[description = "Custom: Blinding Flush damage bonus"] blinding_flush_damage: Option<i64>,
+    #[description = "Custom: Full Status healing bonus"] full_status_heal: Option<i64>,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+    
+    let mut player_state_manager = lock_state(ctx).await;
+    let config = player_state_manager.guild_config_mut(guild_id);
+    config.power_level = level;
+    if level == PowerLevel::Custom {
+        let custom = &mut config.custom_power;
+        let fields = [
+            (&mut custom.value_multiplier, value_multiplier),
+            (&mut custom.triple_threat_heal, triple_threat_heal),
+            (&mut custom.triple_threat_damage, triple_threat_damage),
+            (&mut custom.matched_edge_damage, matched_edge_damage),
+            (&mut custom.double_trouble_damage, double_trouble_damage),
+            (&mut custom.jackpot_recovery, jackpot_recovery),
+            (&mut custom.magic_flush_damage, magic_flush_damage),
+            (&mut custom.blinding_flush_damage, blinding_flush_damage),
+            (&mut custom.full_status_heal, full_status_heal),
+        ];
+        for (field, value) in fields {
+            if let Some(value) = value {
+                *field = value.clamp(0, PowerCoefficients::MAX);
+            }
+        }
+    }
+    let formulas = config.power().describe().join("\n");
+    drop(player_state_manager);
+    
+    ctx.say(format!("Power level set to {:?}:\n{}", level, formulas)).await?;
+    Ok(())
+}
+
+#[poise::command(slash_command)]
+pub async fn rules(
+    ctx: poise::Context<'_, Data, Error>,
+) -> Result<(), Error> {
+    let player_state_manager = lock_state(ctx).await;
+    let config = player_state_manager.guild_config(ctx.guild_id());
+    drop(player_state_manager);
+    
+    let mut message = format!("**Power level: {:?}**\n{}\n", config.power_level, config.power().describe().join("\n"));
+    let overrides: Vec<String> = [HandKind::TripleThreat, HandKind::MatchedEdge, HandKind::DoubleTrouble, HandKind::Jackpot, HandKind::MagicFlush, HandKind::BlindingFlush, HandKind::FullStatus]
+        .iter()
+        .filter_map(|kind| {
+            let custom = config.effect_override(*kind)?;
+            let mut parts = Vec::new();
+            if let Some(formula) = &custom.damage_formula {
+                parts.push(format!("damage `{}`", formula));
+            }
+            if let Some(formula) = &custom.heal_formula {
+                parts.push(format!("healing `{}`", formula));
+            }
+            if custom.text.is_some() {
+                parts.push("custom text".to_string());
+            }
+            if let Some(cost) = custom.mp_cost {
+                parts.push(format!("costs {} MP", cost));
+            }
+            Some(format!("{}: {}", kind.label(), parts.join(", ")))
+        })
+        .collect();
+    if !overrides.is_empty() {
+        message.push_str(&format!("\n**House rules**\n{}\n", overrides.join("\n")));
+    }
+    if !config.homebrew_hands.is_empty() {
+        let homebrew: Vec<String> = config.homebrew_hands.iter().map(HomebrewHand::describe).collect();
+        message.push_str(&format!("\n**Homebrew hands**\n{}\n", homebrew.join("\n")));
+    }
+    if config.deck_template != DeckTemplate::default() {
+        message.push_str(&format!("\n**Deck**: {}\n", config.deck_template.describe()));
+    }
+    
+    ctx.say(message).await?;
+    Ok(())
+}
+
+#[derive(Debug, poise::ChoiceParameter)]
+pub enum DeckMigration {
+    #[name = "Finish current combats on the old deck"]
+    Keep,
+    #[name = "Rebuild decks in current combats"]
+    Rebuild,
+}
+
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD")]
+pub async fn configure_deck(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "Number of jokers in the deck, 0 to 4"]
+    #[min = 0]
+    #[max = 4]
+    jokers: Option<u8>,
+    #[description = "Lowest card value (default 1)"]
+    #[min = 1]
+    #[max = 21]
+    low: Option<u8>,
+    #[description = "Highest card value (default 7)"]
+    #[min = 1]
+    #[max = 21]
+    high: Option<u8>,
+    #[description = "Copies of each numbered card (default 1)"]
+    #[min = 1]
+    #[max = 4]
+    copies: Option<u8>,
+    #[description = "Suits in the deck, e.g. fire, ice, bolt, dark (default Fire, Ice, Earth and Air)"] suits: Option<String>,
+    #[description = "How to treat combats in progress (leave empty for a dry run)"] migration: Option<DeckMigration>,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+    let suits = match suits.as_deref().map(parse::elements).transpose() {
+        Ok(suits) => suits,
+        Err(e) => {
+            ctx.send(|b| b.content(format!("I couldn't read the suits, {}.", e)).ephemeral(true)).await?;
+            return Ok(());
+        }
+    };
+    
+    let mut player_state_manager = lock_state(ctx).await;
+    // Anything left out stays as the guild has it
+    let config = player_state_manager.guild_config(Some(guild_id));
+    let hand_size = config.hand_size();
+    let current = config.deck_template;
+    let template = DeckTemplate {
+        jokers: jokers.unwrap_or(current.jokers),
+        low: low.unwrap_or(current.low),
+        high: high.unwrap_or(current.high),
+        copies: copies.unwrap_or(current.copies),
+        elements: suits.unwrap_or(current.elements),
+        kind: current.kind,
+    };
+    if let Err(e) = template.check(hand_size) {
+        drop(player_state_manager);
+        ctx.send(|b| b.content(format!("That deck won't work, {}.", e)).ephemeral(true)).await?;
+        return Ok(());
+    }
+    let affected = player_state_manager.combats_outside_template(guild_id, &template);
+    
+    let Some(migration) = migration else {
+        drop(player_state_manager);
+        ctx.say(format!("Dry run: the new deck is {}. {} active combat(s) contain cards not in it. Run again with a migration choice to apply it.", template.describe(), affected)).await?;
+        return Ok(());
+    };
+    
+    let rebuild = matches!(migration, DeckMigration::Rebuild);
+    let result = player_state_manager.apply_deck_template(guild_id, template, rebuild);
+    drop(player_state_manager);
+    
+    match result {
+        Ok(rebuilt) if rebuild => ctx.say(format!("Deck updated. Rebuilt {} active combat(s) with the new deck.", rebuilt)).await?,
+        Ok(_) => ctx.say(format!("Deck updated for new combats. {} active combat(s) will finish on the old deck.", affected)).await?,
+        Err(e) => ctx.say(format!("Couldn't apply the new deck, nothing was changed: {}", e)).await?,
+    };
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD")]
+pub async fn configure_shuffle(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "How decks are shuffled"] strategy: ShuffleStrategy,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+    
+    let mut player_state_manager = lock_state(ctx).await;
+    player_state_manager.guild_config_mut(guild_id).shuffle = strategy;
+    drop(player_state_manager);
+    
+    ctx.say(format!("New combats will use {:?} shuffling: {}. Combats in progress keep their shuffle.", strategy, strategy.description())).await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD")]
+pub async fn configure_table_channel(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "Channel the table plays in (defaults to this one)"] channel: Option<serenity::Channel>,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+    let channel = channel.map(|c| c.id()).unwrap_or_else(|| ctx.channel_id());
+    
+    let mut player_state_manager = lock_state(ctx).await;
+    player_state_manager.guild_config_mut(guild_id).table_channel = Some(channel);
+    drop(player_state_manager);
+    
+    ctx.say(format!("Table announcements will go to <#{}>.", channel)).await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD")]
+pub async fn configure_gm_channel(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "Channel approval requests are posted in (defaults to this one)"] channel: Option<serenity::Channel>,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+    let channel = channel.map(|c| c.id()).unwrap_or_else(|| ctx.channel_id());
+    
+    let mut player_state_manager = lock_state(ctx).await;
+    player_state_manager.guild_config_mut(guild_id).gm_channel = Some(channel);
+    drop(player_state_manager);
+    
+    ctx.say(format!("Requests for GM approval will be posted in <#{}>.", channel)).await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD")]
+pub async fn configure_mulligans(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "Mulligans each player gets per combat (leave empty for no limit)"] limit: Option<u32>,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+    
+    let mut player_state_manager = lock_state(ctx).await;
+    player_state_manager.guild_config_mut(guild_id).mulligan_limit = limit;
+    drop(player_state_manager);
+    
+    let message = match limit {
+        Some(limit) => format!("Players now get {} mulligan(s) per combat.", limit),
+        None => "Mulligans are no longer limited per combat.".to_string(),
+    };
+    ctx.say(message).await?;
+    Ok(())
+}
+
+// Keeps hands readable at a glance; whether the table's deck can deal them is checked separately
+const MAX_CONFIGURABLE_HAND_SIZE: usize = 10;
+// So every card in a hand still fits in one select menu
+const MAX_CONFIGURABLE_HAND_LIMIT: usize = MAX_MENU_OPTIONS;
+
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD")]
+pub async fn configure_actions(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "Draw actions per turn"] draws: u32,
+    #[description = "Resolutions per turn"] resolutions: u32,
+    #[description = "Reactions per turn"] reactions: u32,
+    #[description = "Cards a hand is dealt and refilled to in new combats (default 5)"] hand_size: Option<usize>,
+    #[description = "Most cards a hand can hold after extra draws (default 7)"] max_hand_size: Option<usize>,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+    if hand_size.is_some_and(|size| !(1..=MAX_CONFIGURABLE_HAND_SIZE).contains(&size)) {
+        ctx.send(|b| b.content(format!("Hand size must be between 1 and {}.", MAX_CONFIGURABLE_HAND_SIZE)).ephemeral(true)).await?;
+        return Ok(());
+    }
+    if max_hand_size.is_some_and(|max| max > MAX_CONFIGURABLE_HAND_LIMIT) {
+        ctx.send(|b| b.content(format!("The hand limit can be at most {}.", MAX_CONFIGURABLE_HAND_LIMIT)).ephemeral(true)).await?;
+        return Ok(());
+    }
+    
+    let mut player_state_manager = lock_state(ctx).await;
+    let config = player_state_manager.guild_config_mut(guild_id);
+    let size = hand_size.unwrap_or(config.hand_size());
+    if max_hand_size.is_some_and(|max| max < size) {
+        drop(player_state_manager);
+        ctx.send(|b| b.content(format!("The hand limit can't be below the hand size of {}.", size)).ephemeral(true)).await?;
+        return Ok(());
+    }
+    if let Err(e) = config.deck_template.check(size) {
+        drop(player_state_manager);
+        ctx.send(|b| b.content(format!("The table's deck is too small for that hand size, {}. Change it with /configure_deck first.", e)).ephemeral(true)).await?;
+        return Ok(());
+    }
+    let budget = &mut config.action_budget;
+    budget.draws = draws;
+    budget.resolutions = resolutions;
+    budget.reactions = reactions;
+    if hand_size.is_some() {
+        config.hand_size = hand_size;
+    }
+    if max_hand_size.is_some() {
+        config.max_hand_size = max_hand_size;
+    }
+    let (hand_size, max_hand_size) = (config.hand_size(), config.max_hand_size());
+    drop(player_state_manager);
+    
+    ctx.say(format!("Each turn players now get {} draw, {} resolution and {} reaction action(s). Hands hold {} cards, up to {} with extra draws; new hand sizes apply from the next combat.",
+        draws, resolutions, reactions, hand_size, max_hand_size)).await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD")]
+pub async fn setup_status(
+    ctx: poise::Context<'_, Data, Error>,
+) -> Result<(), Error> {
+    let player_state_manager = lock_state(ctx).await;
+    let steps = player_state_manager.guild_config(ctx.guild_id()).setup_checklist();
+    drop(player_state_manager);
+    
+    let done = steps.iter().filter(|s| s.done).count();
+    let mut checklist = format!("**Setup for this server** ({} of {} done)\n", done, steps.len());
+    for step in &steps {
+        checklist.push_str(&format!("{} **{}**: {}\n", if step.done { "✅" } else { "⬜" }, step.name, step.detail));
+    }
+    checklist.push_str("Pick a step below to see how to set it up.");
+    
+    let step_ids: Vec<String> = (0..steps.len()).map(|i| format!("{}_setup_{}", ctx.id(), i)).collect();
+    let reply = ctx.send(|b| b
+        .content(checklist)
+        .ephemeral(true)
+        .components(|c| {
+            // Discord fits at most five buttons in a row
+            for (row, chunk) in steps.chunks(5).enumerate() {
+                c.create_action_row(|r| {
+                    for (i, step) in chunk.iter().enumerate() {
+                        let style = if step.done { serenity::ButtonStyle::Secondary } else { serenity::ButtonStyle::Primary };
+                        r.create_button(|b| b.custom_id(&step_ids[row * 5 + i]).label(step.name).style(style));
+                    }
+                    r
+                });
+            }
+            c
+        })
+    ).await?;
+    let message_id = reply.message().await?.id;
+    
+    while let Some(mci) = serenity::CollectComponentInteraction::new(ctx.serenity_context())
+        .author_id(ctx.author().id)
+        .message_id(message_id)
+        .timeout(std::time::Duration::from_secs(300))
+        .await
+    {
+        let Some(step) = step_ids.iter().position(|id| *id == mci.data.custom_id).map(|i| &steps[i]) else {
+            continue;
+        };
+        mci.create_interaction_response(ctx.serenity_context(), |r| r
+            .kind(serenity::InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|d| d.content(format!("**{}**\n{}", step.name, step.how_to)).ephemeral(true))
+        ).await?;
+    }
+    Ok(())
+}
+
+// Discord's default upload limit for bots
+const MAX_EXPORT_BYTES: usize = 8 * 1024 * 1024;
+
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD", subcommands("gm_deal_all", "gm_export_campaign", "gm_grant_action", "gm_burn", "gm_reshuffle", "gm_history", "gm_affinity", "gm_targets", "gm_reassign_combat", "gm_archive_combat", "gm_party_combos", "gm_approvals", "gm_api_key", "gm_end_turn", "gm_reset_deck"))]
+pub async fn gm(
+    _ctx: poise::Context<'_, Data, Error>,
+) -> Result<(), Error> {
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "export_campaign")]
+pub async fn gm_export_campaign(
+    ctx: poise::Context<'_, Data, Error>,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+    if load::is_degraded() {
+        ctx.send(|b| b.content("The bot is under heavy load right now, please try the export again later.").ephemeral(true)).await?;
+        return Ok(());
+    }
+    let export = lock_state(ctx).await.export_campaign(guild_id);
+    let player_count = export.players.len();
+    
+    // Serialize off the async runtime, large campaigns can take a while
+    let json = tokio::task::spawn_blocking(move || serde_json::to_vec_pretty(&export)).await??;
+    if json.len() > MAX_EXPORT_BYTES {
+        ctx.say(format!("The export is {} bytes, which is over Discord's upload limit.", json.len())).await?;
+        return Ok(());
+    }
+    
+    let filename = format!("campaign_{}.json", guild_id);
+    ctx.send(|b| b
+        .content(format!("Exported {} player state(s).", player_count))
+        .attachment(serenity::AttachmentType::Bytes { data: json.into(), filename })
+        .ephemeral(true)
+    ).await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "api_key", subcommands("gm_api_key_create", "gm_api_key_revoke", "gm_api_key_list"))]
+pub async fn gm_api_key(
+    _ctx: poise::Context<'_, Data, Error>,
+) -> Result<(), Error> {
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "create")]
+pub async fn gm_api_key_create(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "What the key is for, e.g. the dashboard"] label: String,
+    #[description = "Requests it may make per minute (default 60)"]
+    #[min = 1]
+    #[max = 600]
+    requests_per_minute: Option<u32>,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+    let label = match sanitize_display_name(&label) {
+        Ok(label) => label,
+        Err(e) => {
+            ctx.send(|b| b.content(format!("{}.", e)).ephemeral(true)).await?;
+            return Ok(());
+        }
+    };
+    let requests_per_minute = requests_per_minute.unwrap_or(DEFAULT_RATE_LIMIT).clamp(1, MAX_RATE_LIMIT);
+    
+    let mut player_state_manager = lock_state(ctx).await;
+    let config = player_state_manager.guild_config_mut(guild_id);
+    if config.api_keys.len() >= MAX_API_KEYS {
+        drop(player_state_manager);
+        ctx.send(|b| b.content(format!("This server already has {} API keys. Revoke one with /gm api_key revoke first.", MAX_API_KEYS)).ephemeral(true)).await?;
+        return Ok(());
+    }
+    let (key, token) = ApiKey::generate(label, requests_per_minute);
+    let id = key.id.clone();
+    config.api_keys.push(key);
+    drop(player_state_manager);
+    
+    // Only the GM who made it ever sees the token
+    ctx.send(|b| b.content(format!("Created API key `{}` ({} requests per minute). It only reads this server's data. \
+        Copy it now, it won't be shown again:\n`{}`", id, requests_per_minute, token)).ephemeral(true)).await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "revoke")]
+pub async fn gm_api_key_revoke(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "The key's ID, as /gm api_key list shows it"] id: String,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+    let id = id.trim().to_lowercase();
+    let mut player_state_manager = lock_state(ctx).await;
+    let config = player_state_manager.guild_config_mut(guild_id);
+    let before = config.api_keys.len();
+    config.api_keys.retain(|key| key.id != id);
+    let revoked = config.api_keys.len() < before;
+    drop(player_state_manager);
+    
+    let message = if revoked {
+        format!("Revoked API key `{}`. Requests using it are refused from now on.", id)
+    } else {
+        format!("This server has no API key `{}`.", id)
+    };
+    ctx.send(|b| b.content(message).ephemeral(true)).await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "list")]
+pub async fn gm_api_key_list(
+    ctx: poise::Context<'_, Data, Error>,
+) -> Result<(), Error> {
+    let keys = lock_state(ctx).await.guild_config(ctx.guild_id()).api_keys;
+    if keys.is_empty() {
+        ctx.send(|b| b.content("This server has no API keys. Create one with /gm api_key create.").ephemeral(true)).await?;
+        return Ok(());
+    }
+    let lines = keys.iter()
+        .map(|key| format!("`{}` {}: {} requests per minute, created <t:{}:R>", key.id, key.label, key.requests_per_minute, key.created_at))
+        .collect::<Vec<_>>()
+        .join("\n");
+    ctx.send(|b| b.content(format!("**API keys**\n{}", lines)).ephemeral(true)).await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "affinity")]
+pub async fn gm_affinity(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "Enemy name as players type it in targets"] target: String,
+    #[description = "An element, e.g. Fire, Ice or Bolt"] element: String,
+    #[description = "How the enemy takes that element (leave empty to clear)"] affinity: Option<Affinity>,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+    let element = match parse::element(&element) {
+        Ok(element) => element,
+        Err(e) => {
+            ctx.send(|b| b.content(format!("Unknown element, {}.", e)).ephemeral(true)).await?;
+            return Ok(());
+        }
+    };
+    
+    let target = target.trim().to_string();
+    let mut player_state_manager = lock_state(ctx).await;
+    let config = player_state_manager.guild_config_mut(guild_id);
+    let affinities = config.target_affinities.entry(target.to_lowercase()).or_default();
+    match affinity {
+        Some(affinity) => {
+            affinities.insert(element.clone(), affinity);
+        },
+        None => {
+            affinities.remove(&element);
+        },
+    }
+    if affinities.is_empty() {
+        config.target_affinities.remove(&target.to_lowercase());
+    }
+    drop(player_state_manager);
+    
+    let message = match affinity {
+        Some(affinity) => format!("{} now {} {} damage.", target, affinity.verb(), element.name()),
+        None => format!("{} takes normal {} damage again.", target, element.name()),
+    };
+    ctx.send(|b| b.content(message).ephemeral(true)).await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "targets")]
+pub async fn gm_targets(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "Enemies in the scene, e.g. Goblin, Harpy (leave empty to clear)"] names: Option<String>,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+    let targets = parse_targets(names);
+    let mut player_state_manager = lock_state(ctx).await;
+    player_state_manager.guild_config_mut(guild_id).encounter_targets = targets.clone();
+    drop(player_state_manager);
+    
+    let message = if targets.is_empty() {
+        "No enemies tracked. Targeted hands need `targets:` typed in again.".to_string()
+    } else {
+        format!("Tracking {}. Players can pick them when resolving Triple Threat or Double Trouble.", targets.join(", "))
+    };
+    ctx.send(|b| b.content(message).ephemeral(true)).await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "burn")]
+pub async fn gm_burn(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "Player whose card is removed"] player: serenity::User,
+    #[description = "Card position in their hand, 1 for the first card"] position: usize,
+) -> Result<(), Error> {
+    let mut player_state_manager = lock_state(ctx).await;
+    let name = player_state_manager.display_name(player.id, &player.name);
+    
+    let Some(state) = player_state_manager.get_player_state(player.id).filter(|s| s.guild_id == ctx.guild_id()) else {
+        drop(player_state_manager);
+        ctx.say(format!("{} isn't in a combat at this table right now.", name)).await?;
+        return Ok(());
+    };
+    
+    let card = match state.burn_from_hand(position.wrapping_sub(1)) {
+        Ok(card) => card,
+        Err(_) => {
+            let held = state.hand.len();
+            drop(player_state_manager);
+            ctx.say(format!("{} only has {} card(s) in hand.", name, held)).await?;
+            return Ok(());
+        }
+    };
+    drop(player_state_manager);
+    
+    ctx.say(format!("The GM burns {} from {}'s hand. It won't come back this combat.", card.label(), name)).await?;
+    Ok(())
+}
+
+// Settling "what did I draw when" at the table; the timeline shows the player's cards, so only the GM sees it
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "history")]
+pub async fn gm_history(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "Player whose combat to look at"] player: serenity::User,
+    #[description = "Page to show, 1 is the most recent"] page: Option<usize>,
+) -> Result<(), Error> {
+    let player_state_manager = lock_state(ctx).await;
+    let name = player_state_manager.display_name(player.id, &player.name);
+    let log = player_state_manager.players.get(&player.id)
+        .filter(|state| state.guild_id == ctx.guild_id())
+        .map(|state| state.log.clone());
+    drop(player_state_manager);
+    
+    let Some(log) = log else {
+        ctx.send(|b| b.content(format!("{} isn't in a combat on this server.", name)).ephemeral(true)).await?;
+        return Ok(());
+    };
+    match format_history_page(&log, page, &format!("{}'s combat history", name)) {
+        Some(message) => say_chunked(ctx, message, true).await?,
+        None => {
+            ctx.send(|b| b.content("That part of the history couldn't be loaded.").ephemeral(true)).await?;
+        },
+    }
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "reshuffle")]
+pub async fn gm_reshuffle(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "Player whose discard goes back into their deck"] player: serenity::User,
+) -> Result<(), Error> {
+    let mut player_state_manager = lock_state(ctx).await;
+    let name = player_state_manager.display_name(player.id, &player.name);
+    
+    let Some(state) = player_state_manager.get_player_state(player.id).filter(|s| s.guild_id == ctx.guild_id()) else {
+        drop(player_state_manager);
+        ctx.say(format!("{} isn't in a combat at this table right now.", name)).await?;
+        return Ok(());
+    };
+    
+    if state.discard.is_empty() {
+        drop(player_state_manager);
+        ctx.say(format!("{}'s discard pile is empty.", name)).await?;
+        return Ok(());
+    }
+    let count = state.reshuffle_discard();
+    drop(player_state_manager);
+    
+    ctx.say(format!("The GM shuffles {} card(s) from {}'s discard back into their deck.", count, name)).await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "deal_all")]
+pub async fn gm_deal_all(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "Deal from a ready-made deck instead of the table's, e.g. to match physical cards"] deck: Option<DeckPreset>,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+    
+    let mut player_state_manager = lock_state(ctx).await;
+    let members = player_state_manager.guild_config(Some(guild_id)).table_members;
+    if members.is_empty() {
+        ctx.say("Nobody has joined the table yet. Players can use /table join.").await?;
+        return Ok(());
+    }
+    
+    // Deal everyone in under one lock so the table starts together
+    let mut dealt = Vec::new();
+    for &member in &members {
+        let player = player_state_manager.start_new_combat(member, Some(guild_id), deck);
+        player.draw_to_hand(player.hand_size)?;
+        let hand = player.hand.clone();
+        let names = format_arcana_names(&hand, &player.deck_template);
+        let name = player_state_manager.display_name(member, &format!("<@{}>", member));
+        let affinity = player_state_manager.affinity(member);
+        dealt.push((member, name, hand, affinity, names));
+    }
+    drop(player_state_manager);
+    
+    let summary = dealt.iter()
+        .map(|(_, name, hand, ..)| format!("{}: {} cards", name, hand.len()))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let preset = deck.map(|preset| format!("\n{}", preset.describe())).unwrap_or_default();
+    ctx.say(format!("Combat started for the whole table!\n{}{}", summary, preset)).await?;
+    
+    // Hands stay private, each player gets theirs by DM
+    let mut undelivered = Vec::new();
+    for (member, name, hand, affinity, names) in dealt {
+        let message = format!("Combat started! Drew {} cards.\n{}{}", hand.len(), format_hand_display(&hand, affinity.as_ref()), names);
+        let sent = match member.create_dm_channel(ctx.serenity_context()).await {
+            Ok(channel) => {
+                let mut sent = true;
+                for chunk in chunk_message(&message) {
+                    if channel.say(ctx.serenity_context(), chunk).await.is_err() {
+                        sent = false;
+                        break;
+                    }
+                }
+                sent
+            },
+            Err(_) => false,
+        };
+        if !sent {
+            undelivered.push(name);
+        }
+    }
+    if !undelivered.is_empty() {
+        ctx.say(format!("Couldn't DM {}. They can see their hand with /view_hand.", undelivered.join(", "))).await?;
+    }
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "party_combos")]
+pub async fn gm_party_combos(
+    ctx: poise::Context<'_, Data, Error>,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+    let player_state_manager = lock_state(ctx).await;
+    let config = player_state_manager.guild_config(Some(guild_id));
+    if !config.feature_enabled("party_combos") {
+        ctx.send(|b| b.content("Party combos aren't enabled on this server. Turn them on with /configure_feature.").ephemeral(true)).await?;
+        return Ok(());
+    }
+    
+    // Only the table counts once players have joined it, otherwise every combat here
+    let players: Vec<(String, &PlayerState)> = player_state_manager.players.iter()
+        .filter(|(user_id, state)| state.guild_id == Some(guild_id)
+            && !state.orphaned
+            && (config.table_members.is_empty() || config.table_members.contains(user_id)))
+        .map(|(user_id, state)| (player_state_manager.display_name(*user_id, &format!("<@{}>", user_id)), state))
+        .collect();
+    let player_count = players.len();
+    let combos = party::find(&players);
+    drop(players);
+    drop(player_state_manager);
+    
+    // Hands are private, so the report only ever goes to the GM
+    let message = if player_count < 2 {
+        "Party combos need at least two players in combat.".to_string()
+    } else if combos.is_empty() {
+        format!("No combos across {} hands right now.", player_count)
+    } else {
+        let lines = combos.iter().map(|c| format!("• {}", c.description)).collect::<Vec<_>>().join("\n");
+        format!("**Party combos** across {} hands\n{}", player_count, lines)
+    };
+    ctx.send(|b| b.content(message).ephemeral(true)).await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "approvals")]
+pub async fn gm_approvals(
+    ctx: poise::Context<'_, Data, Error>,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+    let player_state_manager = lock_state(ctx).await;
+    let lines: Vec<String> = player_state_manager.pending_approvals(guild_id)
+        .into_iter()
+        .map(|request| {
+            let name = player_state_manager.display_name(request.requester, &format!("<@{}>", request.requester));
+            let link = request.gm_message
+                .map(|message| format!(" (https://discord.com/channels/{}/{}/{})", guild_id, request.gm_channel, message))
+                .unwrap_or_default();
+            format!("• <t:{}:R> {} asks to {}{}", request.requested_at, name, request.action.describe(), link)
+        })
+        .collect();
+    drop(player_state_manager);
+    
+    let message = if lines.is_empty() {
+        "No requests are waiting on a GM.".to_string()
+    } else {
+        format!("**Waiting on a GM**, oldest first\n{}", lines.join("\n"))
+    };
+    say_chunked(ctx, message, true).await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "grant_action")]
+pub async fn gm_grant_action(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "Player who gets the extra action"] player: serenity::User,
+    #[description = "Kind of action to give back"] kind: ActionKind,
+) -> Result<(), Error> {
+    let mut player_state_manager = lock_state(ctx).await;
+    let name = player_state_manager.display_name(player.id, &player.name);
+    
+    let Some(state) = player_state_manager.get_player_state(player.id).filter(|s| s.guild_id == ctx.guild_id()) else {
+        drop(player_state_manager);
+        ctx.say(format!("{} isn't in a combat at this table right now.", name)).await?;
+        return Ok(());
+    };
+    
+    state.refund_action(kind);
+    drop(player_state_manager);
+    
+    ctx.say(format!("The GM lets {} take another {} action this turn.", name, kind.label())).await?;
+    Ok(())
+}
+
+// Turns refresh everyone's actions, so only the GM moves the table on
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "end_turn")]
+pub async fn gm_end_turn(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "Only end this player's turn instead of the whole table's"] player: Option<serenity::User>,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+    let mut player_state_manager = lock_state(ctx).await;
+    
+    let Some(player) = player else {
+        let count = player_state_manager.next_round(guild_id);
+        drop(player_state_manager);
+        
+        if count == 0 {
+            ctx.say("Nobody at this table is in a combat right now.").await?;
+        } else {
+            ctx.say(format!("A new round begins. Actions have been refreshed for {} combat(s).", count)).await?;
+        }
+        return Ok(());
+    };
+    
+    let name = player_state_manager.display_name(player.id, &player.name);
+    let Some(state) = player_state_manager.get_player_state(player.id).filter(|s| s.guild_id == Some(guild_id)) else {
+        ctx.say(format!("{} isn't in a combat at this table right now.", name)).await?;
+        return Ok(());
+    };
+    
+    state.next_turn();
+    let turn = state.turn;
+    drop(player_state_manager);
+    
+    ctx.say(format!("Turn {} begins for {}. Their actions have been refreshed.", turn, name)).await?;
+    Ok(())
+}
+
+// A rebuilt deck is a whole fresh hand, so it's the GM's call rather than a free mulligan
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "reset_deck")]
+pub async fn gm_reset_deck(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "Player whose deck is rebuilt"] player: serenity::User,
+) -> Result<(), Error> {
+    let mut player_state_manager = lock_state(ctx).await;
+    let config = player_state_manager.guild_config(ctx.guild_id());
+    let name = player_state_manager.display_name(player.id, &player.name);
+    
+    let Some(state) = player_state_manager.get_player_state(player.id).filter(|s| s.guild_id == ctx.guild_id()) else {
+        ctx.say(format!("{} isn't in a combat at this table right now.", name)).await?;
+        return Ok(());
+    };
+    
+    // Turn, actions, mulligans and the combat's tallies all carry on. A preset deck stays a preset.
+    let template = state.deck_preset.map_or(config.deck_template, |preset| preset.template());
+    state.rebuild_deck(&template)?;
+    let drawn = state.hand.len();
+    drop(player_state_manager);
+    
+    // The new hand is the player's to look at
+    ctx.say(format!("The GM rebuilt and shuffled {}'s deck, the old hand, discard and burned cards are all back in. They drew {} cards and can see them with /view_hand.", name, drawn)).await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "reassign_combat")]
+pub async fn gm_reassign_combat(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "Player who takes over the combat"] player: serenity::User,
+    #[description = "ID of the player who left (only needed if several combats are frozen)"] from: Option<String>,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+    let mut player_state_manager = lock_state(ctx).await;
+    let from = match pick_orphaned_combat(&player_state_manager, guild_id, from.as_deref()) {
+        Ok(from) => from,
+        Err(e) => {
+            ctx.send(|b| b.content(e).ephemeral(true)).await?;
+            return Ok(());
+        }
+    };
+    if let Err(e) = player_state_manager.reassign_combat(from, player.id) {
+        ctx.send(|b| b.content(e).ephemeral(true)).await?;
+        return Ok(());
+    }
+    let name = player_state_manager.display_name(player.id, &player.name);
+    drop(player_state_manager);
+    
+    ctx.say(format!("{} takes over the combat left behind by user {}.", name, from)).await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "archive_combat")]
+pub async fn gm_archive_combat(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "ID of the player who left (only needed if several combats are frozen)"] from: Option<String>,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+    let mut player_state_manager = lock_state(ctx).await;
+    let from = match pick_orphaned_combat(&player_state_manager, guild_id, from.as_deref()) {
+        Ok(from) => from,
+        Err(e) => {
+            ctx.send(|b| b.content(e).ephemeral(true)).await?;
+            return Ok(());
+        }
+    };
+    player_state_manager.end_combat(from);
+    drop(player_state_manager);
+    
+    ctx.say(format!("The combat left behind by user {} has been closed.", from)).await?;
+    Ok(())
+}
+
+// Which frozen combat a GM command is about: the one given, or the only one at the server
+fn pick_orphaned_combat(player_state_manager: &PlayerStateManager, guild_id: serenity::GuildId, from: Option<&str>) -> Result<serenity::UserId, String> {
+    let orphaned = player_state_manager.orphaned_combats(guild_id);
+    match from {
+        Some(from) => {
+            let user_id = parse::user_id(from).map_err(|e| format!("Couldn't read the player, {}.", e))?;
+            if orphaned.contains(&user_id) {
+                Ok(user_id)
+            } else {
+                Err(format!("User {} doesn't have a frozen combat here.", user_id))
+            }
+        },
+        None => match orphaned.as_slice() {
+            [] => Err("No combats are frozen on this server.".to_string()),
+            [user_id] => Ok(*user_id),
+            several => Err(format!("Several combats are frozen, pick one with `from`: {}",
+                several.iter().map(|id| id.to_string()).collect::<Vec<_>>().join(", "))),
+        },
+    }
+}
+
+#[poise::command(slash_command, owners_only, subcommands("admin_stats", "admin_reload_config", "admin_replay"))]
+pub async fn admin(
+    _ctx: poise::Context<'_, Data, Error>,
+) -> Result<(), Error> {
+    Ok(())
+}
+
+#[poise::command(slash_command, owners_only, rename = "reload_config")]
+pub async fn admin_reload_config(
+    ctx: poise::Context<'_, Data, Error>,
+) -> Result<(), Error> {
+    let new = match Settings::reload() {
+        Ok(new) => new,
+        Err(e) => {
+            ctx.send(|b| b.content(format!("Kept the current settings: {}", e)).ephemeral(true)).await?;
+            return Ok(());
+        }
+    };
+    
+    let mut settings = ctx.data().settings.lock().await;
+    let changes = settings.describe_changes(&new);
+    let token_rotated = settings.token != new.token;
+    ctx.data().metrics.lock().await.trace_stages = new.trace_stages;
+    *settings = new;
+    drop(settings);
+    
+    let report = if changes.is_empty() {
+        "Settings reloaded, nothing changed.".to_string()
+    } else {
+        format!("Settings reloaded:\n{}", changes.join("\n"))
+    };
+    ctx.send(|b| b.content(report).ephemeral(true)).await?;
+    
+    // The gateway has to reconnect to use a new token; combats live outside the client and carry over
+    if token_rotated {
+        ctx.framework().shard_manager().lock().await.shutdown_all().await;
+    }
+    Ok(())
+}
+
+#[poise::command(slash_command, owners_only, rename = "stats")]
+pub async fn admin_stats(
+    ctx: poise::Context<'_, Data, Error>,
+) -> Result<(), Error> {
+    let mut report = ctx.data().metrics.lock().await.format_report();
+    if load::is_degraded() {
+        report.push_str("Running in degraded mode.\n");
+    }
+    ctx.send(|b| b.content(report).ephemeral(true)).await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, owners_only, rename = "replay")]
+pub async fn admin_replay(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "Player whose combat to rebuild"] player: serenity::User,
+    #[description = "Unix time or a <t:...> timestamp (defaults to now)"] at: Option<String>,
+) -> Result<(), Error> {
+    let until = match at.as_deref().map(parse::timestamp) {
+        None => events::unix_now(),
+        Some(Ok(until)) => until,
+        Some(Err(e)) => {
+            ctx.send(|b| b.content(format!("That isn't a timestamp, {}.", e)).ephemeral(true)).await?;
+            return Ok(());
+        }
+    };
+    
+    let player_state_manager = lock_state(ctx).await;
+    let Some(state) = player_state_manager.players.get(&player.id) else {
+        ctx.send(|b| b.content(format!("{} has no combat on record.", player.name)).ephemeral(true)).await?;
+        return Ok(());
+    };
+    let log = state.log.clone();
+    drop(player_state_manager);
+    
+    // Replay always starts from the first event, so archived segments are read back too
+    let events = match log.events_between(0, log.total_events()) {
+        Ok(events) => events,
+        Err(e) => {
+            eprintln!("Failed to load history for {}: {}", log.combat_id, e);
+            ctx.send(|b| b.content(format!("Couldn't load the event log for {}: {}", log.combat_id, e)).ephemeral(true)).await?;
+            return Ok(());
+        }
+    };
+    let replayed = replay::replay(&events, until);
+    
+    let mut report = format!("**Replay of {}** ({}) at <t:{}:F>\n", player.name, log.combat_id, until);
+    report.push_str(&format!("{} of {} events applied, turn {}, {} resolution(s)\n",
+        replayed.events_applied, events.len(), replayed.turn, replayed.resolutions));
+    if let Some(event) = &replayed.last_event {
+        report.push_str(&format!("Last event: <t:{}:T> {}\n", event.at, event.describe()));
+    }
+    report.push_str(&format!("\n**Hand**\n{}", format_hand_display(&replayed.hand, None)));
+    for (zone, cards) in [("Deck (next draw last)", &replayed.deck), ("Discard", &replayed.discard), ("Burned", &replayed.burned)] {
+        let listing = if cards.is_empty() { "empty".to_string() } else { format_card_labels(cards) };
+        report.push_str(&format!("**{}** ({} cards): {}\n", zone, cards.len(), listing));
+    }
+    if !replayed.warnings.is_empty() {
+        report.push_str(&format!("\n**Warnings**\n{}\n", replayed.warnings.join("\n")));
+    }
+    
+    say_chunked(ctx, report, true).await?;
+    Ok(())
+}
+
+// Discord rejects messages over 2000 characters; a full deck listing can get close
+const MESSAGE_LIMIT: usize = 2000;
+
+// Split content into messages Discord will accept, breaking between lines. A single
+// line over the limit is cut wherever it has to be.
+fn chunk_message(content: &str) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    let mut current_len = 0;
+    for line in content.split('\n') {
+        let mut line: Vec<char> = line.chars().collect();
+        while line.len() > MESSAGE_LIMIT {
+            let rest = line.split_off(MESSAGE_LIMIT);
+            chunks.push(std::mem::take(&mut current));
+            chunks.push(line.into_iter().collect());
+            current_len = 0;
+            line = rest;
+        }
+        if !current.is_empty() && current_len + 1 + line.len() > MESSAGE_LIMIT {
+            chunks.push(std::mem::take(&mut current));
+            current_len = 0;
+        }
+        if !current.is_empty() {
+            current.push('\n');
+            current_len += 1;
+        }
+        current_len += line.len();
+        current.extend(line);
+    }
+    chunks.push(current);
+    // Discord won't send a blank message
+    chunks.retain(|chunk| !chunk.trim().is_empty());
+    chunks
+}
+
+// Reply with content of any length, as several messages if it has to be
+async fn say_chunked(ctx: poise::Context<'_, Data, Error>, content: String, ephemeral: bool) -> Result<(), Error> {
+    for chunk in chunk_message(&content) {
+        ctx.send(|b| b.content(chunk).ephemeral(ephemeral)).await?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "fixtures")]
+#[poise::command(slash_command, owners_only, subcommands("debug_load_fixture", "debug_write_fixtures"))]
+pub async fn debug(
+    _ctx: poise::Context<'_, Data, Error>,
+) -> Result<(), Error> {
+    Ok(())
+}
+
+#[cfg(feature = "fixtures")]
+#[poise::command(slash_command, owners_only, rename = "load_fixture")]
+pub async fn debug_load_fixture(
+    ctx: poise::Context<'_, Data, Error>,
+    #[description = "all_jokers, near_jackpot, empty_deck or max_hand"] name: String,
+) -> Result<(), Error> {
+    let mut fixture = match fixtures::load(&name) {
+        Ok(fixture) => fixture,
+        Err(e) => {
+            ctx.send(|b| b.content(e).ephemeral(true)).await?;
+            return Ok(());
+        }
+    };
+    
+    // The fixture replaces your own combat, at this server
+    let mut player_state_manager = lock_state(ctx).await;
+    let player = player_state_manager.start_new_combat(ctx.author().id, ctx.guild_id(), None);
+    fixture.guild_id = player.guild_id;
+    fixture.log.combat_id = player.log.combat_id.clone();
+    *player = fixture;
+    let hand = player.hand.clone();
+    drop(player_state_manager);
+    
+    ctx.send(|b| b.content(format!("Loaded fixture `{}`.\n{}", name, format_hand_display(&hand, None))).ephemeral(true)).await?;
+    Ok(())
+}
+
+#[cfg(feature = "fixtures")]
+#[poise::command(slash_command, owners_only, rename = "write_fixtures")]
+pub async fn debug_write_fixtures(
+    ctx: poise::Context<'_, Data, Error>,
+) -> Result<(), Error> {
+    let message = match fixtures::write_all() {
+        Ok(paths) => format!("Wrote {} fixture(s):\n{}", paths.len(), paths.join("\n")),
+        Err(e) => format!("Couldn't write fixtures: {}", e),
+    };
+    ctx.send(|b| b.content(message).ephemeral(true)).await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, owners_only)]
+pub async fn selftest(
+    ctx: poise::Context<'_, Data, Error>,
+) -> Result<(), Error> {
+    let results = selftest::run();
+    let failures = results.iter().filter(|r| r.outcome.is_err()).count();
+    
+    let mut report = if failures == 0 {
+        format!("Self-test passed ({} steps):\n", results.len())
+    } else {
+        format!("Self-test failed ({} of {} steps):\n", failures, results.len())
+    };
+    for result in &results {
+        match &result.outcome {
+            Ok(()) => report.push_str(&format!("✅ {}\n", result.name)),
+            Err(e) => report.push_str(&format!("❌ {}: {}\n", result.name, e)),
+        }
+    }
+    
+    ctx.send(|b| b.content(report).ephemeral(true)).await?;
+    Ok(())
+}
+
+// Lock the shared state, recording the wait against the running command
+async fn lock_state<'a>(ctx: poise::Context<'a, Data, Error>) -> tokio::sync::MutexGuard<'a, PlayerStateManager> {
+    let started = Instant::now();
+    let player_state_manager = ctx.data().player_state_manager.lock().await;
+    record_stage(ctx, Stage::LockWait, started).await;
+    player_state_manager
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Defer {
+    Public,
+    Private,
+}
+
+// Commands that can take longer than Discord's 3 seconds to answer, deferred before they run.
+// A deferred reply can't change visibility afterwards, so commands answering privately defer privately.
+const DEFERRED_COMMANDS: &[(&str, Defer)] = &[
+    ("view_possible_resolutions", Defer::Public),
+    ("resolve_hand", Defer::Public),
+    ("resolve_best", Defer::Public),
+    ("commit_resolution", Defer::Public),
+    ("odds", Defer::Private),
+    ("simulate", Defer::Private),
+    ("gm export_campaign", Defer::Private),
+    ("gm party_combos", Defer::Private),
+    ("admin replay", Defer::Private),
+];
+
+async fn defer_if_slow(ctx: poise::Context<'_, Data, Error>) -> Result<(), Error> {
+    let command = ctx.command().qualified_name.as_str();
+    match DEFERRED_COMMANDS.iter().find(|(name, _)| *name == command) {
+        Some((_, Defer::Public)) => ctx.defer().await?,
+        Some((_, Defer::Private)) => ctx.defer_ephemeral().await?,
+        None => {},
+    }
+    Ok(())
+}
+
+// Answer at the end of a slow path. The interaction token only lasts 15 minutes, so if
+// replying fails the answer goes to the channel instead of being lost.
+async fn say_followup(ctx: poise::Context<'_, Data, Error>, content: String) -> Result<(), Error> {
+    for chunk in chunk_message(&content) {
+        if let Err(e) = ctx.say(chunk.clone()).await {
+            eprintln!("Reply to /{} failed, posting in the channel instead: {}", ctx.command().qualified_name, e);
+            ctx.channel_id().say(ctx.serenity_context(), format!("<@{}>\n{}", ctx.author().id, chunk)).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn record_stage(ctx: poise::Context<'_, Data, Error>, stage: Stage, started: Instant) {
+    ctx.data().metrics.lock().await.record_stage(ctx.id(), stage, started.elapsed());
+}
+
+// Record the outcome of a command and alert the owner channel on error spikes
+async fn record_command_result(ctx: poise::Context<'_, Data, Error>, success: bool) {
+    let command = ctx.command().qualified_name.clone();
+    let alert = ctx.data().metrics.lock().await.finish(ctx.id(), &command, success);
+
+    if let Some(alert) = alert {
+        let alert_channel = ctx.data().settings.lock().await.alert_channel;
+        send_alert(ctx.serenity_context(), alert_channel, &alert).await;
+    }
+}
+
+async fn send_alert(http: impl AsRef<serenity::Http>, channel: Option<serenity::ChannelId>, message: &str) {
+    eprintln!("{}", message);
+    let Some(channel) = channel else {
+        return;
+    };
+    if let Err(e) = channel.say(&http, message).await {
+        eprintln!("Failed to send alert: {}", e);
+    }
+}
+
+// Answer a GM's click on an approval request's buttons
+async fn handle_approval_button(ctx: &serenity::Context, mci: &serenity::MessageComponentInteraction, data: &Data) -> Result<(), Error> {
+    let Some((id, approve)) = approvals::parse_button_id(&mci.data.custom_id) else {
+        return Ok(());
+    };
+    let is_gm = mci.member.as_ref()
+        .and_then(|member| member.permissions)
+        .is_some_and(|permissions| permissions.manage_guild());
+    if !is_gm {
+        mci.create_interaction_response(ctx, |r| r
+            .kind(serenity::InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|d| d.content("Only a GM can answer approval requests.").ephemeral(true))
+        ).await?;
+        return Ok(());
+    }
+    
+    let mut player_state_manager = data.player_state_manager.lock().await;
+    let Some(request) = player_state_manager.take_approval(id) else {
+        drop(player_state_manager);
+        mci.create_interaction_response(ctx, |r| r
+            .kind(serenity::InteractionResponseType::UpdateMessage)
+            .interaction_response_data(|d| d.content(format!("{}\nAlready answered.", mci.message.content)).components(|c| c))
+        ).await?;
+        return Ok(());
+    };
+    let outcome = if approve {
+        Some(player_state_manager.apply_approval(request.requester, &request.action))
+    } else {
+        None
+    };
+    let gm = player_state_manager.display_name(mci.user.id, &mci.user.name);
+    drop(player_state_manager);
+    
+    let (verdict, reply) = match outcome {
+        Some(Ok(result)) => (
+            format!("**Approved** by {}", gm),
+            format!("<@{}>, the GM approved your request. {}", request.requester, result),
+        ),
+        Some(Err(e)) => (
+            format!("**Approved** by {}, but it couldn't be applied: {}", gm, e),
+            format!("<@{}>, the GM approved your request but it couldn't be applied: {}", request.requester, e),
+        ),
+        None => (
+            format!("**Denied** by {}", gm),
+            format!("<@{}>, the GM denied your request to {}.", request.requester, request.action.describe()),
+        ),
+    };
+    mci.create_interaction_response(ctx, |r| r
+        .kind(serenity::InteractionResponseType::UpdateMessage)
+        .interaction_response_data(|d| d.content(format!("{}\n{}", mci.message.content, verdict)).components(|c| c))
+    ).await?;
+    request.reply_channel.say(ctx, reply).await?;
+    Ok(())
+}
+
+const PROBE_INTERVAL: Duration = Duration::from_secs(60);
+
+// Well past what tables deal, so detection is timed with room to spare
+const PROBE_HAND_SIZE: usize = 12;
+
+// Time what every command leans on without going through Discord: waiting for the
+// state lock, detecting hands and writing to the store
+async fn run_latency_probe(player_state_manager: &Mutex<PlayerStateManager>) -> Result<Vec<(ProbeStage, Duration)>, Error> {
+    let started = Instant::now();
+    drop(player_state_manager.lock().await);
+    let lock = started.elapsed();
+    
+    // A throwaway combat, so the probe never touches anyone's state. Its jokers are dealt in
+    // on top, since wild cards are what make a hand expensive to search.
+    let mut player = PlayerState::new(None, &DeckTemplate::default());
+    let jokers: Vec<CardType> = player.deck.cards.iter().filter(|card| matches!(card, CardType::Joker { .. })).cloned().collect();
+    player.deck.cards.retain(|card| !matches!(card, CardType::Joker { .. }));
+    player.draw_to_hand(PROBE_HAND_SIZE - jokers.len())?;
+    player.hand.extend(jokers);
+    let started = Instant::now();
+    player.find_available_hands(&GuildConfig::default());
+    let detection = started.elapsed();
+    
+    let started = Instant::now();
+    tokio::task::spawn_blocking(PlayerStateManager::probe_store).await??;
+    let store = started.elapsed();
+    
+    Ok(vec![(ProbeStage::Lock, lock), (ProbeStage::Detection, detection), (ProbeStage::Store, store)])
+}
+
+// Freeze the combats of members who leave, and thaw them if they come back.
+// GM answers to approval requests come through here too, they outlive any collector.
+async fn on_event(ctx: &serenity::Context, event: &poise::Event<'_>, data: &Data) -> Result<(), Error> {
+    let (user, guild_id, left) = match event {
+        poise::Event::InteractionCreate { interaction: serenity::Interaction::MessageComponent(mci) } => {
+            return handle_approval_button(ctx, mci, data).await;
+        },
+        poise::Event::GuildMemberRemoval { guild_id, user, .. } => (user, *guild_id, true),
+        poise::Event::GuildMemberAddition { new_member } => (&new_member.user, new_member.guild_id, false),
+        _ => return Ok(()),
+    };
+    
+    let mut player_state_manager = data.player_state_manager.lock().await;
+    let changed = if left {
+        player_state_manager.orphan_combat(user.id, guild_id)
+    } else {
+        player_state_manager.restore_combat(user.id, guild_id)
+    };
+    if !changed {
+        return Ok(());
+    }
+    let name = player_state_manager.display_name(user.id, &user.name);
+    let table_channel = player_state_manager.guild_config(Some(guild_id)).table_channel;
+    drop(player_state_manager);
+    
+    let note = if left {
+        format!("{} left the server mid-combat, so their combat is frozen. A GM can hand it to someone with /gm reassign_combat or close it with /gm archive_combat (user {}).", name, user.id)
+    } else {
+        format!("Welcome back {}, your combat is right where you left it.", name)
+    };
+    eprintln!("{}", note);
+    if let Some(channel) = table_channel {
+        channel.say(ctx, note).await?;
+    }
+    Ok(())
+}
+
+async fn on_error(error: poise::FrameworkError<'_, Data, Error>) {
+    if let poise::FrameworkError::Command { ctx, .. } = &error {
+        record_command_result(*ctx, false).await;
+    }
+    if let Err(e) = poise::builtins::on_error(error).await {
+        eprintln!("Error while handling error: {}", e);
+    }
+}
+
+// Define the commands list as a static
+static COMMANDS: &[fn() -> poise::Command<Data, Error>] = &[
+    start_new_combat,
+    end_combat,
+    mulligan,
+    discard,
+    card_guard,
+    card_boost,
+    redraw_hand,
+    draw_extra,
+    quick_draw,
+    peek_deck,
+    view_hand,
+    hand_delivery,
+    sort_hand,
+    view_discard,
+    deck_status,
+    hand_value,
+    advise,
+    odds,
+    simulate,
+    simulate_hand,
+    reshuffle,
+    view_possible_resolutions,
+    resolve_hand,
+    resolve_best,
+    commit_resolution,
+    cancel_resolution,
+    deck_diff,
+    history,
+    archive,
+    burn,
+    tuck,
+    search_deck,
+    give_card,
+    set_joker,
+    contribute,
+    status,
+    party_status,
+    table,
+    character,
+    joker,
+    modifiers,
+    fp,
+    ask_gm,
+    configure_module,
+    configure_feature,
+    configure_actions,
+    configure_mulligans,
+    setup_status,
+    configure_theme,
+    configure_effect,
+    configure_homebrew_hand,
+    configure_power,
+    rules,
+    configure_deck,
+    configure_shuffle,
+    configure_table_channel,
+    configure_gm_channel,
+    gm,
+    admin,
+    selftest,
+    #[cfg(feature = "fixtures")]
+    debug,
+];
+
+// Update your main() function to include the GameState
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    dotenv().ok();
     
     // Try to load saved state, or create a new one if loading fails
     let player_state_manager = Arc::new(Mutex::new(
@@ -287,33 +4707,159 @@ async fn main() -> Result<(), Error> {
         })
     ));
     
+    let settings = Settings::from_env().expect("Missing DISCORD_TOKEN");
+    let mut metrics = CommandMetrics::new();
+    metrics.trace_stages = settings.trace_stages;
+    let metrics = Arc::new(Mutex::new(metrics));
+    if let Some(addr) = settings.api_addr.clone() {
+        let manager = player_state_manager.clone();
+        tokio::spawn(async move {
+            if let Err(e) = api::serve(addr, manager).await {
+                eprintln!("The HTTP API stopped: {}", e);
+            }
+        });
+    }
+    let settings = Arc::new(Mutex::new(settings));
+    
     let state_manager_clone = player_state_manager.clone();
+    let background_settings = settings.clone();
     tokio::spawn(async move {
         let mut interval = tokio::time::interval(std::time::Duration::from_secs(30));
         loop {
             interval.tick().await;
+            let started = Instant::now();
             if let Err(e) = PlayerStateManager::save_if_needed(&state_manager_clone).await {
                 eprintln!("Failed to save state: {}", e);
             }
+            if let Some(degraded) = load::observe(Pressure::Store, started.elapsed()) {
+                // Background jobs use their own HTTP client, built from the current token
+                let settings = background_settings.lock().await.clone();
+                let http = Arc::new(serenity::Http::new(&settings.token));
+                send_alert(&http, settings.alert_channel, load::mode_change_message(degraded)).await;
+            }
+            collapse_expired_interactions(&state_manager_clone, &background_settings).await;
+        }
+    });
+    
+    let probe_state = player_state_manager.clone();
+    let probe_metrics = metrics.clone();
+    let probe_settings = settings.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PROBE_INTERVAL);
+        loop {
+            interval.tick().await;
+            let timings = match run_latency_probe(&probe_state).await {
+                Ok(timings) => timings,
+                Err(e) => {
+                    eprintln!("Latency probe failed: {}", e);
+                    continue;
+                }
+            };
+            if let Some(alert) = probe_metrics.lock().await.probe.record(&timings) {
+                let settings = probe_settings.lock().await.clone();
+                let http = Arc::new(serenity::Http::new(&settings.token));
+                send_alert(&http, settings.alert_channel, &alert).await;
+            }
         }
     });
     
-    let framework = poise::Framework::builder()
+    // Serenity resumes short gateway drops on its own. If the client gives up entirely,
+    // rebuild it with backoff; player state lives outside the framework so nothing is lost.
+    let mut backoff = MIN_RECONNECT_BACKOFF;
+    let mut outage_since: Option<Instant> = None;
+    loop {
+        let connected_at = Instant::now();
+        let (token, intents) = {
+            let settings = settings.lock().await;
+            (settings.token.clone(), settings.intents())
+        };
+        let framework = build_framework(
+            token.clone(),
+            intents,
+            player_state_manager.clone(),
+            metrics.clone(),
+            settings.clone(),
+            outage_since.take(),
+        );
+        
+        if let Err(e) = framework.run().await {
+            eprintln!("Gateway connection lost: {}", e);
+        } else if settings.lock().await.token == token {
+            return Ok(());
+        } else {
+            // /admin reload_config stopped the gateway to pick up a rotated token
+            eprintln!("Reconnecting with the rotated token");
+            backoff = MIN_RECONNECT_BACKOFF;
+            continue;
+        }
+        
+        // A connection that stayed up for a while starts the backoff over
+        if connected_at.elapsed() > MAX_RECONNECT_BACKOFF {
+            backoff = MIN_RECONNECT_BACKOFF;
+        }
+        outage_since = Some(Instant::now());
+        eprintln!("Reconnecting in {}s", backoff.as_secs());
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_RECONNECT_BACKOFF);
+    }
+}
+
+const MIN_RECONNECT_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_BACKOFF: Duration = Duration::from_secs(300);
+// Outages longer than this get announced in table channels once we're back
+const ANNOUNCE_OUTAGE_AFTER: Duration = Duration::from_secs(120);
+
+fn build_framework(
+    token: String,
+    intents: serenity::GatewayIntents,
+    player_state_manager: Arc<Mutex<PlayerStateManager>>,
+    metrics: Arc<Mutex<CommandMetrics>>,
+    settings: Arc<Mutex<Settings>>,
+    outage_since: Option<Instant>,
+) -> poise::FrameworkBuilder<Data, Error> {
+    poise::Framework::builder()
         .options(poise::FrameworkOptions {
             commands: COMMANDS.iter().map(|cmd| cmd()).collect(),
+            pre_command: |ctx| Box::pin(async move {
+                // Before anything that can queue, including the lock wait below
+                if let Err(e) = defer_if_slow(ctx).await {
+                    eprintln!("Failed to defer /{}: {}", ctx.command().qualified_name, e);
+                }
+                ctx.data().metrics.lock().await.start(ctx.id());
+                // Time how long commands wait for the shared state to spot queueing
+                let started = Instant::now();
+                drop(ctx.data().player_state_manager.lock().await);
+                if let Some(degraded) = load::observe(Pressure::CommandQueue, started.elapsed()) {
+                    let alert_channel = ctx.data().settings.lock().await.alert_channel;
+                    send_alert(ctx.serenity_context(), alert_channel, load::mode_change_message(degraded)).await;
+                }
+            }),
+            post_command: |ctx| Box::pin(record_command_result(ctx, true)),
+            on_error: |error| Box::pin(on_error(error)),
+            event_handler: |ctx, event, _framework, data| Box::pin(on_event(ctx, event, data)),
             ..Default::default()
         })
-        .token(std::env::var("DISCORD_TOKEN").expect("Missing DISCORD_TOKEN"))
-        .intents(serenity::GatewayIntents::non_privileged())
+        .token(token)
+        .intents(intents)
         .setup(move |ctx, _ready, framework| {
             Box::pin(async move {
                 poise::builtins::register_globally(ctx, &framework.options().commands).await?;
+                
+                if let Some(outage) = outage_since.map(|since| since.elapsed()).filter(|o| *o > ANNOUNCE_OUTAGE_AFTER) {
+                    let channels = player_state_manager.lock().await.table_channels();
+                    let note = format!("I'm back online after about {} minute(s) away. Your combats were kept as they were.", outage.as_secs() / 60);
+                    for channel in channels {
+                        if let Err(e) = channel.say(ctx, &note).await {
+                            eprintln!("Failed to post back-online note: {}", e);
+                        }
+                    }
+                }
+                
                 Ok(Data {
-                    player_state_manager: player_state_manager.clone(),
+                    player_state_manager,
+                    metrics,
+                    settings,
                 })
             })
-        });
-
-    framework.run().await?;
-    Ok(())
-}
\ No newline at end of file
+        })
+}
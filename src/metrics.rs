@@ -0,0 +1,300 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+// Keep a rolling window of latencies per command for percentile reporting
+const LATENCY_SAMPLES: usize = 200;
+// Don't alert on the first unlucky invocation after a deploy
+const ALERT_MIN_INVOCATIONS: u64 = 5;
+const ALERT_ERROR_RATE: f64 = 0.5;
+// The smoke probe runs every minute or so, keep about the last twenty minutes
+const PROBE_SAMPLES: usize = 20;
+// A few probes before the p95 means anything
+const PROBE_MIN_SAMPLES: usize = 5;
+
+// Parts of handling a command that are timed separately
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Stage {
+    LockWait,
+    Detection,
+    Formatting,
+    Send,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 4] = [Stage::LockWait, Stage::Detection, Stage::Formatting, Stage::Send];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Stage::LockWait => "lock",
+            Stage::Detection => "detect",
+            Stage::Formatting => "format",
+            Stage::Send => "send",
+        }
+    }
+}
+
+// What the background smoke probe times, outside of any Discord command
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ProbeStage {
+    Lock,
+    Detection,
+    Store,
+}
+
+impl ProbeStage {
+    pub const ALL: [ProbeStage; 3] = [ProbeStage::Lock, ProbeStage::Detection, ProbeStage::Store];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ProbeStage::Lock => "lock",
+            ProbeStage::Detection => "detect",
+            ProbeStage::Store => "store",
+        }
+    }
+
+    // Well under the degraded-mode limits, so the owner hears about it before players do
+    pub fn p95_limit(&self) -> Duration {
+        match self {
+            ProbeStage::Lock => Duration::from_millis(500),
+            ProbeStage::Detection => Duration::from_millis(50),
+            ProbeStage::Store => Duration::from_millis(750),
+        }
+    }
+}
+
+// Recent smoke probe timings, alerting while any stage's p95 is over its limit
+#[derive(Default)]
+pub struct ProbeStats {
+    samples: HashMap<ProbeStage, VecDeque<Duration>>,
+    alerting: bool,
+}
+
+impl ProbeStats {
+    // Returns an alert when the probe goes over its limits, and another once it recovers
+    pub fn record(&mut self, timings: &[(ProbeStage, Duration)]) -> Option<String> {
+        for (stage, elapsed) in timings {
+            let samples = self.samples.entry(*stage).or_default();
+            if samples.len() == PROBE_SAMPLES {
+                samples.pop_front();
+            }
+            samples.push_back(*elapsed);
+        }
+
+        let slow: Vec<String> = ProbeStage::ALL.iter()
+            .filter_map(|stage| {
+                let samples = self.samples.get(stage).filter(|s| s.len() >= PROBE_MIN_SAMPLES)?;
+                let p95 = percentile(samples, 95.0).filter(|p95| *p95 > stage.p95_limit())?;
+                Some(format!("{} p95 {}ms (limit {}ms)", stage.label(), p95.as_millis(), stage.p95_limit().as_millis()))
+            })
+            .collect();
+        if !slow.is_empty() && !self.alerting {
+            self.alerting = true;
+            return Some(format!("⚠️ Latency probe is slow: {}.", slow.join(", ")));
+        }
+        if slow.is_empty() && self.alerting {
+            self.alerting = false;
+            return Some("✅ Latency probe is back under its limits.".to_string());
+        }
+        None
+    }
+
+    pub fn format_report(&self) -> Option<String> {
+        let stages: Vec<String> = ProbeStage::ALL.iter()
+            .filter_map(|stage| {
+                let samples = self.samples.get(stage)?;
+                Some(format!("{} p95 {}", stage.label(), format_latency(percentile(samples, 95.0))))
+            })
+            .collect();
+        (!stages.is_empty()).then(|| format!("Latency probe: {}\n", stages.join(", ")))
+    }
+}
+
+#[derive(Default)]
+pub struct CommandStats {
+    pub successes: u64,
+    pub errors: u64,
+    latencies: VecDeque<Duration>,
+    stage_latencies: HashMap<Stage, VecDeque<Duration>>,
+    alerting: bool,
+}
+
+impl CommandStats {
+    pub fn total(&self) -> u64 {
+        self.successes + self.errors
+    }
+
+    pub fn error_rate(&self) -> f64 {
+        if self.total() == 0 {
+            return 0.0;
+        }
+        self.errors as f64 / self.total() as f64
+    }
+
+    pub fn percentile(&self, pct: f64) -> Option<Duration> {
+        percentile(&self.latencies, pct)
+    }
+
+    pub fn stage_percentile(&self, stage: Stage, pct: f64) -> Option<Duration> {
+        self.stage_latencies.get(&stage).and_then(|samples| percentile(samples, pct))
+    }
+
+    fn record_latency(&mut self, latency: Duration) {
+        push_sample(&mut self.latencies, latency);
+    }
+}
+
+fn percentile(samples: &VecDeque<Duration>, pct: f64) -> Option<Duration> {
+    if samples.is_empty() {
+        return None;
+    }
+    let mut sorted: Vec<Duration> = samples.iter().copied().collect();
+    sorted.sort();
+    let rank = ((pct / 100.0) * (sorted.len() - 1) as f64).round() as usize;
+    Some(sorted[rank.min(sorted.len() - 1)])
+}
+
+fn push_sample(samples: &mut VecDeque<Duration>, sample: Duration) {
+    if samples.len() == LATENCY_SAMPLES {
+        samples.pop_front();
+    }
+    samples.push_back(sample);
+}
+
+// Timing for a command that's still running
+struct Invocation {
+    started: Instant,
+    stages: HashMap<Stage, Duration>,
+}
+
+pub struct CommandMetrics {
+    pub commands: HashMap<String, CommandStats>,
+    in_flight: HashMap<u64, Invocation>,
+    started_at: Instant,
+    // Log a per-stage breakdown of every invocation
+    pub trace_stages: bool,
+    pub probe: ProbeStats,
+}
+
+impl Default for CommandMetrics {
+    fn default() -> Self {
+        CommandMetrics {
+            commands: HashMap::new(),
+            in_flight: HashMap::new(),
+            started_at: Instant::now(),
+            trace_stages: false,
+            probe: ProbeStats::default(),
+        }
+    }
+}
+
+impl CommandMetrics {
+    pub fn new() -> Self {
+        CommandMetrics::default()
+    }
+
+    // Called from the pre-command hook with the invocation's unique id
+    pub fn start(&mut self, invocation_id: u64) {
+        self.in_flight.insert(invocation_id, Invocation {
+            started: Instant::now(),
+            stages: HashMap::new(),
+        });
+    }
+
+    // Add time spent in one stage; a stage hit more than once is summed
+    pub fn record_stage(&mut self, invocation_id: u64, stage: Stage, elapsed: Duration) {
+        if let Some(invocation) = self.in_flight.get_mut(&invocation_id) {
+            *invocation.stages.entry(stage).or_default() += elapsed;
+        }
+    }
+
+    // Record the outcome of an invocation. Returns an alert message the first time
+    // a command's error rate crosses the threshold since it was last healthy.
+    pub fn finish(&mut self, invocation_id: u64, command: &str, success: bool) -> Option<String> {
+        let invocation = self.in_flight.remove(&invocation_id);
+
+        let stats = self.commands.entry(command.to_string()).or_default();
+        if success {
+            stats.successes += 1;
+        } else {
+            stats.errors += 1;
+        }
+        if let Some(invocation) = invocation {
+            let latency = invocation.started.elapsed();
+            stats.record_latency(latency);
+            for (stage, elapsed) in &invocation.stages {
+                push_sample(stats.stage_latencies.entry(*stage).or_default(), *elapsed);
+            }
+            if self.trace_stages {
+                eprintln!("[trace] /{} took {}ms: {}", command, latency.as_millis(), format_stages(&invocation.stages));
+            }
+        }
+
+        let spiking = stats.total() >= ALERT_MIN_INVOCATIONS && stats.error_rate() >= ALERT_ERROR_RATE;
+        if spiking && !stats.alerting {
+            stats.alerting = true;
+            return Some(format!(
+                "⚠️ `/{}` error rate is {:.0}% ({} of {} invocations) since startup.",
+                command,
+                stats.error_rate() * 100.0,
+                stats.errors,
+                stats.total()
+            ));
+        }
+        if !spiking {
+            stats.alerting = false;
+        }
+        None
+    }
+
+    pub fn format_report(&self) -> String {
+        let probe = self.probe.format_report().unwrap_or_default();
+        if self.commands.is_empty() {
+            return format!("No commands have been run since startup.\n{}", probe);
+        }
+
+        let mut names: Vec<&String> = self.commands.keys().collect();
+        names.sort();
+
+        let mut report = format!("Command stats (uptime {}s):\n{}", self.started_at.elapsed().as_secs(), probe);
+        for name in names {
+            let stats = &self.commands[name];
+            report.push_str(&format!(
+                "`/{}`: {} ok / {} err ({:.1}%), p50 {}, p95 {}, p99 {}\n",
+                name,
+                stats.successes,
+                stats.errors,
+                stats.error_rate() * 100.0,
+                format_latency(stats.percentile(50.0)),
+                format_latency(stats.percentile(95.0)),
+                format_latency(stats.percentile(99.0))
+            ));
+            let stages: Vec<String> = Stage::ALL.iter()
+                .filter_map(|stage| {
+                    let p50 = stats.stage_percentile(*stage, 50.0)?;
+                    Some(format!("{} p50 {} p95 {}", stage.label(), format_latency(Some(p50)), format_latency(stats.stage_percentile(*stage, 95.0))))
+                })
+                .collect();
+            if !stages.is_empty() {
+                report.push_str(&format!("  ↳ {}\n", stages.join(", ")));
+            }
+        }
+        report
+    }
+}
+
+fn format_stages(stages: &HashMap<Stage, Duration>) -> String {
+    Stage::ALL.iter()
+        .map(|stage| match stages.get(stage) {
+            Some(elapsed) => format!("{} {}ms", stage.label(), elapsed.as_millis()),
+            None => format!("{} -", stage.label()),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn format_latency(latency: Option<Duration>) -> String {
+    match latency {
+        Some(latency) => format!("{}ms", latency.as_millis()),
+        None => "-".to_string(),
+    }
+}
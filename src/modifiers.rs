@@ -0,0 +1,51 @@
+use serde::{Serialize, Deserialize};
+use crate::types::HandType;
+
+// Whether a modifier raises low values or caps high ones
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, poise::ChoiceParameter)]
+pub enum ModifierKind {
+    Floor,
+    Ceiling,
+}
+
+// An equipment or skill effect on what hands are worth, e.g. a charm that has 1s count as 3s
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ValueModifier {
+    // What grants it, e.g. "Lucky Charm"
+    pub source: String,
+    pub kind: ModifierKind,
+    pub value: u8,
+}
+
+impl ValueModifier {
+    pub fn adjust(&self, value: u8) -> u8 {
+        match self.kind {
+            ModifierKind::Floor => value.max(self.value),
+            ModifierKind::Ceiling => value.min(self.value),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self.kind {
+            ModifierKind::Floor => format!("{}: values under {} count as {}", self.source, self.value, self.value),
+            ModifierKind::Ceiling => format!("{}: values over {} count as {}", self.source, self.value, self.value),
+        }
+    }
+}
+
+// Runs the hand's values through every modifier in order and names the ones that changed it
+pub fn apply(modifiers: &[ValueModifier], hand: &mut HandType) -> Vec<String> {
+    let mut applied = Vec::new();
+    for modifier in modifiers {
+        let mut changed = false;
+        for value in hand.values_mut() {
+            let adjusted = modifier.adjust(*value);
+            changed |= adjusted != *value;
+            *value = adjusted;
+        }
+        if changed {
+            applied.push(modifier.source.clone());
+        }
+    }
+    applied
+}
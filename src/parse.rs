@@ -0,0 +1,462 @@
+use std::fmt;
+use poise::serenity_prelude::UserId;
+use crate::dice::{CheckDice, Die};
+use crate::homebrew::HandPattern;
+use crate::types::{CardId, CardKey, CardMatch, CardQuery, CardType, DeckTemplate, ElementType, Suit};
+
+// A free-text argument that couldn't be read, pointing at the offending token
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub token: String,
+    // 1-based position of the token within the argument
+    pub position: usize,
+    pub reason: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.token.is_empty() {
+            write!(f, "{}", self.reason)
+        } else {
+            write!(f, "couldn't read '{}' at position {}: {}", self.token, self.position, self.reason)
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+fn error(token: &str, position: usize, reason: impl Into<String>) -> ParseError {
+    ParseError {
+        token: token.to_string(),
+        position,
+        reason: reason.into(),
+    }
+}
+
+// Cards in a hand by position, such as "1 3 5", "2,4" or "1-3", or by description, such as
+// "fire 5, ice 2", "5🔥" or "joker". Returned 0-based in the order given, every card distinct.
+pub fn cards(input: &str, hand: &[CardType]) -> Result<Vec<usize>, ParseError> {
+    let mut indices = Vec::new();
+    let mut position = 0;
+    // A value only belongs to the element before it up to the next comma, so "fire, 5" is two cards
+    for item in input.split(',') {
+        let mut words = item.split_whitespace().peekable();
+        while let Some(token) = words.next() {
+            position += 1;
+            let query = match card_label(token) {
+                Some(query) => query,
+                None if token.starts_with(|c: char| c.is_ascii_digit()) => {
+                    positions(token, position, hand.len(), &mut indices)?;
+                    continue;
+                },
+                None if token.eq_ignore_ascii_case("joker") || token == "🃏" => CardQuery::Joker,
+                None => {
+                    let element = element_named(token)
+                        .ok_or_else(|| error(token, position, "not a card position or description"))?;
+                    let value = words.next_if(|next| next.parse::<u8>().is_ok_and(|value| (1..=DeckTemplate::MAX_VALUE).contains(&value)));
+                    CardQuery::Element(element, value.and_then(|value| value.parse().ok()))
+                }
+            };
+            match query.resolve(hand, &indices) {
+                CardMatch::Found(index) => indices.push(index),
+                CardMatch::Missing => return Err(error(token, position, "there's no such card left in your hand")),
+                CardMatch::Ambiguous(candidates) => {
+                    let positions = candidates.iter().map(|i| (i + 1).to_string()).collect::<Vec<_>>().join(" or ");
+                    return Err(error(token, position, format!("that could be card {}, use its position or add a value", positions)));
+                }
+            }
+        }
+    }
+    if indices.is_empty() {
+        return Err(error("", 0, "no cards given"));
+    }
+    Ok(indices)
+}
+
+// A made-up hand such as "5🔥 5❄️ 3🪨 joker 7💨" or "fire 5, ice 5", for trying hands
+// out away from a combat. Cards have to be in the deck, as many times as it holds them.
+pub fn hand(input: &str, max_cards: usize, deck: &DeckTemplate) -> Result<Vec<CardType>, ParseError> {
+    let mut hand = Vec::new();
+    let mut position = 0;
+    for item in input.split(',') {
+        let mut words = item.split_whitespace().peekable();
+        while let Some(token) = words.next() {
+            position += 1;
+            let card = match card_label(token) {
+                Some(CardQuery::Element(element, Some(value))) => number_card(value, element),
+                _ if token.eq_ignore_ascii_case("joker") || token == "🃏" => {
+                    let slot = hand.iter().filter(|card| matches!(card, CardType::Joker { .. })).count() as u8;
+                    CardType::Joker { current_value: None, current_suit: None, symbol: "🃏".to_string(), slot, name: None, id: CardId::default() }
+                },
+                _ => {
+                    // Either "fire 5" or "5 fire"
+                    let (element, value) = match (element_named(token), token.parse::<u8>().ok()) {
+                        (Some(element), _) => (Some(element), words.next().and_then(|next| next.parse::<u8>().ok())),
+                        (None, Some(value)) => (words.next().and_then(element_named), Some(value)),
+                        (None, None) => (None, None),
+                    };
+                    match (element, value) {
+                        (Some(element), Some(value)) => number_card(value, element),
+                        _ => return Err(error(token, position, format!("cards look like 5🔥, fire 5 or joker, with values from {} to {}", deck.low, deck.high))),
+                    }
+                }
+            };
+            if let CardType::Number(Some(value), suit, _) = &card {
+                if !deck.values().contains(value) || !deck.elements.contains(&suit.element) {
+                    return Err(error(token, position, format!("there's no {} in the deck", card.label())));
+                }
+            }
+            let held = hand.iter().filter(|held: &&CardType| held.key() == card.key()).count();
+            if card.key() != CardKey::Joker && held >= deck.copies as usize {
+                let copies = if deck.copies == 1 { "one".to_string() } else { deck.copies.to_string() };
+                return Err(error(token, position, format!("the deck only has {} {}", copies, card.label())));
+            }
+            hand.push(card);
+        }
+    }
+    if hand.is_empty() {
+        return Err(error("", 0, "no cards given"));
+    }
+    if hand.len() > max_cards {
+        return Err(error("", 0, format!("a hand holds at most {} cards", max_cards)));
+    }
+    for (i, card) in hand.iter_mut().enumerate() {
+        card.set_id(CardId(i as u32));
+    }
+    Ok(hand)
+}
+
+fn number_card(value: u8, element: ElementType) -> CardType {
+    CardType::Number(Some(value), Suit::of(element), CardId::default())
+}
+
+// A card as the bot labels it, "5🔥", or typed as "5fire"
+fn card_label(token: &str) -> Option<CardQuery> {
+    let split = token.find(|c: char| !c.is_ascii_digit())?;
+    let (value, element) = token.split_at(split);
+    let value = value.parse().ok().filter(|value| (1..=DeckTemplate::MAX_VALUE).contains(value))?;
+    Some(CardQuery::Element(element_named(element)?, Some(value)))
+}
+
+// A position or range such as "2" or "1-3", added to the cards picked so far
+fn positions(token: &str, position: usize, hand_len: usize, indices: &mut Vec<usize>) -> Result<(), ParseError> {
+    let (first, last) = match token.split_once('-') {
+        Some((first, last)) => (position_number(first, token, position, hand_len)?, position_number(last, token, position, hand_len)?),
+        None => {
+            let number = position_number(token, token, position, hand_len)?;
+            (number, number)
+        }
+    };
+    if first > last {
+        return Err(error(token, position, "ranges go from the lower position to the higher one"));
+    }
+    for number in first..=last {
+        if indices.contains(&(number - 1)) {
+            return Err(error(token, position, format!("card {} is already listed", number)));
+        }
+        indices.push(number - 1);
+    }
+    Ok(())
+}
+
+// One 1-based position, reporting errors against the whole token it came from
+fn position_number(text: &str, token: &str, position: usize, hand_len: usize) -> Result<usize, ParseError> {
+    let number: usize = text.parse()
+        .map_err(|_| error(token, position, "not a card position"))?;
+    if number == 0 || number > hand_len {
+        return Err(error(token, position, format!("positions go from 1 to {}", hand_len)));
+    }
+    Ok(number)
+}
+
+// An element by name or by its card symbol
+pub fn element(input: &str) -> Result<ElementType, ParseError> {
+    let token = input.trim();
+    element_named(token).ok_or_else(|| error(token, 1, format!("elements are {}", ElementType::list_names())))
+}
+
+// Several elements such as "fire, ice" or "🔥 ❄️", each listed once
+pub fn elements(input: &str) -> Result<Vec<ElementType>, ParseError> {
+    let mut elements = Vec::new();
+    for (i, token) in input.split(|c: char| c == ',' || c.is_whitespace()).filter(|token| !token.is_empty()).enumerate() {
+        let element = element_named(token).ok_or_else(|| error(token, i + 1, format!("elements are {}", ElementType::list_names())))?;
+        if elements.contains(&element) {
+            return Err(error(token, i + 1, "that element is already listed"));
+        }
+        elements.push(element);
+    }
+    if elements.is_empty() {
+        return Err(error("", 0, "no elements given"));
+    }
+    Ok(elements)
+}
+
+fn element_named(token: &str) -> Option<ElementType> {
+    if let Some(element) = ElementType::from_name(token) {
+        return Some(element);
+    }
+    // Symbols may come with or without the emoji variation selector
+    let token = token.trim_end_matches('\u{fe0f}');
+    ElementType::ALL
+        .into_iter()
+        .find(|element| element.symbol().trim_end_matches('\u{fe0f}') == token)
+}
+
+// Two attribute dice and an optional modifier, such as "d8+d10+2", "d8 d10 -1" or "D6+D6"
+pub fn check_dice(input: &str) -> Result<CheckDice, ParseError> {
+    let mut dice = Vec::new();
+    let mut modifier: i64 = 0;
+    // Keep each sign with the term after it
+    let spaced = input.replace('+', " +").replace('-', " -");
+    for (i, token) in spaced.split_whitespace().filter(|t| *t != "+" && *t != "-").enumerate() {
+        let position = i + 1;
+        let term = token.trim_start_matches('+');
+        if let Some(sides) = term.strip_prefix(['d', 'D']) {
+            let die = sides.parse().ok()
+                .and_then(Die::from_sides)
+                .ok_or_else(|| error(token, position, "attribute dice are d6, d8, d10 or d12"))?;
+            if dice.len() == 2 {
+                return Err(error(token, position, "a check rolls exactly two dice"));
+            }
+            dice.push(die);
+        } else {
+            let value: i64 = term.parse()
+                .map_err(|_| error(token, position, "not a die or a modifier"))?;
+            // Each term and the running total stay small, so rolling can't overflow
+            let range = -CheckDice::MAX_MODIFIER..=CheckDice::MAX_MODIFIER;
+            modifier = Some(value)
+                .filter(|value| range.contains(value))
+                .map(|value| modifier + value)
+                .filter(|total| range.contains(total))
+                .ok_or_else(|| error(token, position, format!("modifiers go from -{0} to +{0}", CheckDice::MAX_MODIFIER)))?;
+        }
+    }
+    match dice[..] {
+        [first, second] => Ok(CheckDice { dice: [first, second], modifier }),
+        _ => Err(error("", 0, "a check rolls two attribute dice, e.g. d8+d10+1")),
+    }
+}
+
+// A homebrew hand's shape as a GM writes it: "3 of a kind", "straight of 5" or "flush of 4".
+// "straight of length 5" and "flush of size 4" read the same.
+pub fn hand_pattern(input: &str) -> Result<HandPattern, ParseError> {
+    let lowered = input.trim().to_lowercase();
+    let words: Vec<&str> = lowered.split_whitespace().collect();
+    let (pattern, size_token, position): (fn(usize) -> HandPattern, &str, usize) = match words[..] {
+        [size, "of", "a", "kind"] => (HandPattern::OfAKind, size, 1),
+        ["straight", "of", size] | ["straight", "of", "length", size] => (HandPattern::Straight, size, words.len()),
+        ["flush", "of", size] | ["flush", "of", "size", size] => (HandPattern::Flush, size, words.len()),
+        _ => return Err(error("", 0, "use \"N of a kind\", \"straight of N\" or \"flush of N\"")),
+    };
+    let size: usize = size_token.parse()
+        .map_err(|_| error(size_token, position, "not a number of cards"))?;
+    // No suit runs past the highest value any deck has, and it takes two cards to make anything
+    let most = DeckTemplate::MAX_VALUE as usize;
+    if !(2..=most).contains(&size) {
+        return Err(error(size_token, position, format!("a hand is 2 to {} cards", most)));
+    }
+    Ok(pattern(size))
+}
+
+// Raw unix seconds or a Discord timestamp mention such as <t:1700000000:F>
+pub fn timestamp(input: &str) -> Result<u64, ParseError> {
+    let token = input.trim();
+    let digits = token.strip_prefix("<t:")
+        .map(|rest| rest.trim_end_matches('>').split(':').next().unwrap_or(""))
+        .unwrap_or(token);
+    digits.parse()
+        .map_err(|_| error(token, 1, "use unix seconds or a <t:...> mention"))
+}
+
+// A user ID or mention, for users the slash command picker can't offer, e.g. ones who left
+pub fn user_id(input: &str) -> Result<UserId, ParseError> {
+    let token = input.trim();
+    let digits = token.strip_prefix("<@")
+        .map(|rest| rest.trim_start_matches('!').trim_end_matches('>'))
+        .unwrap_or(token);
+    digits.parse()
+        .map(UserId)
+        .map_err(|_| error(token, 1, "use a user ID or an @mention"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn card(value: u8, element: ElementType, id: u32) -> CardType {
+        CardType::Number(Some(value), Suit::of(element), CardId(id))
+    }
+
+    fn joker(id: u32) -> CardType {
+        CardType::Joker { current_value: None, current_suit: None, symbol: "🃏".to_string(), slot: 0, name: None, id: CardId(id) }
+    }
+
+    // 5🔥 2❄️ 5❄️ 🃏 7🪨
+    fn sample_hand() -> Vec<CardType> {
+        vec![
+            card(5, ElementType::Fire, 0),
+            card(2, ElementType::Ice, 1),
+            card(5, ElementType::Ice, 2),
+            joker(3),
+            card(7, ElementType::Earth, 4),
+        ]
+    }
+
+    #[test]
+    fn cards_by_position_and_description() {
+        let hand = sample_hand();
+        assert_eq!(cards("1 3", &hand), Ok(vec![0, 2]));
+        assert_eq!(cards("2,4", &hand), Ok(vec![1, 3]));
+        assert_eq!(cards("5🔥, ice 2", &hand), Ok(vec![0, 1]));
+        assert_eq!(cards("5fire joker", &hand), Ok(vec![0, 3]));
+        assert_eq!(cards("earth", &hand), Ok(vec![4]));
+    }
+
+    #[test]
+    fn cards_errors_point_at_the_token() {
+        let hand = sample_hand();
+        let err = cards("1 banana", &hand).unwrap_err();
+        assert_eq!((err.token.as_str(), err.position), ("banana", 2));
+
+        let err = cards("ice", &hand).unwrap_err();
+        assert_eq!((err.token.as_str(), err.position), ("ice", 1));
+        assert!(err.reason.contains("2 or 3"));
+
+        let err = cards("2 3🔥", &hand).unwrap_err();
+        assert_eq!((err.token.as_str(), err.position), ("3🔥", 2));
+
+        let err = cards(" , ", &hand).unwrap_err();
+        assert_eq!((err.token.as_str(), err.position), ("", 0));
+        assert_eq!(err.to_string(), "no cards given");
+    }
+
+    #[test]
+    fn position_ranges() {
+        let hand = sample_hand();
+        assert_eq!(cards("1-3", &hand), Ok(vec![0, 1, 2]));
+        assert_eq!(cards("4-4", &hand), Ok(vec![3]));
+        assert_eq!(cards("5, 1-2", &hand), Ok(vec![4, 0, 1]));
+
+        let err = cards("3-1", &hand).unwrap_err();
+        assert_eq!((err.token.as_str(), err.position), ("3-1", 1));
+        let err = cards("1-x", &hand).unwrap_err();
+        assert_eq!((err.token.as_str(), err.position), ("1-x", 1));
+        assert_eq!(err.reason, "not a card position");
+    }
+
+    #[test]
+    fn duplicate_positions() {
+        let hand = sample_hand();
+        let err = cards("2 2", &hand).unwrap_err();
+        assert_eq!((err.token.as_str(), err.position), ("2", 2));
+        assert_eq!(err.reason, "card 2 is already listed");
+
+        let err = cards("1-3, 2-4", &hand).unwrap_err();
+        assert_eq!((err.token.as_str(), err.position), ("2-4", 2));
+        assert_eq!(err.reason, "card 2 is already listed");
+    }
+
+    #[test]
+    fn out_of_range_positions() {
+        let hand = sample_hand();
+        for input in ["0", "6", "4-6", "0-2"] {
+            let err = cards(input, &hand).unwrap_err();
+            assert_eq!((err.token.as_str(), err.position), (input, 1));
+            assert_eq!(err.reason, "positions go from 1 to 5");
+        }
+        let err = cards("1 99999999999999999999999", &hand).unwrap_err();
+        assert_eq!(err.position, 2);
+    }
+
+    #[test]
+    fn positions_mixed_with_descriptions() {
+        let hand = sample_hand();
+        assert_eq!(cards("2 5🔥 4", &hand), Ok(vec![1, 0, 3]));
+        assert_eq!(cards("ice 5, 1-2", &hand), Ok(vec![2, 0, 1]));
+        // A description skips cards already picked by position
+        assert_eq!(cards("3 ice", &hand), Ok(vec![2, 1]));
+
+        let err = cards("1 5🔥", &hand).unwrap_err();
+        assert_eq!((err.token.as_str(), err.position), ("5🔥", 2));
+        let err = cards("5🔥 1", &hand).unwrap_err();
+        assert_eq!((err.token.as_str(), err.position), ("1", 2));
+    }
+
+    #[test]
+    fn element_names_and_symbols() {
+        assert_eq!(element("fire"), Ok(ElementType::Fire));
+        assert_eq!(element(" Ice "), Ok(ElementType::Ice));
+        assert_eq!(element("🔥"), Ok(ElementType::Fire));
+        assert_eq!(element("❄"), Ok(ElementType::Ice));
+        assert_eq!(element("❄️"), Ok(ElementType::Ice));
+        let err = element("mud").unwrap_err();
+        assert_eq!((err.token.as_str(), err.position), ("mud", 1));
+    }
+
+    #[test]
+    fn timestamp_seconds_and_mentions() {
+        assert_eq!(timestamp("1700000000"), Ok(1700000000));
+        assert_eq!(timestamp("<t:1700000000:F>"), Ok(1700000000));
+        assert_eq!(timestamp("<t:1700000000>"), Ok(1700000000));
+        assert!(timestamp("yesterday").is_err());
+        assert!(timestamp("<t:soon:F>").is_err());
+    }
+
+    #[test]
+    fn user_id_plain_and_mentions() {
+        assert_eq!(user_id("1234"), Ok(UserId(1234)));
+        assert_eq!(user_id("<@1234>"), Ok(UserId(1234)));
+        assert_eq!(user_id("<@!1234>"), Ok(UserId(1234)));
+        let err = user_id("@someone").unwrap_err();
+        assert_eq!((err.token.as_str(), err.position), ("@someone", 1));
+    }
+
+    #[test]
+    fn check_dice_with_modifiers() {
+        assert_eq!(check_dice("d8+d10+2"), Ok(CheckDice { dice: [Die::D8, Die::D10], modifier: 2 }));
+        assert_eq!(check_dice("d8 d10 -1"), Ok(CheckDice { dice: [Die::D8, Die::D10], modifier: -1 }));
+        assert_eq!(check_dice("D6+D6"), Ok(CheckDice { dice: [Die::D6, Die::D6], modifier: 0 }));
+        assert_eq!(check_dice("d12+1+2-4+d6"), Ok(CheckDice { dice: [Die::D12, Die::D6], modifier: -1 }));
+    }
+
+    #[test]
+    fn check_dice_errors() {
+        let err = check_dice("d8+d7").unwrap_err();
+        assert_eq!((err.token.as_str(), err.position), ("+d7", 2));
+
+        let err = check_dice("d8+d8+d8").unwrap_err();
+        assert_eq!((err.token.as_str(), err.position), ("+d8", 3));
+
+        let err = check_dice("d8+x").unwrap_err();
+        assert_eq!((err.token.as_str(), err.position), ("+x", 2));
+
+        let err = check_dice("d8+2").unwrap_err();
+        assert_eq!(err.position, 0);
+
+        assert_eq!(check_dice("d8+d8+99").map(|dice| dice.modifier), Ok(99));
+        assert_eq!(check_dice("d8+d8-99").map(|dice| dice.modifier), Ok(-99));
+        let err = check_dice("d8+d8+100").unwrap_err();
+        assert_eq!((err.token.as_str(), err.position), ("+100", 3));
+        let err = check_dice("d8+d8+60+60").unwrap_err();
+        assert_eq!((err.token.as_str(), err.position), ("+60", 4));
+        let err = check_dice("d8+d8-9223372036854775808").unwrap_err();
+        assert_eq!(err.position, 3);
+    }
+
+    #[test]
+    fn hand_patterns() {
+        assert_eq!(hand_pattern("3 of a kind"), Ok(HandPattern::OfAKind(3)));
+        assert_eq!(hand_pattern("Straight of 5"), Ok(HandPattern::Straight(5)));
+        assert_eq!(hand_pattern("straight of length 5"), Ok(HandPattern::Straight(5)));
+        assert_eq!(hand_pattern("flush of size 4"), Ok(HandPattern::Flush(4)));
+        assert_eq!(hand_pattern("straight of 21"), Ok(HandPattern::Straight(21)));
+
+        let err = hand_pattern("1 of a kind").unwrap_err();
+        assert_eq!((err.token.as_str(), err.position), ("1", 1));
+        let err = hand_pattern("straight of 22").unwrap_err();
+        assert_eq!((err.token.as_str(), err.position), ("22", 3));
+        let err = hand_pattern("flush of many").unwrap_err();
+        assert_eq!((err.token.as_str(), err.position), ("many", 3));
+        assert!(hand_pattern("full house").is_err());
+    }
+}
@@ -0,0 +1,56 @@
+use std::collections::{BTreeMap, BTreeSet};
+use crate::state::PlayerState;
+
+// A teamwork opportunity spanning several players' hands
+pub struct PartyCombo {
+    pub description: String,
+}
+
+// How many cards of each value a hand holds. Wild jokers are left out, they can
+// already complete the player's own hand and would make every value look reachable.
+fn value_counts(state: &PlayerState) -> BTreeMap<u8, usize> {
+    let mut counts = BTreeMap::new();
+    for card in &state.hand {
+        if let Some((Some(value), _)) = card.face() {
+            *counts.entry(value).or_insert(0) += 1;
+        }
+    }
+    counts
+}
+
+// Scan the table's hands for combos no single player holds alone
+pub fn find(players: &[(String, &PlayerState)]) -> Vec<PartyCombo> {
+    let counts: Vec<(&str, BTreeMap<u8, usize>)> = players.iter()
+        .map(|(name, state)| (name.as_str(), value_counts(state)))
+        .collect();
+    let mut combos = Vec::new();
+    // Players may be on decks with different values, so only look at values someone holds
+    let values: BTreeSet<u8> = counts.iter().flat_map(|(_, counts)| counts.keys().copied()).collect();
+
+    for value in values {
+        let holders: Vec<(&str, usize)> = counts.iter()
+            .filter_map(|(name, counts)| counts.get(&value).map(|&count| (*name, count)))
+            .collect();
+
+        // Two or more players each sitting on a pair of the same value
+        let pairs: Vec<&str> = holders.iter().filter(|(_, count)| *count >= 2).map(|(name, _)| *name).collect();
+        if pairs.len() >= 2 {
+            combos.push(PartyCombo {
+                description: format!("Mirrored pairs of {}: {}", value, pairs.join(", ")),
+            });
+        }
+
+        // Four of a value spread over several hands, a jackpot nobody can make alone
+        let total: usize = holders.iter().map(|(_, count)| count).sum();
+        if holders.len() >= 2 && total >= 4 && holders.iter().all(|(_, count)| *count < 4) {
+            let spread = holders.iter()
+                .map(|(name, count)| format!("{} ×{}", name, count))
+                .collect::<Vec<_>>()
+                .join(", ");
+            combos.push(PartyCombo {
+                description: format!("Split jackpot of {}: {}", value, spread),
+            });
+        }
+    }
+    combos
+}
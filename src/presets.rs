@@ -0,0 +1,59 @@
+use serde::{Serialize, Deserialize};
+use crate::arcana;
+use crate::types::{DeckKind, DeckTemplate, ElementType};
+
+// A ready-made deck a combat can be dealt from instead of the guild's own, for tables that
+// play along with a physical deck
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, poise::ChoiceParameter)]
+pub enum DeckPreset {
+    #[name = "Playing cards (52 and 2 jokers)"]
+    PlayingCards,
+    #[name = "Major arcana (21 trumps and The Fool)"]
+    MajorArcana,
+}
+
+// The playing-card suits and the element each one plays as, after the tarot suits they came from
+pub const PLAYING_CARD_SUITS: [(&str, &str, ElementType); 4] = [
+    ("♣️", "Clubs", ElementType::Fire),
+    ("♥️", "Hearts", ElementType::Ice),
+    ("♦️", "Diamonds", ElementType::Earth),
+    ("♠️", "Spades", ElementType::Air),
+];
+
+impl DeckPreset {
+    pub fn template(&self) -> DeckTemplate {
+        match self {
+            DeckPreset::PlayingCards => DeckTemplate {
+                jokers: 2,
+                low: 1,
+                high: 13,
+                copies: 1,
+                elements: PLAYING_CARD_SUITS.iter().map(|(_, _, element)| element.clone()).collect(),
+                kind: DeckKind::Elemental,
+            },
+            DeckPreset::MajorArcana => DeckTemplate {
+                jokers: 1,
+                low: 1,
+                high: arcana::MAJOR_ARCANA.len() as u8,
+                copies: 1,
+                elements: vec![ElementType::Fire, ElementType::Ice, ElementType::Earth, ElementType::Air],
+                kind: DeckKind::MajorArcana,
+            },
+        }
+    }
+
+    // How the physical cards read as the bot's, shown to the players dealt from it
+    pub fn describe(&self) -> String {
+        match self {
+            DeckPreset::PlayingCards => {
+                let suits = PLAYING_CARD_SUITS.iter()
+                    .map(|(symbol, name, element)| format!("{} {} are {}", symbol, name, element.symbol()))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!("Playing cards: {}. Aces are 1, Jacks 11, Queens 12 and Kings 13.", suits)
+            },
+            DeckPreset::MajorArcana => format!("Major arcana: each trump plays as its number in its element, and {} is wild. \
+                Sets are made of one element rather than one value and are worth their highest trump.", arcana::FOOL),
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use crate::events::{CombatEvent, EventKind};
+use crate::state::DeckEnd;
+use crate::types::CardType;
+
+// A combat's zones rebuilt from its event log, for debugging reports after the fact
+#[derive(Default)]
+pub struct ReplayedState {
+    pub deck: Vec<CardType>,
+    pub hand: Vec<CardType>,
+    pub discard: Vec<CardType>,
+    pub burned: Vec<CardType>,
+    pub turn: u32,
+    pub resolutions: u32,
+    pub events_applied: usize,
+    // The last event at or before the requested moment
+    pub last_event: Option<CombatEvent>,
+    // Places where the log disagrees with itself, worth a closer look
+    pub warnings: Vec<String>,
+}
+
+// Replay events in order up to and including the given unix time
+pub fn replay(events: &[CombatEvent], until: u64) -> ReplayedState {
+    let mut state = ReplayedState { turn: 1, ..Default::default() };
+
+    if !matches!(events.first().map(|e| &e.kind), Some(EventKind::Dealt { .. })) {
+        state.warnings.push("The log doesn't start with a deal, zones before the first recorded event are unknown".to_string());
+    }
+
+    for (position, event) in events.iter().enumerate().take_while(|(_, e)| e.at <= until) {
+        apply(&mut state, position, event);
+        state.events_applied += 1;
+        state.last_event = Some(event.clone());
+    }
+    state
+}
+
+fn apply(state: &mut ReplayedState, position: usize, event: &CombatEvent) {
+    match &event.kind {
+        EventKind::Dealt { deck } => {
+            state.deck = deck.clone();
+            state.hand.clear();
+            state.discard.clear();
+            state.burned.clear();
+        },
+        EventKind::Drew { cards } => {
+            for card in cards {
+                // Cards come off the end of the deck
+                match state.deck.pop() {
+                    Some(top) if top.key() == card.key() => {},
+                    Some(top) => state.warnings.push(format!(
+                        "Event {}: drew {} but {} was on top of the deck", position + 1, card.label(), top.label())),
+                    None => state.warnings.push(format!(
+                        "Event {}: drew {} from an empty deck", position + 1, card.label())),
+                }
+                state.hand.push(card.clone());
+            }
+        },
+        EventKind::Discarded { position: index, card }
+        | EventKind::Guarded { position: index, card, .. }
+        | EventKind::Boosted { position: index, card, .. } => {
+            if let Some(mut card) = take_from_hand(state, position, *index, card) {
+                card.clear_assignment();
+                state.discard.push(card);
+            }
+        },
+        EventKind::Burned { position: index, card } => {
+            if let Some(card) = take_from_hand(state, position, *index, card) {
+                state.burned.push(card);
+            }
+        },
+        EventKind::Tucked { position: index, card, end } => {
+            if let Some(mut card) = take_from_hand(state, position, *index, card) {
+                card.clear_assignment();
+                match end {
+                    DeckEnd::Top => state.deck.push(card),
+                    DeckEnd::Bottom => state.deck.insert(0, card),
+                }
+            }
+        },
+        EventKind::Gave { position: index, card, .. } => {
+            take_from_hand(state, position, *index, card);
+        },
+        EventKind::Received { card, .. } => {
+            state.hand.push(card.clone());
+        },
+        EventKind::Reshuffled { deck } => {
+            // The discard only goes back in once the deck is empty
+            state.deck = deck.clone();
+            state.discard.clear();
+        },
+        EventKind::ZonesRestored { deck, hand, discard, burned } => {
+            state.deck = deck.clone();
+            state.hand = hand.clone();
+            state.discard = discard.clone();
+            state.burned = burned.clone();
+        },
+        EventKind::HandReordered { hand } => {
+            state.hand = hand.clone();
+        },
+        EventKind::DeckReordered { deck } => {
+            state.deck = deck.clone();
+        },
+        EventKind::Searched { card, deck } => {
+            state.hand.push(card.clone());
+            state.deck = deck.clone();
+        },
+        EventKind::JokerAssigned { position: index, card } => match state.hand.get_mut(*index) {
+            Some(slot) => *slot = card.clone(),
+            None => state.warnings.push(format!(
+                "Event {}: assigned a joker at position {} in a hand of {}", position + 1, index + 1, state.hand.len())),
+        },
+        EventKind::Resolved { .. } => {
+            state.resolutions += 1;
+        },
+        EventKind::TurnEnded { turn } => {
+            state.turn = turn + 1;
+        },
+        EventKind::Mulligan { .. } | EventKind::HandRedrawn { .. } => {},
+    }
+}
+
+fn take_from_hand(state: &mut ReplayedState, position: usize, index: usize, expected: &CardType) -> Option<CardType> {
+    if index >= state.hand.len() {
+        state.warnings.push(format!(
+            "Event {}: removed card {} from a hand of {}", position + 1, index + 1, state.hand.len()));
+        return None;
+    }
+    let card = state.hand.remove(index);
+    if card.key() != expected.key() {
+        state.warnings.push(format!(
+            "Event {}: expected {} at position {} but found {}", position + 1, expected.label(), index + 1, card.label()));
+    }
+    Some(card)
+}
@@ -0,0 +1,127 @@
+use crate::config::GuildConfig;
+use crate::effects::HandKind;
+use crate::state::{Declaration, PlayerState};
+use crate::types::{CardKey, DeckTemplate, ElementType};
+
+pub struct StepResult {
+    pub name: String,
+    pub outcome: Result<(), String>,
+}
+
+// Hands rigged for each hand type, as (value, suit)
+const RIGGED_HANDS: &[(HandKind, [(u8, ElementType); 5])] = &[
+    (HandKind::MatchedEdge, [(5, ElementType::Fire), (5, ElementType::Ice), (1, ElementType::Earth), (2, ElementType::Air), (7, ElementType::Fire)]),
+    (HandKind::TripleThreat, [(3, ElementType::Fire), (3, ElementType::Ice), (3, ElementType::Earth), (1, ElementType::Air), (6, ElementType::Fire)]),
+    (HandKind::DoubleTrouble, [(2, ElementType::Fire), (2, ElementType::Ice), (6, ElementType::Earth), (6, ElementType::Air), (1, ElementType::Fire)]),
+    (HandKind::Jackpot, [(4, ElementType::Fire), (4, ElementType::Ice), (4, ElementType::Earth), (4, ElementType::Air), (1, ElementType::Fire)]),
+    (HandKind::MagicFlush, [(2, ElementType::Ice), (3, ElementType::Ice), (4, ElementType::Ice), (5, ElementType::Ice), (7, ElementType::Fire)]),
+    (HandKind::FullStatus, [(5, ElementType::Fire), (5, ElementType::Ice), (5, ElementType::Earth), (2, ElementType::Air), (2, ElementType::Fire)]),
+    (HandKind::BlindingFlush, [(4, ElementType::Earth), (5, ElementType::Air), (6, ElementType::Fire), (7, ElementType::Earth), (1, ElementType::Ice)]),
+];
+
+// Run a scripted combat against a throwaway state that never touches the manager
+pub fn run() -> Vec<StepResult> {
+    let config = GuildConfig::default();
+    let template = DeckTemplate::default();
+    let total_cards: usize = template.card_counts().values().sum();
+    let mut results = Vec::new();
+
+    let mut player = PlayerState::new(None, &template);
+    results.push(step("Deal", || {
+        player.draw_to_hand(5)?;
+        check(player.hand.len() == 5, format!("dealt {} cards instead of 5", player.hand.len()))?;
+        check_card_count(&player, total_cards)
+    }));
+
+    results.push(step("Mulligan", || {
+        player.discard_from_hand(4)?;
+        player.discard_from_hand(1)?;
+        player.draw_to_hand(2)?;
+        check(player.hand.len() == 5, format!("hand has {} cards after the mulligan", player.hand.len()))?;
+        check(player.discard.len() == 2, format!("discard has {} cards instead of 2", player.discard.len()))?;
+        check_card_count(&player, total_cards)
+    }));
+
+    for (kind, cards) in RIGGED_HANDS {
+        results.push(step(kind.label(), || {
+            rig_hand(&mut player, cards)?;
+            let hand = player.find_available_hands(&config)
+                .into_iter()
+                .find(|hand| HandKind::of(hand) == *kind)
+                .ok_or_else(|| format!("{} wasn't detected", kind.label()))?;
+
+            let targets = if hand.needs_targets() { vec!["Sandbag".to_string()] } else { Vec::new() };
+            let declaration = Declaration::new(hand, targets);
+            check(!declaration.effect_text(&config).is_empty(), "the effect text is empty".to_string())?;
+
+            player.consume_hand(&declaration.hand, &config)?;
+            check(player.hand.len() == 5, format!("hand has {} cards after resolving", player.hand.len()))?;
+            check_card_count(&player, total_cards)
+        }));
+    }
+
+    results.push(step("Reshuffle", || {
+        let reshuffles = player.reshuffles;
+        player.discard_from_hand(0)?;
+        let mut deck = std::mem::take(&mut player.deck.cards);
+        player.discard.append(&mut deck);
+        player.draw_to_hand(1)?;
+        check(player.reshuffles == reshuffles + 1, "the discard wasn't shuffled back in".to_string())?;
+        check(player.discard.is_empty(), format!("{} cards were left in the discard", player.discard.len()))?;
+        check_card_count(&player, total_cards)
+    }));
+
+    results.push(step("Undo", || {
+        rig_hand(&mut player, &RIGGED_HANDS[0].1)?;
+        let before: Vec<CardKey> = player.hand.iter().map(|card| card.key()).collect();
+        let hand = player.find_available_hands(&config)
+            .into_iter()
+            .next()
+            .ok_or("no hand to resolve")?;
+
+        player.last_resolution = Some(player.snapshot_zones());
+        player.consume_hand(&hand, &config)?;
+        player.reopen_last_resolution()?;
+        let after: Vec<CardKey> = player.hand.iter().map(|card| card.key()).collect();
+        check(before == after, "the hand wasn't restored".to_string())?;
+        check_card_count(&player, total_cards)
+    }));
+
+    results
+}
+
+fn step(name: &str, run: impl FnOnce() -> Result<(), String>) -> StepResult {
+    StepResult {
+        name: name.to_string(),
+        outcome: run(),
+    }
+}
+
+fn check(ok: bool, failure: String) -> Result<(), String> {
+    if ok { Ok(()) } else { Err(failure) }
+}
+
+// No step may lose or duplicate cards
+fn check_card_count(player: &PlayerState, expected: usize) -> Result<(), String> {
+    let count = player.all_cards().count();
+    check(count == expected, format!("{} cards in play instead of {}", count, expected))
+}
+
+// Swap the hand for specific cards, taken from the deck or the discard
+fn rig_hand(player: &mut PlayerState, cards: &[(u8, ElementType)]) -> Result<(), String> {
+    let mut hand = std::mem::take(&mut player.hand);
+    player.deck.cards.append(&mut hand);
+
+    for (value, element) in cards {
+        let key = CardKey::Number(*value, element.clone());
+        let card = if let Some(position) = player.deck.cards.iter().position(|card| card.key() == key) {
+            player.deck.cards.remove(position)
+        } else if let Some(position) = player.discard.iter().position(|card| card.key() == key) {
+            player.discard.remove(position)
+        } else {
+            return Err(format!("{} of {} is missing from the sandbox deck", value, element.name()));
+        };
+        player.hand.push(card);
+    }
+    Ok(())
+}
@@ -0,0 +1,80 @@
+use poise::serenity_prelude::{ChannelId, GatewayIntents};
+
+// Operator settings from the environment, re-read by /admin reload_config
+#[derive(Clone)]
+pub struct Settings {
+    pub token: String,
+    // Optional channel for operational alerts such as error-rate spikes
+    pub alert_channel: Option<ChannelId>,
+    // Log a per-stage timing breakdown of every command
+    pub trace_stages: bool,
+    // Where the HTTP API listens, e.g. 127.0.0.1:8080. Unset keeps it off.
+    pub api_addr: Option<String>,
+    // Watch for members leaving mid-combat. Needs the Server Members intent enabled
+    // in the developer portal, or the gateway refuses to connect.
+    pub track_members: bool,
+}
+
+impl Settings {
+    pub fn from_env() -> Result<Self, String> {
+        let token = std::env::var("DISCORD_TOKEN").map_err(|_| "Missing DISCORD_TOKEN".to_string())?;
+        let alert_channel = std::env::var("ALERT_CHANNEL_ID")
+            .ok()
+            .and_then(|id| id.parse::<u64>().ok())
+            .map(ChannelId);
+
+        Ok(Settings {
+            token,
+            alert_channel,
+            trace_stages: std::env::var("TRACE_COMMAND_STAGES").is_ok(),
+            api_addr: std::env::var("API_ADDR").ok().filter(|addr| !addr.trim().is_empty()),
+            track_members: std::env::var("TRACK_MEMBER_LEAVES").is_ok(),
+        })
+    }
+    
+    pub fn intents(&self) -> GatewayIntents {
+        let intents = GatewayIntents::non_privileged();
+        if self.track_members {
+            intents | GatewayIntents::GUILD_MEMBERS
+        } else {
+            intents
+        }
+    }
+
+    // Pick up edits to .env, letting them replace values loaded at startup.
+    // dotenv() never overrides existing variables, the iterator is the only way to.
+    #[allow(deprecated)]
+    pub fn reload() -> Result<Self, String> {
+        if let Ok(vars) = dotenv::dotenv_iter() {
+            for var in vars {
+                let (key, value) = var.map_err(|e| format!("Couldn't read .env: {}", e))?;
+                std::env::set_var(key, value);
+            }
+        }
+        Settings::from_env()
+    }
+
+    // What changed between two sets of settings, without leaking the token
+    pub fn describe_changes(&self, new: &Settings) -> Vec<String> {
+        let mut changes = Vec::new();
+        if self.token != new.token {
+            changes.push("Bot token rotated, reconnecting to the gateway".to_string());
+        }
+        if self.alert_channel != new.alert_channel {
+            changes.push(match new.alert_channel {
+                Some(channel) => format!("Alerts now go to <#{}>", channel),
+                None => "Alerts are now only logged".to_string(),
+            });
+        }
+        if self.trace_stages != new.trace_stages {
+            changes.push(format!("Stage tracing {}", if new.trace_stages { "on" } else { "off" }));
+        }
+        if self.api_addr != new.api_addr {
+            changes.push("The API address changed, it takes effect after a restart".to_string());
+        }
+        if self.track_members != new.track_members {
+            changes.push(format!("Member tracking {} after the next reconnect", if new.track_members { "on" } else { "off" }));
+        }
+        changes
+    }
+}
@@ -0,0 +1,123 @@
+use rand::seq::SliceRandom;
+use rand::{Rng, RngCore};
+use serde::{Serialize, Deserialize};
+use crate::types::CardType;
+
+// A way of putting a deck in random order. Cards are drawn from the end.
+pub trait Shuffler {
+    fn shuffle(&self, cards: &mut Vec<CardType>, rng: &mut dyn RngCore);
+}
+
+// Every order equally likely
+pub struct FisherYates;
+
+impl Shuffler for FisherYates {
+    fn shuffle(&self, cards: &mut Vec<CardType>, rng: &mut dyn RngCore) {
+        cards.shuffle(rng);
+    }
+}
+
+// Riffle shuffles as a person would do them: cut near the middle, then drop cards from
+// either half in proportion to what's left in it (the Gilbert-Shannon-Reeds model).
+// A few passes leave some runs of the old order together, which is the point.
+pub struct Riffle {
+    pub passes: usize,
+}
+
+impl Shuffler for Riffle {
+    fn shuffle(&self, cards: &mut Vec<CardType>, rng: &mut dyn RngCore) {
+        for _ in 0..self.passes {
+            let cut = (0..cards.len()).filter(|_| rng.gen_bool(0.5)).count();
+            let mut right = cards.split_off(cut);
+            let mut left = std::mem::take(cards);
+            // Popping from the back, so flip the halves to interleave front to front
+            left.reverse();
+            right.reverse();
+            while !left.is_empty() || !right.is_empty() {
+                let from_left = rng.gen_range(0..left.len() + right.len()) < left.len();
+                let pile = if from_left { &mut left } else { &mut right };
+                cards.extend(pile.pop());
+            }
+        }
+    }
+}
+
+// Leaves the deck as built so every draw is predictable, for reproducing bugs
+#[cfg(feature = "fixtures")]
+pub struct Stacked;
+
+#[cfg(feature = "fixtures")]
+impl Shuffler for Stacked {
+    fn shuffle(&self, _cards: &mut Vec<CardType>, _rng: &mut dyn RngCore) {}
+}
+
+const RIFFLE_PASSES: usize = 3;
+
+// Shuffle a guild picks for its combats
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, poise::ChoiceParameter)]
+pub enum ShuffleStrategy {
+    Riffle,
+    // Only development builds offer an unshuffled deck, a table could be dealt it by mistake
+    #[cfg(feature = "fixtures")]
+    Stacked,
+    // Last so it can also be what a stacked deck saved by a development build loads as
+    #[default]
+    #[serde(other)]
+    Uniform,
+}
+
+impl ShuffleStrategy {
+    pub fn shuffler(&self) -> Box<dyn Shuffler> {
+        match self {
+            ShuffleStrategy::Uniform => Box::new(FisherYates),
+            ShuffleStrategy::Riffle => Box::new(Riffle { passes: RIFFLE_PASSES }),
+            #[cfg(feature = "fixtures")]
+            ShuffleStrategy::Stacked => Box::new(Stacked),
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            ShuffleStrategy::Uniform => "a fair shuffle, every order equally likely",
+            ShuffleStrategy::Riffle => "a few hand riffles, so some cards stay clumped together",
+            #[cfg(feature = "fixtures")]
+            ShuffleStrategy::Stacked => "no shuffling at all, for debugging",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use crate::types::{CardId, ElementType, Suit};
+
+    #[test]
+    fn fisher_yates_is_uniform() {
+        const CARDS: usize = 5;
+        const SHUFFLES: usize = 60_000;
+        let deck: Vec<CardType> = (0..CARDS)
+            .map(|i| CardType::Number(Some(i as u8 + 1), Suit::of(ElementType::Fire), CardId(i as u32)))
+            .collect();
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+
+        // How often each card lands in each position
+        let mut counts = [[0usize; CARDS]; CARDS];
+        for _ in 0..SHUFFLES {
+            let mut cards = deck.clone();
+            FisherYates.shuffle(&mut cards, &mut rng);
+            for (position, card) in cards.iter().enumerate() {
+                counts[card.id().0 as usize][position] += 1;
+            }
+        }
+
+        // Chi-squared over the 25 cells, 16 degrees of freedom. 39.25 is the 0.1% critical value,
+        // so a fair shuffle only fails with a seed that unlucky.
+        let expected = (SHUFFLES / CARDS) as f64;
+        let chi_squared: f64 = counts.iter().flatten()
+            .map(|&observed| (observed as f64 - expected).powi(2) / expected)
+            .sum();
+        assert!(chi_squared < 39.25, "chi-squared {} for {:?}", chi_squared, counts);
+    }
+}
@@ -0,0 +1,23 @@
+// Optional rule modules that a table can switch off in its GuildConfig
+pub trait SkillModule: Sync {
+    fn name(&self) -> &'static str;
+    fn description(&self) -> &'static str;
+}
+
+pub struct FabulaPoints;
+
+impl SkillModule for FabulaPoints {
+    fn name(&self) -> &'static str {
+        "fabula_points"
+    }
+
+    fn description(&self) -> &'static str {
+        "Track Fabula Points and spend them to reroll a card or re-open your last resolution"
+    }
+}
+
+pub static SKILL_MODULES: &[&dyn SkillModule] = &[&FabulaPoints];
+
+pub fn find_module(name: &str) -> Option<&'static dyn SkillModule> {
+    SKILL_MODULES.iter().copied().find(|m| m.name() == name)
+}
@@ -1,360 +1,1046 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::{BTreeMap, HashMap, HashSet}, sync::Arc};
 use std::fs;
 use std::time::Instant;
 use serde::{Serialize, Deserialize};
-use crate::types::{CardType, Deck, ElementType, HandType};
-use poise::serenity_prelude::UserId;
+use crate::types::{format_element_list, CardId, CardKey, CardType, Deck, DeckTemplate, ElementType, HandType, Suit};
+use crate::config::{ActionBudget, GuildConfig};
+use crate::dice::CheckDice;
+use crate::approvals::{ApprovalAction, ApprovalRequest};
+use crate::archive::{CombatSummary, MAX_ARCHIVED_COMBATS};
+use crate::character::{sanitize_display_name, CharacterProfile, JokerSlot};
+use crate::effects::{format_number, HandEffect, HandKind};
+use crate::detect::{self, DetectionCache, DetectionKey, HandDetector, HomebrewDetector};
+use crate::events::{unix_now, CombatEvent, EventKind, EventLog};
+use crate::modifiers::{self, ValueModifier};
+use crate::shuffle::ShuffleStrategy;
+use crate::presets::DeckPreset;
+use poise::serenity_prelude::{ChannelId, GuildId, MessageId, UserId};
 use tokio::sync::Mutex;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct PlayerState {
+    // Guild the combat was started in, if any
+    #[serde(default)]
+    pub guild_id: Option<GuildId>,
     pub deck: Deck,
     pub hand: Vec<CardType>,    
     pub discard: Vec<CardType>, 
+    // Cards removed from the combat for good, never shuffled back in
+    #[serde(default)]
+    pub burned: Vec<CardType>,
+    // Zones as they were right before the last resolution, so it can be re-opened
+    #[serde(default)]
+    pub last_resolution: Option<ZoneSnapshot>,
+    // Other players' help waiting to be credited on the next resolution
+    #[serde(default)]
+    pub pending_contributions: Vec<Contribution>,
+    // Equipment and skill effects on hand values, granted by the GM
+    #[serde(default)]
+    pub modifiers: Vec<ValueModifier>,
+    #[serde(default)]
+    pub turn: u32,
+    #[serde(default)]
+    pub actions_used: ActionsUsed,
+    // A hand that has been declared but not yet committed
+    #[serde(default)]
+    pub declaration: Option<Declaration>,
+    // Times the discard was shuffled back into the deck this combat
+    #[serde(default)]
+    pub reshuffles: u32,
+    #[serde(default)]
+    pub log: EventLog,
+    // Times each joker slot was drawn this combat
+    #[serde(default)]
+    pub joker_appearances: Vec<u32>,
+    // The player left the server; the combat is frozen until the GM reassigns or archives it
+    #[serde(default)]
+    pub orphaned: bool,
+    // Cards the hand is dealt and refilled to, fixed when the combat starts
+    #[serde(default = "default_hand_size")]
+    pub hand_size: usize,
+    #[serde(default)]
+    pub mulligans: u32,
+    #[serde(default)]
+    pub started_at: u64,
+    // Hands resolved this combat, by hand label
+    #[serde(default)]
+    pub resolutions: BTreeMap<String, u32>,
+    // How the deck is shuffled, fixed when the combat starts
+    #[serde(default)]
+    pub shuffle: ShuffleStrategy,
+    // What the deck was built from, kept when the guild's template changes unless it's rebuilt
+    #[serde(default)]
+    pub deck_template: DeckTemplate,
+    // Set when the combat was dealt from a ready-made deck, which guild deck changes leave alone
+    #[serde(default)]
+    pub deck_preset: Option<DeckPreset>,
+    #[serde(default)]
+    pub resolution_list: Option<ResolutionList>,
+    // Whether the whole hand has been thrown away and redrawn, allowed once per combat
+    #[serde(default)]
+    pub hand_redrawn: bool,
+    // Order the hand is put back in after every draw, copied from the player's preference
+    #[serde(default)]
+    pub hand_sort: Option<HandSort>,
+    #[serde(skip)]
+    detection: DetectionCache,
+}
+
+// A hand the player doesn't hold yet but one more card would make
+pub struct NearHand {
+    pub hand: HandType,
+    // Cards that would complete it, ignoring wild jokers which complete anything
+    pub needs: Vec<CardType>,
+    pub left_in_deck: usize,
+}
+
+// The numbered hands last shown by /view_possible_resolutions, so /resolve_hand
+// resolves exactly what the player saw
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ResolutionList {
+    // The hand the list was built from, each card as it showed then
+    pub hand: Vec<(CardId, String)>,
+    pub hands: Vec<HandType>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Declaration {
+    pub hand: HandType,
+    pub element: Option<ElementType>,
+    pub targets: Vec<String>,
+    // The resolving character's affinity when the hand was declared
+    #[serde(default)]
+    pub affinity: Option<ElementType>,
+    // Dice for the weapon attack a Matched Edge goes with, rolled when it commits
+    #[serde(default)]
+    pub attack: Option<CheckDice>,
+}
+
+// Extra damage for resolving in the character's affine element, with the affinity_bonus feature
+pub const AFFINITY_BONUS_DAMAGE: i64 = 2;
+
+// One entry of the element picker: the element and what it would do to each target
+pub struct ElementPreview {
+    pub element: ElementType,
+    pub summary: String,
+}
+
+impl Declaration {
+    pub fn new(hand: HandType, targets: Vec<String>) -> Self {
+        // No need to ask for an element when there's only one to pick
+        let element = match hand.suits() {
+            [only] => Some(only.clone()),
+            _ => None,
+        };
+        Declaration { hand, element, targets, affinity: None, attack: None }
+    }
+
+    // Damage in one of several elements has to be locked in before the hand resolves
+    pub fn needs_element(&self, config: &GuildConfig) -> bool {
+        self.element.is_none()
+            && self.hand.suits().len() > 1
+            && self.effect(config).damage.is_some()
+    }
+
+    // Whether the hand resolves in the character's element, or could if the element isn't picked yet
+    pub fn matches_affinity(&self) -> bool {
+        let Some(affinity) = &self.affinity else {
+            return false;
+        };
+        match &self.element {
+            Some(element) => element == affinity,
+            None => self.hand.suits().contains(affinity),
+        }
+    }
+
+    fn affinity_bonus(&self, element: &ElementType, config: &GuildConfig) -> i64 {
+        if config.feature_enabled("affinity_bonus") && self.affinity.as_ref() == Some(element) {
+            AFFINITY_BONUS_DAMAGE
+        } else {
+            0
+        }
+    }
+
+    // Final damage per element the hand allows, after each target's affinities
+    pub fn element_previews(&self, config: &GuildConfig) -> Vec<ElementPreview> {
+        let effect = HandEffect::resolve(&self.hand, &config.power(), config.hand_override(&self.hand));
+        self.hand.suits().iter()
+            .map(|element| {
+                let mut effect = effect.clone();
+                effect.add_damage(self.affinity_bonus(element, config));
+                let per_target: Vec<String> = self.targets.iter()
+                    .map(|target| {
+                        let affinity = config.affinity(target, element);
+                        let damage = format_number(effect.damage_against(affinity));
+                        let amount = match affinity {
+                            Some(affinity) => format!("{} ({})", damage, affinity.note()),
+                            None => damage,
+                        };
+                        if self.targets.len() == 1 { amount } else { format!("{} {}", target, amount) }
+                    })
+                    .collect();
+                let summary = if per_target.is_empty() {
+                    format_number(effect.damage)
+                } else {
+                    per_target.join(", ")
+                };
+                ElementPreview { element: element.clone(), summary }
+            })
+            .collect()
+    }
+
+    // What the hand does at this table, with the affinity bonus if it applies
+    pub fn effect(&self, config: &GuildConfig) -> HandEffect {
+        let mut effect = HandEffect::resolve(&self.hand, &config.power(), config.hand_override(&self.hand));
+        if let Some(affinity) = self.affinity.as_ref().filter(|_| self.matches_affinity()) {
+            effect.add_damage(self.affinity_bonus(affinity, config));
+        }
+        effect
+    }
+
+    pub fn effect_text(&self, config: &GuildConfig) -> String {
+        let elements_str = match &self.element {
+            Some(element) => format_element_list(std::slice::from_ref(element)),
+            None => format_element_list(self.hand.suits()),
+        };
+        let mut text = self.effect(config).render(&self.hand, &elements_str);
+        if !self.targets.is_empty() {
+            text.push_str(&format!("\nTargets: {}", self.targets.join(", ")));
+        }
+        text
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, poise::ChoiceParameter)]
+pub enum ActionKind {
+    Draw,
+    Resolution,
+    Reaction,
+}
+
+impl ActionKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ActionKind::Draw => "draw",
+            ActionKind::Resolution => "resolution",
+            ActionKind::Reaction => "reaction",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct ActionsUsed {
+    pub draws: u32,
+    pub resolutions: u32,
+    pub reactions: u32,
+}
+
+impl ActionsUsed {
+    pub fn get(&self, kind: ActionKind) -> u32 {
+        match kind {
+            ActionKind::Draw => self.draws,
+            ActionKind::Resolution => self.resolutions,
+            ActionKind::Reaction => self.reactions,
+        }
+    }
+
+    fn get_mut(&mut self, kind: ActionKind) -> &mut u32 {
+        match kind {
+            ActionKind::Draw => &mut self.draws,
+            ActionKind::Resolution => &mut self.resolutions,
+            ActionKind::Reaction => &mut self.reactions,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, poise::ChoiceParameter)]
+pub enum ContributionKind {
+    Assist,
+    Trap,
+}
+
+impl ContributionKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ContributionKind::Assist => "assist",
+            ContributionKind::Trap => "trap",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Contribution {
+    pub contributor: UserId,
+    pub contributor_name: String,
+    pub kind: ContributionKind,
+}
+
+// Where a player's hand goes after commands that deal or show it
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, poise::ChoiceParameter)]
+pub enum HandDelivery {
+    #[default]
+    Channel,
+    #[name = "Direct message"]
+    DirectMessage,
+}
+
+// Orders a hand can be sorted in
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, poise::ChoiceParameter)]
+pub enum HandSort {
+    Value,
+    Suit,
+}
+
+impl HandSort {
+    pub fn label(&self) -> &'static str {
+        match self {
+            HandSort::Value => "value",
+            HandSort::Suit => "suit",
+        }
+    }
+
+    // Wild jokers go last so they don't jump around; ties keep the order the cards were drawn in
+    fn key(&self, card: &CardType) -> (bool, u8, u8) {
+        let Some((value, element)) = card.face() else {
+            return (true, 0, 0);
+        };
+        let value = value.unwrap_or(0);
+        let element = element.sort_order();
+        match self {
+            HandSort::Value => (false, value, element),
+            HandSort::Suit => (false, element, value),
+        }
+    }
+}
+
+// Where a card goes back into the deck
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, poise::ChoiceParameter)]
+pub enum DeckEnd {
+    #[default]
+    Top,
+    Bottom,
+}
+
+impl DeckEnd {
+    pub fn label(&self) -> &'static str {
+        match self {
+            DeckEnd::Top => "top",
+            DeckEnd::Bottom => "bottom",
+        }
+    }
+}
+
+// A player's own settings, kept across combats
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Preferences {
+    #[serde(default)]
+    pub hand_delivery: HandDelivery,
+    // Order new combats keep the hand in after every draw, if any
+    #[serde(default)]
+    pub hand_sort: Option<HandSort>,
+}
+
+// A bot message with live buttons, collapsed by housekeeping if its collector is gone
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PendingInteraction {
+    pub channel_id: ChannelId,
+    pub message_id: MessageId,
+    pub expires_at: u64,
+    // What the message says once its buttons are removed
+    pub expired_text: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ZoneSnapshot {
+    pub deck: Vec<CardType>,
+    pub hand: Vec<CardType>,
+    pub discard: Vec<CardType>,
+    #[serde(default)]
+    pub burned: Vec<CardType>,
 }
 
 impl PlayerState {
-    pub fn new() -> Self {
-        let mut deck = Deck::new();
-        deck.shuffle();
-        PlayerState {
+    pub fn new(guild_id: Option<GuildId>, template: &DeckTemplate) -> Self {
+        Self::with_shuffle(guild_id, template, ShuffleStrategy::default())
+    }
+
+    pub fn with_shuffle(guild_id: Option<GuildId>, template: &DeckTemplate, shuffle: ShuffleStrategy) -> Self {
+        let mut deck = Deck::from_template(template);
+        deck.shuffle_with(shuffle.shuffler().as_ref());
+        let mut state = PlayerState {
+            guild_id,
             deck,
             hand: Vec::new(),
             discard: Vec::new(),
-        }
+            burned: Vec::new(),
+            last_resolution: None,
+            pending_contributions: Vec::new(),
+            modifiers: Vec::new(),
+            turn: 1,
+            actions_used: ActionsUsed::default(),
+            declaration: None,
+            reshuffles: 0,
+            log: EventLog::default(),
+            joker_appearances: Vec::new(),
+            orphaned: false,
+            hand_size: DEFAULT_HAND_SIZE,
+            mulligans: 0,
+            started_at: unix_now(),
+            resolutions: BTreeMap::new(),
+            shuffle,
+            deck_template: template.clone(),
+            deck_preset: None,
+            resolution_list: None,
+            hand_redrawn: false,
+            hand_sort: None,
+            detection: DetectionCache::default(),
+        };
+        state.record(EventKind::Dealt { deck: state.deck.cards.clone() });
+        state
     }
 
     pub fn draw_to_hand(&mut self, num_cards: usize) -> Result<(), String> {
+        let mut drawn = Vec::new();
+        let mut result = Ok(());
         for _ in 0..num_cards {
+            // Only the discard goes back in, burned cards stay out for the rest of the combat
+            if self.deck.cards.is_empty() && !self.discard.is_empty() {
+                // Log draws before the reshuffle so the history stays in order
+                if !drawn.is_empty() {
+                    self.record(EventKind::Drew { cards: std::mem::take(&mut drawn) });
+                }
+                self.reshuffle_discard();
+            }
+            
             if let Some(card) = self.deck.cards.pop() {
-                self.hand.push(card);
-            } else {
-                if !self.discard.is_empty() {
-                    self.deck.cards.append(&mut self.discard);
-                    self.deck.shuffle();
-                    
-                    if let Some(card) = self.deck.cards.pop() {
-                        self.hand.push(card);
-                    } else {
-                        return Err("No cards left in deck or discard".to_string());
+                if let CardType::Joker { slot, .. } = &card {
+                    let slot = *slot as usize;
+                    if self.joker_appearances.len() <= slot {
+                        self.joker_appearances.resize(slot + 1, 0);
                     }
-                } else {
-                    return Err("No cards left in deck or discard".to_string());
+                    self.joker_appearances[slot] += 1;
                 }
+                drawn.push(card.clone());
+                self.hand.push(card);
+            } else {
+                result = Err("No cards left in deck or discard".to_string());
+                break;
             }
         }
-        Ok(())
+        if !drawn.is_empty() {
+            self.record(EventKind::Drew { cards: drawn });
+            self.keep_sorted();
+        }
+        result
     } 
     
+    // Reorder the hand; card IDs move with their cards, so declarations and resolution lists still hold
+    pub fn sort_hand(&mut self, sort: HandSort) {
+        let before: Vec<CardId> = self.hand.iter().map(|card| card.id()).collect();
+        self.hand.sort_by_key(|card| sort.key(card));
+        if self.hand.iter().map(|card| card.id()).ne(before) {
+            self.record(EventKind::HandReordered { hand: self.hand.clone() });
+        }
+    }
+    
+    fn keep_sorted(&mut self) {
+        if let Some(sort) = self.hand_sort {
+            self.sort_hand(sort);
+        }
+    }
+    
+    // Shuffle the discard back into the deck, returning how many cards went back in
+    pub fn reshuffle_discard(&mut self) -> usize {
+        let count = self.discard.len();
+        self.deck.cards.append(&mut self.discard);
+        self.deck.shuffle_with(self.shuffle.shuffler().as_ref());
+        self.reshuffles += 1;
+        self.record(EventKind::Reshuffled { deck: self.deck.cards.clone() });
+        count
+    }
+    
     pub fn discard_from_hand(&mut self, card_index: usize) -> Result<(), String> {
         if card_index >= self.hand.len() {
             return Err("Card index out of bounds".to_string());
         }
         
-        let card = self.hand.remove(card_index);
+        let mut card = self.hand.remove(card_index);
+        self.record(EventKind::Discarded { position: card_index, card: card.clone() });
+        card.clear_assignment();
         self.discard.push(card);
         Ok(())
     } 
     
-    pub fn find_possible_hands(&self) -> Vec<HandType> {
-        let mut hands = Vec::new();
-        let hand_len = self.hand.len();
-        
-        // Track pairs we've already seen to avoid duplicates
-        let mut seen_double_troubles = Vec::new();
-        
-        // Check for jackpot (4 of a kind)
-        if hand_len >= 4 {
-            for i in 0..hand_len {
-                for j in (i + 1)..hand_len {
-                    for k in (j + 1)..hand_len {
-                        for l in (k + 1)..hand_len {
-                            if let Some(hand_type) = self.check_jackpot(i, j, k, l) {
-                                hands.push(hand_type);
-                            }
-                        }
-                    }
-                }
-            }
+    // Discard a card as a reaction to soften a blow. Returns the card as it was played and the damage it stops.
+    pub fn guard_with(&mut self, card_index: usize, budget: &ActionBudget) -> Result<(CardType, u8), String> {
+        if card_index >= self.hand.len() {
+            return Err("Card index out of bounds".to_string());
+        }
+        self.use_action(ActionKind::Reaction, budget)?;
+        self.spend_card(card_index, |position, card, reduction| EventKind::Guarded { position, card, reduction })
+    }
+    
+    // Discard a card to add its value to a check. Returns the card as it was played and the bonus.
+    pub fn boost_with(&mut self, card_index: usize) -> Result<(CardType, u8), String> {
+        if card_index >= self.hand.len() {
+            return Err("Card index out of bounds".to_string());
+        }
+        self.spend_card(card_index, |position, card, bonus| EventKind::Boosted { position, card, bonus })
+    }
+    
+    fn spend_card(&mut self, card_index: usize, event: impl FnOnce(usize, CardType, u8) -> EventKind) -> Result<(CardType, u8), String> {
+        let card = self.hand.remove(card_index);
+        let value = card.spent_value(self.deck_template.high);
+        self.record(event(card_index, card.clone(), value));
+        let mut discarded = card.clone();
+        discarded.clear_assignment();
+        self.discard.push(discarded);
+        Ok((card, value))
+    }
+    
+    // Swap cards at the given hand positions for fresh ones, within the table's limits
+    pub fn mulligan(&mut self, mut indices: Vec<usize>, config: &GuildConfig) -> Result<(), String> {
+        if let Some(limit) = config.mulligan_limit.filter(|&limit| self.mulligans >= limit) {
+            return Err(format!("You've used your {} mulligan(s) for this combat.", limit));
         }
+        self.use_action(ActionKind::Draw, &config.action_budget)?;
         
-        // Check for double trouble (2 pairs)
-        if hand_len >= 4 {
-            for i in 0..hand_len {
-                for j in (i + 1)..hand_len {
-                    if let Some((value1, _)) = self.check_pair_value(i, j) {
-                        for k in 0..hand_len {
-                            if k == i || k == j {
-                                continue;
-                            }
-                            for l in (k + 1)..hand_len {
-                                if l == i || l == j {
-                                    continue;
-                                }
-                                if let Some((value2, _)) = self.check_pair_value(k, l) {
-                                    if value1 != value2 {
-                                        // Create a pair of values, sorted to avoid duplicates
-                                        let mut pair_values = [value1, value2];
-                                        pair_values.sort();
-                                        
-                                        // Check if we've already seen this combination
-                                        if !seen_double_troubles.contains(&pair_values) {
-                                            seen_double_troubles.push(pair_values);
-                                            
-                                            if let Some(hand_type) = self.check_double_trouble(i, j, k, l) {
-                                                hands.push(hand_type);
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-            }
+        indices.sort_unstable_by(|a, b| b.cmp(a)); // Sort in reverse to remove from highest index first
+        self.mulligans += 1;
+        self.record(EventKind::Mulligan { count: indices.len() });
+        for &index in &indices {
+            self.discard_from_hand(index)?;
+        }
+        self.draw_to_hand(indices.len())
+    }
+    
+    // Discard the whole hand and draw as many fresh cards, once per combat. Returns how many were redrawn.
+    pub fn redraw_hand(&mut self, config: &GuildConfig) -> Result<usize, String> {
+        if self.hand_redrawn {
+            return Err("You've already thrown your hand away this combat.".to_string());
         }
+        if self.hand.is_empty() {
+            return Err("Your hand is empty, there's nothing to throw away.".to_string());
+        }
+        self.use_action(ActionKind::Draw, &config.action_budget)?;
         
-        // Check for triples
-        for i in 0..hand_len {
-            for j in (i + 1)..hand_len {
-                for k in (j + 1)..hand_len {
-                    if let Some(hand_type) = self.check_triple(i, j, k) {
-                        hands.push(hand_type);
-                    }
-                }
-            }
+        let count = self.hand.len();
+        self.hand_redrawn = true;
+        self.record(EventKind::HandRedrawn { count });
+        for index in (0..count).rev() {
+            self.discard_from_hand(index)?;
+        }
+        self.draw_to_hand(count)?;
+        Ok(count)
+    }
+    
+    // Declare what a joker in hand stands for until it leaves the hand
+    pub fn assign_joker(&mut self, card_index: usize, value: u8, element: ElementType) -> Result<CardType, String> {
+        if !self.deck_template.values().contains(&value) {
+            return Err(format!("Jokers can only stand for values from {} to {} in this deck", self.deck_template.low, self.deck_template.high));
+        }
+        if !self.deck_template.elements.contains(&element) {
+            return Err(format!("There are no {} cards in this deck", element.name()));
+        }
+        let card = self.hand.get_mut(card_index).ok_or("Card index out of bounds")?;
+        card.assign_joker(value, element)?;
+        let card = card.clone();
+        self.record(EventKind::JokerAssigned { position: card_index, card: card.clone() });
+        Ok(card)
+    }
+    
+    // Put a card from the hand back on the deck, to be drawn next or last
+    pub fn tuck_from_hand(&mut self, card_index: usize, end: DeckEnd) -> Result<CardType, String> {
+        if card_index >= self.hand.len() {
+            return Err("Card index out of bounds".to_string());
+        }
+        let mut card = self.hand.remove(card_index);
+        self.record(EventKind::Tucked { position: card_index, card: card.clone(), end });
+        card.clear_assignment();
+        // Cards are drawn from the end of the deck
+        match end {
+            DeckEnd::Top => self.deck.cards.push(card.clone()),
+            DeckEnd::Bottom => self.deck.cards.insert(0, card.clone()),
+        }
+        Ok(card)
+    }
+    
+    // Turn over the top card of the deck for a story moment; it goes straight to the discard
+    pub fn reveal_from_deck(&mut self) -> Result<CardType, String> {
+        let held: Vec<CardId> = self.hand.iter().map(|card| card.id()).collect();
+        self.draw_to_hand(1)?;
+        // A sorted hand can put the new card anywhere, so find it by ID
+        let index = self.hand.iter().position(|card| !held.contains(&card.id()))
+            .ok_or("There were no cards left to turn over")?;
+        let card = self.hand[index].clone();
+        self.discard_from_hand(index)?;
+        Ok(card)
+    }
+    
+    // Take a card out of the hand to hand to another player
+    pub fn give_from_hand(&mut self, card_id: CardId, to: &str) -> Result<CardType, String> {
+        let card_index = self.hand.iter().position(|card| card.id() == card_id)
+            .ok_or("That card is no longer in the hand")?;
+        let mut card = self.hand.remove(card_index);
+        self.record(EventKind::Gave { position: card_index, card: card.clone(), to: to.to_string() });
+        card.clear_assignment();
+        Ok(card)
+    }
+    
+    // A card from another player's deck joins the hand. It gets an ID of this combat's own
+    // so it can't be mistaken for one of the player's cards.
+    pub fn receive_card(&mut self, mut card: CardType, from: &str) -> CardType {
+        card.set_id(self.unused_card_id());
+        self.record(EventKind::Received { card: card.clone(), from: from.to_string() });
+        self.hand.push(card.clone());
+        self.keep_sorted();
+        card
+    }
+    
+    fn unused_card_id(&self) -> CardId {
+        let snapshot_cards = self.last_resolution.iter()
+            .flat_map(|s| s.deck.iter().chain(&s.hand).chain(&s.discard).chain(&s.burned));
+        let highest = self.all_cards().chain(snapshot_cards).map(|card| card.id().0).max();
+        CardId(highest.map_or(0, |id| id + 1))
+    }
+    
+    pub fn burn_from_hand(&mut self, card_index: usize) -> Result<CardType, String> {
+        if card_index >= self.hand.len() {
+            return Err("Card index out of bounds".to_string());
+        }
+        let card = self.hand.remove(card_index);
+        self.record(EventKind::Burned { position: card_index, card: card.clone() });
+        self.burned.push(card.clone());
+        Ok(card)
+    }
+    
+    pub fn record(&mut self, kind: EventKind) {
+        // Tallied as they happen so the combat's summary never has to read the archive
+        if let EventKind::Resolved { hand, .. } = &kind {
+            let label = match hand {
+                HandType::Homebrew { name, .. } => name.clone(),
+                _ => HandKind::of(hand).label().to_string(),
+            };
+            *self.resolutions.entry(label).or_insert(0) += 1;
+        }
+        self.log.push(CombatEvent::now(kind));
+    }
+    
+    // The next cards to be drawn, in the order they'll come
+    pub fn peek(&self, count: usize) -> Vec<CardType> {
+        self.deck.cards.iter().rev().take(count).cloned().collect()
+    }
+    
+    // Put peeked cards back: `top` in the order they'll be drawn, `bottom` under the rest of
+    // the deck with the last one deepest. Refused if they're no longer the top of the deck.
+    pub fn arrange_peeked(&mut self, top: &[CardId], bottom: &[CardId]) -> Result<(), String> {
+        let count = top.len() + bottom.len();
+        let mut peeked: Vec<CardId> = self.peek(count).iter().map(|card| card.id()).collect();
+        let mut given: Vec<CardId> = top.iter().chain(bottom).copied().collect();
+        peeked.sort_unstable_by_key(|id| id.0);
+        given.sort_unstable_by_key(|id| id.0);
+        if peeked != given {
+            return Err("Your deck has changed since you looked at it.".to_string());
         }
         
-        // Check for pairs
-        for i in 0..hand_len {
-            for j in (i + 1)..hand_len {
-                if let Some(hand_type) = self.check_pair(i, j) {
-                    hands.push(hand_type);
-                }
+        let mut cards = self.deck.cards.split_off(self.deck.cards.len() - count);
+        let mut take = |id: CardId| {
+            let index = cards.iter().position(|card| card.id() == id).expect("checked against the peeked cards");
+            cards.remove(index)
+        };
+        for &id in bottom {
+            self.deck.cards.insert(0, take(id));
+        }
+        // Drawn from the end, so the first card to draw goes on last
+        for &id in top.iter().rev() {
+            self.deck.cards.push(take(id));
+        }
+        self.record(EventKind::DeckReordered { deck: self.deck.cards.clone() });
+        Ok(())
+    }
+    
+    // Where a numbered card sits in the deck, if it's there at all
+    pub fn deck_position(&self, value: u8, element: &ElementType) -> Option<usize> {
+        self.deck.cards.iter()
+            .position(|card| matches!(card, CardType::Number(Some(v), suit, _) if *v == value && suit.element == *element))
+    }
+    
+    // Take a card out of the deck into the hand, then shuffle so the search doesn't give away the order
+    pub fn search_deck(&mut self, value: u8, element: &ElementType) -> Result<CardType, String> {
+        let Some(position) = self.deck_position(value, element) else {
+            return Err(format!("There's no {}{} left in your deck", value, Suit::of(element.clone()).symbol));
+        };
+        let card = self.deck.cards.remove(position);
+        self.hand.push(card.clone());
+        self.deck.shuffle_with(self.shuffle.shuffler().as_ref());
+        self.record(EventKind::Searched { card: card.clone(), deck: self.deck.cards.clone() });
+        self.keep_sorted();
+        Ok(card)
+    }
+    
+    // Swap a single card for a fresh one without it counting as a mulligan
+    pub fn reroll_card(&mut self, card_index: usize) -> Result<(), String> {
+        self.discard_from_hand(card_index)?;
+        let last = self.hand.len();
+        self.draw_to_hand(1)?;
+        // A sorted hand already put the new card where it belongs
+        if self.hand_sort.is_some() {
+            return Ok(());
+        }
+        // Keep the new card in the rerolled card's position
+        let card = self.hand.remove(last);
+        self.hand.insert(card_index, card);
+        self.record(EventKind::HandReordered { hand: self.hand.clone() });
+        Ok(())
+    }
+    
+    // Spend an action of the given kind if the turn's budget allows it
+    pub fn use_action(&mut self, kind: ActionKind, budget: &ActionBudget) -> Result<(), String> {
+        let used = self.actions_used.get_mut(kind);
+        if *used >= budget.limit(kind) {
+            return Err(format!("You've already used your {} action this turn", kind.label()));
+        }
+        *used += 1;
+        Ok(())
+    }
+    
+    pub fn has_action(&self, kind: ActionKind, budget: &ActionBudget) -> bool {
+        self.actions_used.get(kind) < budget.limit(kind)
+    }
+    
+    // Give back an action, used for GM overrides
+    pub fn refund_action(&mut self, kind: ActionKind) {
+        let used = self.actions_used.get_mut(kind);
+        *used = used.saturating_sub(1);
+    }
+    
+    pub fn next_turn(&mut self) {
+        self.record(EventKind::TurnEnded { turn: self.turn });
+        self.turn += 1;
+        self.actions_used = ActionsUsed::default();
+    }
+    
+    // Draw past the normal hand size for skills that grant extra cards
+    pub fn draw_extra(&mut self, count: usize, max_hand_size: usize) -> Result<(), String> {
+        if self.hand.len() + count > max_hand_size {
+            return Err(format!("That would take your hand past the limit of {} cards", max_hand_size));
+        }
+        self.draw_to_hand(count)
+    }
+    
+    // Cards held beyond the normal hand size; refills only top up to hand_size, so these are never replaced
+    pub fn extra_cards(&self) -> usize {
+        self.hand.len().saturating_sub(self.hand_size)
+    }
+    
+    // Discard the cards making up a resolved hand and draw back up to the hand size. Tables
+    // with the burn_used_jokers rule burn its jokers instead, so they never come back.
+    pub fn consume_hand(&mut self, hand: &HandType, config: &GuildConfig) -> Result<(), String> {
+        let positions = hand.positions_in(&self.hand);
+        if positions.is_empty() || positions.len() != hand.card_ids().len() {
+            return Err("Some of that hand's cards are no longer in your hand".to_string());
+        }
+        let burn_jokers = config.feature_enabled("burn_used_jokers");
+        // Remove from the highest position first so the others don't shift
+        for &index in positions.iter().rev() {
+            if burn_jokers && matches!(self.hand[index], CardType::Joker { .. }) {
+                self.burn_from_hand(index)?;
+            } else {
+                self.discard_from_hand(index)?;
             }
         }
         
-        hands
+        let cards_needed = self.hand_size.saturating_sub(self.hand.len());
+        if cards_needed > 0 {
+            self.draw_to_hand(cards_needed)?;
+        }
+        Ok(())
     }
-
-    // Helper function to check if two cards form a pair and return their value
-    fn check_pair_value(&self, i: usize, j: usize) -> Option<(u8, Vec<ElementType>)> {
-        let cards = [&self.hand[i], &self.hand[j]];
-        let mut value = None;
-        let mut joker_count = 0;
-        let mut non_joker_suits = Vec::new();
-
-        // First pass to find value and count jokers
-        for card in &cards {
-            match card {
-                CardType::Number(v, suit) => {
-                    if let Some(num) = v {
-                        if value.is_none() {
-                            value = Some(*num);
-                        } else if value != Some(*num) {
-                            return None;
-                        }
-                        if suit.element != ElementType::None {
-                            non_joker_suits.push(suit.element.clone());
-                        }
-                    } else {
-                        joker_count += 1;
-                    }
+    
+    // Every card in the combat, wherever it currently is
+    pub fn all_cards(&self) -> impl Iterator<Item = &CardType> {
+        self.deck.cards.iter()
+            .chain(self.hand.iter())
+            .chain(self.discard.iter())
+            .chain(self.burned.iter())
+    }
+    
+    // Whether this combat holds cards a template wouldn't produce
+    pub fn fits_template(&self, template: &DeckTemplate) -> bool {
+        let mut allowed = template.card_counts();
+        self.all_cards().all(|card| {
+            match allowed.get_mut(&card.key()) {
+                Some(count) if *count > 0 => {
+                    *count -= 1;
+                    true
                 },
-                CardType::Joker { .. } => {
-                    joker_count += 1;
+                _ => false,
+            }
+        })
+    }
+    
+    // Start over from a fresh deck built from the template, keeping turn and other combat data
+    pub fn rebuild_deck(&mut self, template: &DeckTemplate) -> Result<(), String> {
+        let hand_size = self.hand.len().max(self.hand_size);
+        let joker_names = self.joker_names();
+        self.deck = Deck::from_template(template);
+        self.deck.shuffle_with(self.shuffle.shuffler().as_ref());
+        self.deck_template = template.clone();
+        self.hand.clear();
+        self.discard.clear();
+        self.burned.clear();
+        self.name_jokers(&joker_names);
+        // Card IDs start over with the new deck, so nothing may still point at the old cards
+        self.declaration = None;
+        self.last_resolution = None;
+        self.resolution_list = None;
+        self.record(EventKind::Dealt { deck: self.deck.cards.clone() });
+        self.draw_to_hand(hand_size)
+    }
+    
+    // Signature names currently on the jokers, by slot
+    pub fn joker_names(&self) -> Vec<Option<String>> {
+        let mut names = Vec::new();
+        for card in self.all_cards() {
+            if let CardType::Joker { slot, name: Some(name), .. } = card {
+                let slot = *slot as usize;
+                if names.len() <= slot {
+                    names.resize(slot + 1, None);
                 }
+                names[slot] = Some(name.clone());
             }
         }
-
-        // Use 7 as default if no value was found (all jokers)
-        let value = value.unwrap_or(7);
-        
-        if joker_count + non_joker_suits.len() == 2 {
-            Some((value, if joker_count > 0 {
-                vec![ElementType::Air, ElementType::Earth, ElementType::Fire, ElementType::Ice]
-            } else {
-                non_joker_suits
-            }))
-        } else {
-            None
-        }
-    }
-
-    fn check_jackpot(&self, i: usize, j: usize, k: usize, l: usize) -> Option<HandType> {
-        let cards = [&self.hand[i], &self.hand[j], &self.hand[k], &self.hand[l]];
-        let mut value = None;
-        let mut joker_count = 0;
-        let mut non_joker_suits = Vec::new();
-
-        // Jackpot requires 4 of a kind with no jokers
-        for card in &cards {
-            match card {
-                CardType::Number(v, suit) => {
-                    if let Some(num) = v {
-                        if value.is_none() {
-                            value = Some(*num);
-                        } else if value != Some(*num) {
-                            return None;
-                        }
-                        if suit.element != ElementType::None {
-                            non_joker_suits.push(suit.element.clone());
-                        }
-                    } else {
-                        return None; // No wildcard number in Jackpot
-                    }
-                },
-                CardType::Joker { .. } => {
-                    return None; // No jokers in Jackpot
-                }
+        names
+    }
+    
+    // Every card in the combat and in its undo snapshot, for changes that have to reach both
+    fn every_card_mut(&mut self) -> impl Iterator<Item = &mut CardType> {
+        let snapshot_cards = self.last_resolution.iter_mut()
+            .flat_map(|s| s.deck.iter_mut().chain(s.hand.iter_mut()).chain(s.discard.iter_mut()).chain(s.burned.iter_mut()));
+        self.deck.cards.iter_mut()
+            .chain(self.hand.iter_mut())
+            .chain(self.discard.iter_mut())
+            .chain(self.burned.iter_mut())
+            .chain(snapshot_cards)
+    }
+    
+    // Put signature names on the jokers wherever they are, including an undo snapshot
+    pub fn name_jokers(&mut self, names: &[Option<String>]) {
+        for card in self.every_card_mut() {
+            if let CardType::Joker { slot, name, .. } = card {
+                *name = names.get(*slot as usize).cloned().flatten();
             }
         }
-
-        if non_joker_suits.len() == 4 {
-            Some(HandType::Jackpot {
-                value: value.unwrap_or(7),
-                suits: non_joker_suits,
-                card_indices: vec![i, j, k, l],
-            })
-        } else {
-            None
+    }
+    
+    // Combats saved before cards had IDs load with every ID at zero. Number their cards by
+    // what they are, so a card keeps the same ID in the undo snapshot as in the live zones.
+    pub fn assign_missing_card_ids(&mut self) {
+        let mut seen = HashSet::new();
+        if self.all_cards().all(|card| seen.insert(card.id())) {
+            return;
+        }
+        let mut ids: HashMap<(CardKey, u8), CardId> = HashMap::new();
+        for card in self.every_card_mut() {
+            let slot = match card {
+                CardType::Joker { slot, .. } => *slot,
+                CardType::Number(..) => 0,
+            };
+            let next = CardId(ids.len() as u32);
+            card.set_id(*ids.entry((card.key(), slot)).or_insert(next));
         }
+        // Its hand was found before the cards could be told apart
+        self.declaration = None;
     }
-
-    fn check_double_trouble(&self, i: usize, j: usize, k: usize, l: usize) -> Option<HandType> {
-        // First pair
-        let first_pair = self.check_pair_value(i, j)?;
-        // Second pair
-        let second_pair = self.check_pair_value(k, l)?;
-        
-        // Get suits from both pairs
-        let mut all_suits = Vec::new();
-        let mut added_elements = Vec::new();
-        
-        // Process first pair suits
-        for element in &first_pair.1 {
-            if !added_elements.contains(element) {
-                all_suits.push(element.clone());
-                added_elements.push(element.clone());
-            }
+    
+    pub fn snapshot_zones(&self) -> ZoneSnapshot {
+        ZoneSnapshot {
+            deck: self.deck.cards.clone(),
+            hand: self.hand.clone(),
+            discard: self.discard.clone(),
+            burned: self.burned.clone(),
         }
-        
-        // Process second pair suits
-        for element in &second_pair.1 {
-            if !added_elements.contains(element) {
-                all_suits.push(element.clone());
-                added_elements.push(element.clone());
-            }
+    }
+    
+    // Restore the zones from before the last resolution, and the resolution action it spent
+    // so the choice can be made again this turn
+    pub fn reopen_last_resolution(&mut self) -> Result<(), String> {
+        let Some(snapshot) = self.last_resolution.take() else {
+            return Err("There is no resolution to re-open".to_string());
+        };
+        self.record(EventKind::ZonesRestored {
+            deck: snapshot.deck.clone(),
+            hand: snapshot.hand.clone(),
+            discard: snapshot.discard.clone(),
+            burned: snapshot.burned.clone(),
+        });
+        self.deck.cards = snapshot.deck;
+        self.hand = snapshot.hand;
+        self.discard = snapshot.discard;
+        self.burned = snapshot.burned;
+        self.refund_action(ActionKind::Resolution);
+        Ok(())
+    }
+    
+    // Find the available hands and remember them as the numbered list the player is shown,
+    // each with the modifiers that changed it. With an element, only hands that can deal
+    // damage of that element are listed.
+    pub fn present_resolutions(&mut self, config: &GuildConfig, element: Option<&ElementType>) -> Vec<(HandType, Vec<String>)> {
+        let hands: Vec<(HandType, Vec<String>)> = self.find_modified_hands(config)
+            .into_iter()
+            .filter(|(hand, _)| element.is_none_or(|element| {
+                hand.suits().contains(element)
+                    && HandEffect::resolve(hand, &config.power(), config.hand_override(hand)).damage.is_some()
+            }))
+            .collect();
+        self.resolution_list = Some(ResolutionList {
+            hand: self.hand_fingerprint(),
+            hands: hands.iter().map(|(hand, _)| hand.clone()).collect(),
+        });
+        hands
+    }
+    
+    // A hand by its number in the last list shown, as long as the hand hasn't changed since.
+    // Labels are compared too, so assigning a joker counts as a change.
+    pub fn presented_hand(&self, number: usize) -> Result<HandType, String> {
+        let Some(list) = &self.resolution_list else {
+            return Err("Use /view_possible_resolutions first to see your numbered hands.".to_string());
+        };
+        if list.hand != self.hand_fingerprint() {
+            return Err("Your hand has changed since you viewed your resolutions. Use /view_possible_resolutions again.".to_string());
         }
-        
-        Some(HandType::DoubleTrouble {
-            first_pair_value: first_pair.0,
-            second_pair_value: second_pair.0,
-            suits: all_suits,
-            card_indices: vec![i, j, k, l],
-        })
+        number.checked_sub(1)
+            .and_then(|i| list.hands.get(i))
+            .cloned()
+            .ok_or_else(|| "Invalid hand number.".to_string())
+    }
+    
+    fn hand_fingerprint(&self) -> Vec<(CardId, String)> {
+        self.hand.iter().map(|card| (card.id(), card.label())).collect()
+    }
+    
+    // Possible hands filtered down to what the guild's rules allow, with its homebrew hands
+    pub fn find_available_hands(&self, config: &GuildConfig) -> Vec<HandType> {
+        self.find_modified_hands(config).into_iter().map(|(hand, _)| hand).collect()
     }
 
-    fn check_triple(&self, i: usize, j: usize, k: usize) -> Option<HandType> {
-        let cards = [&self.hand[i], &self.hand[j], &self.hand[k]];
-        let mut value = None;
-        let mut joker_count = 0;
-        let mut non_joker_suits = Vec::new();
-
-        // First pass to find value and count jokers
-        for card in &cards {
-            match card {
-                CardType::Number(v, suit) => {
-                    if let Some(num) = v {
-                        if value.is_none() {
-                            value = Some(*num);
-                        } else if value != Some(*num) {
-                            return None;
-                        }
-                        if suit.element != ElementType::None {
-                            non_joker_suits.push(suit.element.clone());
-                        }
-                    } else {
-                        joker_count += 1;
-                    }
-                },
-                CardType::Joker { .. } => {
-                    joker_count += 1;
-                }
-            }
-        }
+    // Available hands with the player's modifiers applied, each with the sources that changed it.
+    // The search is cached until the hand changes, so viewing, declaring and committing a
+    // resolution only search once between them.
+    pub fn find_modified_hands(&self, config: &GuildConfig) -> Vec<(HandType, Vec<String>)> {
+        let key = DetectionKey {
+            hand: self.hand_fingerprint(),
+            homebrew: config.homebrew_hands.iter()
+                .map(|homebrew| (homebrew.name.clone(), homebrew.pattern))
+                .collect(),
+            jokers_in_jackpot: config.feature_enabled("jokers_in_jackpot"),
+            deck: self.deck_template.clone(),
+        };
+        let hands = self.detection.get(&key).unwrap_or_else(|| {
+            let homebrew: Vec<HomebrewDetector> = config.homebrew_hands.iter().map(HomebrewDetector).collect();
+            let detectors: Vec<&dyn HandDetector> = detect::for_deck(&key.deck, key.jokers_in_jackpot).into_iter()
+                .chain(homebrew.iter().map(|detector| detector as &dyn HandDetector))
+                .collect();
+            let hands = detect::find_hands(&self.hand, &key.deck, &detectors);
+            self.detection.store(key, hands.clone());
+            hands
+        });
+        hands
+            .into_iter()
+            .map(|mut hand| {
+                let applied = modifiers::apply(&self.modifiers, &mut hand);
+                (hand, applied)
+            })
+            .collect()
+    }
 
-        // Use 7 as default if no value was found (all jokers)
-        let value = value.unwrap_or(7);
+    // Every hand in the rules the cards in hand can make, best first
+    pub fn find_possible_hands(&self) -> Vec<HandType> {
+        detect::find_hands(&self.hand, &self.deck_template, &detect::for_deck(&self.deck_template, false))
+    }
+
+    // Every hand one more numbered card would add, best first
+    pub fn hands_one_card_away(&self, config: &GuildConfig) -> Vec<NearHand> {
+        let held: HashSet<String> = self.find_available_hands(config).iter().map(|hand| hand.shape()).collect();
+        // Only cards that can still turn up are worth suggesting
+        let reachable: HashSet<CardKey> = self.deck.cards.iter().chain(&self.discard).map(|card| card.key()).collect();
+        let mut probe = PlayerState::new(None, &self.deck_template.without_jokers());
+        probe.hand = self.hand.clone();
         
-        if joker_count + non_joker_suits.len() == 3 {
-            Some(HandType::TripleThreat {
-                value,
-                suits: if joker_count > 0 {
-                    vec![ElementType::Air, ElementType::Earth, ElementType::Fire, ElementType::Ice]
-                } else {
-                    non_joker_suits
-                },
-                card_indices: vec![i, j, k],
-            })
-        } else {
-            None
-        }
-    }
-
-    fn check_pair(&self, i: usize, j: usize) -> Option<HandType> {
-        let cards = [&self.hand[i], &self.hand[j]];
-        let mut value = None;
-        let mut joker_count = 0;
-        let mut non_joker_suits = Vec::new();
-
-        // First pass to find value and count jokers
-        for card in &cards {
-            match card {
-                CardType::Number(v, suit) => {
-                    if let Some(num) = v {
-                        if value.is_none() {
-                            value = Some(*num);
-                        } else if value != Some(*num) {
-                            return None;
-                        }
-                        if suit.element != ElementType::None {
-                            non_joker_suits.push(suit.element.clone());
-                        }
-                    } else {
-                        joker_count += 1;
+        let mut near: Vec<NearHand> = Vec::new();
+        for value in self.deck_template.values() {
+            for element in &self.deck_template.elements {
+                let card = CardType::Number(Some(value), Suit::of(element.clone()), CardId::default());
+                if !reachable.contains(&card.key()) {
+                    continue;
+                }
+                probe.hand.push(card.clone());
+                for hand in probe.find_available_hands(config) {
+                    let shape = hand.shape();
+                    if held.contains(&shape) {
+                        continue;
+                    }
+                    match near.iter_mut().find(|n| n.hand.shape() == shape) {
+                        Some(n) if n.needs.iter().any(|c| c.key() == card.key()) => {},
+                        Some(n) => n.needs.push(card.clone()),
+                        None => near.push(NearHand { hand, needs: vec![card.clone()], left_in_deck: 0 }),
                     }
-                },
-                CardType::Joker { .. } => {
-                    joker_count += 1;
                 }
+                probe.hand.pop();
             }
         }
-
-        // Use 7 as default if no value was found (all jokers)
-        let value = value.unwrap_or(7);
         
-        if joker_count + non_joker_suits.len() == 2 {
-            Some(HandType::MatchedEdge {
-                value,
-                suits: if joker_count > 0 {
-                    vec![ElementType::Air, ElementType::Earth, ElementType::Fire, ElementType::Ice]
-                } else {
-                    non_joker_suits
-                },
-                card_indices: vec![i, j],
-            })
-        } else {
-            None
+        for n in &mut near {
+            n.left_in_deck = self.deck.cards.iter()
+                .filter(|card| n.needs.iter().any(|need| need.key() == card.key()))
+                .count();
         }
+        near.sort_by_key(|n| std::cmp::Reverse(config.mp_cost(&n.hand)));
+        near
     }
 }
 
+#[derive(Serialize)]
+pub struct CampaignExport {
+    pub guild_id: GuildId,
+    pub exported_at: u64,
+    pub config: GuildConfig,
+    pub players: Vec<ExportedPlayer>,
+}
+
+#[derive(Serialize)]
+pub struct ExportedPlayer {
+    pub user_id: UserId,
+    pub fabula_points: u32,
+    pub state: PlayerState,
+}
+
 #[derive(Serialize, Deserialize, Default)]
 pub struct PlayerStateManager {
     pub players: HashMap<UserId, PlayerState>,
+    #[serde(default)]
+    pub guild_configs: HashMap<GuildId, GuildConfig>,
+    // Fabula Points persist across combats, so they live outside PlayerState
+    #[serde(default)]
+    pub fabula_points: HashMap<UserId, u32>,
+    // How many times each player's assists or traps fed into a resolution
+    #[serde(default)]
+    pub contribution_counts: HashMap<UserId, u32>,
+    #[serde(default)]
+    pub characters: HashMap<UserId, CharacterProfile>,
+    #[serde(default)]
+    pub pending_interactions: Vec<PendingInteraction>,
+    // Summaries of each player's ended combats, oldest first
+    #[serde(default)]
+    pub combat_archive: HashMap<UserId, Vec<CombatSummary>>,
+    // Requests waiting on a GM, oldest first
+    #[serde(default)]
+    pub approvals: Vec<ApprovalRequest>,
+    #[serde(default)]
+    pub preferences: HashMap<UserId, Preferences>,
+    // Shards changed since the last save
     #[serde(skip)]
-    dirty: bool,
+    dirty: HashSet<ShardKey>,
     #[serde(skip)]
     last_save: Option<Instant>,
 }
@@ -363,51 +1049,571 @@ impl PlayerStateManager {
     pub fn new() -> Self {
         PlayerStateManager {
             players: HashMap::new(),
-            dirty: false,
+            guild_configs: HashMap::new(),
+            fabula_points: HashMap::new(),
+            contribution_counts: HashMap::new(),
+            characters: HashMap::new(),
+            pending_interactions: Vec::new(),
+            combat_archive: HashMap::new(),
+            approvals: Vec::new(),
+            preferences: HashMap::new(),
+            dirty: HashSet::new(),
             last_save: Some(Instant::now()),
         }
     }
 
     pub fn get_player_state(&mut self, user_id: UserId) -> Option<&mut PlayerState> {
+        // Frozen combats can be read but not changed
+        if self.players.get(&user_id).is_some_and(|s| s.orphaned) {
+            return None;
+        }
+        // Callers only borrow mutably to change the state, so it needs saving
+        if let Some(guild_id) = self.players.get(&user_id).map(|s| s.guild_id) {
+            self.mark_dirty(guild_id);
+        }
         self.players.get_mut(&user_id)
     }
+    
+    // Freeze a departed member's combat at that server, returning whether there was one
+    pub fn orphan_combat(&mut self, user_id: UserId, guild_id: GuildId) -> bool {
+        let Some(state) = self.players.get_mut(&user_id).filter(|s| s.guild_id == Some(guild_id) && !s.orphaned) else {
+            return false;
+        };
+        state.orphaned = true;
+        self.mark_dirty(Some(guild_id));
+        true
+    }
+    
+    // A member who came back picks their combat up where they left it
+    pub fn restore_combat(&mut self, user_id: UserId, guild_id: GuildId) -> bool {
+        let Some(state) = self.players.get_mut(&user_id).filter(|s| s.guild_id == Some(guild_id) && s.orphaned) else {
+            return false;
+        };
+        state.orphaned = false;
+        self.mark_dirty(Some(guild_id));
+        true
+    }
+    
+    pub fn orphaned_combats(&self, guild_id: GuildId) -> Vec<UserId> {
+        self.players.iter()
+            .filter(|(_, state)| state.orphaned && state.guild_id == Some(guild_id))
+            .map(|(user_id, _)| *user_id)
+            .collect()
+    }
+    
+    // Start the next turn for every active combat at a table, returning how many moved on
+    pub fn next_round(&mut self, guild_id: GuildId) -> usize {
+        let mut count = 0;
+        for state in self.players.values_mut().filter(|s| s.guild_id == Some(guild_id) && !s.orphaned) {
+            state.next_turn();
+            count += 1;
+        }
+        if count > 0 {
+            self.mark_dirty(Some(guild_id));
+        }
+        count
+    }
+    
+    // Hand a frozen combat to another player, who takes it over as their own
+    pub fn reassign_combat(&mut self, from: UserId, to: UserId) -> Result<(), String> {
+        if self.players.contains_key(&to) {
+            return Err("That player already has a combat. They need to /end_combat first.".to_string());
+        }
+        let Some(mut state) = self.players.remove(&from) else {
+            return Err("There's no combat to reassign.".to_string());
+        };
+        state.orphaned = false;
+        self.mark_dirty(state.guild_id);
+        self.players.insert(to, state);
+        Ok(())
+    }
 
-    pub fn start_new_combat(&mut self, user_id: UserId) -> &mut PlayerState {
-        self.players.insert(user_id, PlayerState::new());
-        self.mark_dirty();
+    pub fn start_new_combat(&mut self, user_id: UserId, guild_id: Option<GuildId>, preset: Option<DeckPreset>) -> &mut PlayerState {
+        let config = self.guild_config(guild_id);
+        let template = preset.map_or_else(|| config.deck_template.clone(), |preset| preset.template());
+        let mut state = PlayerState::with_shuffle(guild_id, &template, config.shuffle);
+        state.deck_preset = preset;
+        state.hand_size = config.hand_size();
+        state.log.combat_id = format!("{}_{}", user_id, unix_now());
+        
+        // The old combat has to leave its guild's shard too
+        self.end_combat(user_id);
+        let profile = self.characters.entry(user_id).or_default();
+        let names: Vec<Option<String>> = profile.jokers.iter().map(|j| j.name.clone()).collect();
+        state.name_jokers(&names);
+        state.hand_sort = self.preferences(user_id).hand_sort;
+        self.players.insert(user_id, state);
+        self.mark_dirty(guild_id);
+        self.mark_dirty(None);
         self.players.get_mut(&user_id).unwrap()
     }
     
-    // Save state to file
-    pub fn save_state(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        if !self.dirty {
-            return Ok(());
+    // Move a card between two players' hands under the one lock, so it's never in both or neither
+    pub fn give_card(&mut self, from: (UserId, &str), to: (UserId, &str), card_id: CardId, max_hand_size: usize) -> Result<CardType, String> {
+        let Some(receiver) = self.get_player_state(to.0) else {
+            return Err(format!("{} isn't in a combat right now.", to.1));
+        };
+        if receiver.hand.len() >= max_hand_size {
+            return Err(format!("{}'s hand is already at the limit of {} cards.", to.1, max_hand_size));
+        }
+        let (guild_id, deck_template) = (receiver.guild_id, receiver.deck_template.clone());
+        let Some(giver) = self.get_player_state(from.0) else {
+            return Err(format!("{} isn't in a combat anymore.", from.1));
+        };
+        // A card only belongs in a deck built the same way, at the same table
+        if giver.guild_id != guild_id {
+            return Err(format!("{} is fighting at another table.", to.1));
+        }
+        if giver.deck_template != deck_template {
+            return Err(format!("{}'s deck isn't built like yours, so the card wouldn't fit in it.", to.1));
+        }
+        let card = giver.give_from_hand(card_id, to.1)
+            .map_err(|_| format!("{} doesn't have that card anymore.", from.1))?;
+        match self.get_player_state(to.0) {
+            Some(receiver) => Ok(receiver.receive_card(card, from.1)),
+            // Nothing can end the combat under this lock, but the card must not vanish if it did
+            None => {
+                if let Some(giver) = self.get_player_state(from.0) {
+                    giver.receive_card(card, to.1);
+                }
+                Err(format!("{} isn't in a combat anymore.", to.1))
+            }
+        }
+    }
+    
+    // Remove a player's combat, keeping a summary of it in their archive
+    pub fn end_combat(&mut self, user_id: UserId) -> Option<CombatSummary> {
+        let old = self.players.remove(&user_id)?;
+        // The combat's joker draws now count toward the campaign total
+        let profile = self.characters.entry(user_id).or_default();
+        for (index, count) in old.joker_appearances.iter().enumerate().filter(|(_, count)| **count > 0) {
+            profile.joker_mut(index).appearances += count;
         }
         
-        let json = serde_json::to_string(&self)?;
-        // Create a temp file first to avoid corruption if the process crashes
-        fs::write("player_state.json.tmp", &json)?;
-        fs::rename("player_state.json.tmp", "player_state.json")?;
+        let summary = CombatSummary::of(&old);
+        let archive = self.combat_archive.entry(user_id).or_default();
+        archive.push(summary.clone());
+        if archive.len() > MAX_ARCHIVED_COMBATS {
+            archive.remove(0);
+        }
+        self.mark_dirty(old.guild_id);
+        self.mark_dirty(None);
+        Some(summary)
+    }
+    
+    pub fn archived_combats(&self, user_id: UserId) -> &[CombatSummary] {
+        self.combat_archive.get(&user_id).map_or(&[], |archive| archive.as_slice())
+    }
+    
+    pub fn guild_config(&self, guild_id: Option<GuildId>) -> GuildConfig {
+        guild_id
+            .and_then(|id| self.guild_configs.get(&id).cloned())
+            .unwrap_or_default()
+    }
+
+    pub fn table_channels(&self) -> Vec<ChannelId> {
+        self.guild_configs.values().filter_map(|c| c.table_channel).collect()
+    }
+
+    pub fn guild_config_mut(&mut self, guild_id: GuildId) -> &mut GuildConfig {
+        self.mark_dirty(Some(guild_id));
+        self.guild_configs.entry(guild_id).or_default()
+    }
+
+    // Active combats at a table that a new deck template would invalidate. Combats dealt from
+    // a preset keep it whatever the guild's deck is.
+    pub fn combats_outside_template(&self, guild_id: GuildId, template: &DeckTemplate) -> usize {
+        self.players.values()
+            .filter(|state| state.guild_id == Some(guild_id) && state.deck_preset.is_none() && !state.fits_template(template))
+            .count()
+    }
+
+    // Switch a table to a new deck template. With rebuild, live combats move to the new
+    // deck too; otherwise they finish on the deck they started with.
+    pub fn apply_deck_template(&mut self, guild_id: GuildId, template: DeckTemplate, rebuild: bool) -> Result<usize, String> {
+        let mut rebuilt = Vec::new();
+        if rebuild {
+            // Rebuild copies first so a failure leaves the whole table untouched
+            for (user_id, state) in self.players.iter().filter(|(_, s)| s.guild_id == Some(guild_id) && s.deck_preset.is_none()) {
+                let mut state = state.clone();
+                state.rebuild_deck(&template)?;
+                rebuilt.push((*user_id, state));
+            }
+        }
+        
+        let count = rebuilt.len();
+        self.players.extend(rebuilt);
+        self.guild_config_mut(guild_id).deck_template = template;
+        Ok(count)
+    }
+
+    pub fn fabula_points(&self, user_id: UserId) -> u32 {
+        self.fabula_points.get(&user_id).copied().unwrap_or(0)
+    }
+
+    pub fn add_fabula_points(&mut self, user_id: UserId, amount: u32) -> u32 {
+        let points = self.fabula_points.entry(user_id).or_insert(0);
+        *points = points.saturating_add(amount);
+        let total = *points;
+        self.mark_dirty(None);
+        total
+    }
+
+    pub fn spend_fabula_point(&mut self, user_id: UserId) -> Result<u32, String> {
+        let points = self.fabula_points.entry(user_id).or_insert(0);
+        if *points == 0 {
+            return Err("You don't have any Fabula Points to spend".to_string());
+        }
+        *points -= 1;
+        let remaining = *points;
+        self.mark_dirty(None);
+        Ok(remaining)
+    }
+    
+    // A player's own settings, the defaults until they change one
+    pub fn preferences(&self, user_id: UserId) -> Preferences {
+        self.preferences.get(&user_id).cloned().unwrap_or_default()
+    }
+    
+    pub fn set_hand_delivery(&mut self, user_id: UserId, delivery: HandDelivery) {
+        self.preferences.entry(user_id).or_default().hand_delivery = delivery;
+        self.mark_dirty(None);
+    }
+    
+    // Sort new draws this way from now on, in the live combat as well as future ones
+    pub fn set_hand_sort(&mut self, user_id: UserId, sort: Option<HandSort>) {
+        self.preferences.entry(user_id).or_default().hand_sort = sort;
+        self.mark_dirty(None);
+        if let Some(state) = self.get_player_state(user_id) {
+            state.hand_sort = sort;
+        }
+    }
+    
+    pub fn affinity(&self, user_id: UserId) -> Option<ElementType> {
+        self.characters.get(&user_id).and_then(|c| c.affinity.clone())
+    }
+    
+    pub fn set_affinity(&mut self, user_id: UserId, affinity: Option<ElementType>) {
+        self.characters.entry(user_id).or_default().affinity = affinity;
+        self.mark_dirty(None);
+    }
+    
+    // Name to show in public messages, falling back to the Discord name
+    pub fn display_name(&self, user_id: UserId, fallback: &str) -> String {
+        self.characters
+            .get(&user_id)
+            .and_then(|c| c.display_name.clone())
+            .unwrap_or_else(|| fallback.to_string())
+    }
+
+    // Who a targeted hand can be aimed at: the scene's enemies, then allies in combat here.
+    // Allies without a character name are left out, there's nothing readable to list them by.
+    pub fn target_choices(&self, guild_id: Option<GuildId>) -> Vec<String> {
+        let config = self.guild_config(guild_id);
+        let mut allies: Vec<String> = self.players.iter()
+            .filter(|(user_id, state)| state.guild_id.is_some()
+                && state.guild_id == guild_id
+                && !state.orphaned
+                && (config.table_members.is_empty() || config.table_members.contains(user_id)))
+            .filter_map(|(user_id, _)| self.characters.get(user_id).and_then(|c| c.display_name.clone()))
+            .collect();
+        allies.sort_by_key(|name| name.to_lowercase());
+        let mut choices = config.encounter_targets;
+        choices.extend(allies);
+        choices
+    }
+
+    pub fn name_joker(&mut self, user_id: UserId, slot: JokerSlot, name: &str) -> Result<String, String> {
+        let name = sanitize_display_name(name)?;
+        let profile = self.characters.entry(user_id).or_default();
+        profile.joker_mut(slot.index()).name = Some(name.clone());
+        let names: Vec<Option<String>> = profile.jokers.iter().map(|j| j.name.clone()).collect();
+        
+        if let Some(state) = self.players.get_mut(&user_id) {
+            state.name_jokers(&names);
+            let guild_id = state.guild_id;
+            self.mark_dirty(guild_id);
+        }
+        self.mark_dirty(None);
+        Ok(name)
+    }
+    
+    // Times a joker was drawn across the campaign, including the running combat
+    pub fn joker_appearances(&self, user_id: UserId, slot: JokerSlot) -> u32 {
+        let past = self.characters
+            .get(&user_id)
+            .and_then(|c| c.joker(slot))
+            .map(|joker| joker.appearances)
+            .unwrap_or(0);
+        let current = self.players
+            .get(&user_id)
+            .and_then(|s| s.joker_appearances.get(slot.index()).copied())
+            .unwrap_or(0);
+        past + current
+    }
+    
+    pub fn rename_character(&mut self, user_id: UserId, guild_id: Option<GuildId>, name: &str) -> Result<String, String> {
+        let name = sanitize_display_name(name)?;
         
-        self.dirty = false;
-        self.last_save = Some(Instant::now());
+        let taken = self.characters.iter().any(|(id, c)| {
+            *id != user_id
+                && c.guild_id == guild_id
+                && c.display_name.as_ref().is_some_and(|n| n.eq_ignore_ascii_case(&name))
+        });
+        if taken {
+            return Err(format!("{} is already taken at this table", name));
+        }
+        
+        let profile = self.characters.entry(user_id).or_default();
+        profile.guild_id = guild_id;
+        profile.display_name = Some(name.clone());
+        self.mark_dirty(None);
+        Ok(name)
+    }
+
+    // Remember an interactive message so it can be collapsed even if the bot restarts first
+    pub fn register_interaction(&mut self, channel_id: ChannelId, message_id: MessageId, ttl: u64, expired_text: &str) {
+        self.pending_interactions.push(PendingInteraction {
+            channel_id,
+            message_id,
+            expires_at: unix_now() + ttl,
+            expired_text: expired_text.to_string(),
+        });
+        self.mark_dirty(None);
+    }
+    
+    // The message's own collector cleaned it up
+    pub fn finish_interaction(&mut self, message_id: MessageId) {
+        self.pending_interactions.retain(|p| p.message_id != message_id);
+        self.mark_dirty(None);
+    }
+    
+    pub fn take_expired_interactions(&mut self, now: u64) -> Vec<PendingInteraction> {
+        let (expired, pending) = std::mem::take(&mut self.pending_interactions)
+            .into_iter()
+            .partition(|p| p.expires_at <= now);
+        self.pending_interactions = pending;
+        if !expired.is_empty() {
+            self.mark_dirty(None);
+        }
+        expired
+    }
+    
+    // Add a request to the GM's inbox, one per player and action at a time
+    pub fn queue_approval(&mut self, request: ApprovalRequest) -> Result<(), String> {
+        if self.approvals.iter().any(|r| r.requester == request.requester && r.action == request.action) {
+            return Err("You already asked the GM for that, wait for their answer.".to_string());
+        }
+        self.approvals.push(request);
+        self.mark_dirty(None);
         Ok(())
     }
+    
+    pub fn set_approval_message(&mut self, id: u64, message_id: MessageId) {
+        if let Some(request) = self.approvals.iter_mut().find(|r| r.id == id) {
+            request.gm_message = Some(message_id);
+            self.mark_dirty(None);
+        }
+    }
+    
+    // Remove a request once it's answered, None if someone else got there first
+    pub fn take_approval(&mut self, id: u64) -> Option<ApprovalRequest> {
+        let position = self.approvals.iter().position(|r| r.id == id)?;
+        self.mark_dirty(None);
+        Some(self.approvals.remove(position))
+    }
+    
+    pub fn pending_approvals(&self, guild_id: GuildId) -> Vec<&ApprovalRequest> {
+        self.approvals.iter().filter(|r| r.guild_id == guild_id).collect()
+    }
+    
+    // Apply an approved request to the requester's combat
+    pub fn apply_approval(&mut self, requester: UserId, action: &ApprovalAction) -> Result<String, String> {
+        let Some(player) = self.get_player_state(requester) else {
+            return Err("they're no longer in a combat".to_string());
+        };
+        action.apply(player)
+    }
+    
+    // Credits each helper once per resolution, however many entries they left
+    pub fn record_contributions(&mut self, contributions: &[Contribution]) {
+        let contributors: HashSet<UserId> = contributions.iter().map(|c| c.contributor).collect();
+        for contributor in contributors {
+            let count = self.contribution_counts.entry(contributor).or_insert(0);
+            *count = count.saturating_add(1);
+        }
+        if !contributions.is_empty() {
+            self.mark_dirty(None);
+        }
+    }
+
+    // Snapshot everything belonging to a guild's campaign
+    pub fn export_campaign(&self, guild_id: GuildId) -> CampaignExport {
+        let players = self.players.iter()
+            .filter(|(_, state)| state.guild_id == Some(guild_id))
+            .map(|(user_id, state)| ExportedPlayer {
+                user_id: *user_id,
+                fabula_points: self.fabula_points(*user_id),
+                state: state.clone(),
+            })
+            .collect();
+        
+        CampaignExport {
+            guild_id,
+            exported_at: unix_now(),
+            // Exports get posted in Discord, the guild's API keys stay behind
+            config: GuildConfig { api_keys: Vec::new(), ..self.guild_config(Some(guild_id)) },
+            players,
+        }
+    }
+    
+    // The part of the state stored in one shard file. Guild shards hold that guild's
+    // combats and config; the global shard holds DM combats and per-user data.
+    fn shard(&self, key: ShardKey) -> PlayerStateManager {
+        let players = self.players.iter()
+            .filter(|(_, state)| state.guild_id == key)
+            .map(|(user_id, state)| (*user_id, state.clone()))
+            .collect();
+        
+        match key {
+            Some(guild_id) => PlayerStateManager {
+                players,
+                guild_configs: self.guild_configs.get(&guild_id)
+                    .map(|config| HashMap::from([(guild_id, config.clone())]))
+                    .unwrap_or_default(),
+                ..Self::new()
+            },
+            None => PlayerStateManager {
+                players,
+                fabula_points: self.fabula_points.clone(),
+                contribution_counts: self.contribution_counts.clone(),
+                characters: self.characters.clone(),
+                pending_interactions: self.pending_interactions.clone(),
+                combat_archive: self.combat_archive.clone(),
+                approvals: self.approvals.clone(),
+                preferences: self.preferences.clone(),
+                ..Self::new()
+            },
+        }
+    }
+    
+    // Serialize every dirty shard, clearing the dirty set. The files are written
+    // separately so the lock isn't held for disk I/O.
+    fn take_dirty_shards(&mut self) -> Result<Vec<(ShardKey, String)>, serde_json::Error> {
+        // Keys stay dirty until every shard has serialized, so a failure is retried next save
+        let mut shards = Vec::new();
+        for &key in &self.dirty {
+            shards.push((key, serde_json::to_string(&self.shard(key))?));
+        }
+        self.dirty.clear();
+        Ok(shards)
+    }
+    
+    fn write_shard(key: ShardKey, json: &str) -> std::io::Result<()> {
+        fs::create_dir_all(STATE_DIR)?;
+        let path = shard_path(key);
+        // Create a temp file first to avoid corruption if the process crashes
+        let tmp = format!("{}.tmp", path);
+        fs::write(&tmp, json)?;
+        fs::rename(&tmp, &path)
+    }
+
+    // Write, read back and remove a scratch file beside the state, the way a save would.
+    // It lives outside the state directory so loading never mistakes it for a shard.
+    pub fn probe_store() -> std::io::Result<()> {
+        let path = format!("{}.probe", STATE_DIR);
+        let tmp = format!("{}.tmp", path);
+        fs::write(&tmp, b"probe")?;
+        fs::rename(&tmp, &path)?;
+        fs::read(&path)?;
+        fs::remove_file(&path)
+    }
 
     pub async fn save_if_needed(arc_self: &Arc<Mutex<Self>>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let mut self_guard = arc_self.lock().await;
-    if self_guard.dirty {
-        self_guard.save_state()?;
+        let shards = arc_self.lock().await.take_dirty_shards()?;
+        if shards.is_empty() {
+            return Ok(());
+        }
+        
+        let written = tokio::task::spawn_blocking(move || {
+            shards.into_iter()
+                .map(|(key, json)| (key, Self::write_shard(key, &json)))
+                .collect::<Vec<_>>()
+        }).await?;
+        
+        // A failed shard is retried on the next save without holding back the others
+        let mut state = arc_self.lock().await;
+        let mut failures = Vec::new();
+        for (key, result) in written {
+            if let Err(e) = result {
+                state.dirty.insert(key);
+                failures.push(format!("{}: {}", shard_path(key), e));
+            }
+        }
+        state.last_save = Some(Instant::now());
+        if !failures.is_empty() {
+            return Err(failures.join(", ").into());
+        }
+        Ok(())
     }
-    Ok(())
-}
     
-    // Load state from file
+    // Load every shard in the state directory, or the old single-file save if there is none yet
     pub fn load_state() -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
-        match fs::read_to_string("player_state.json") {
+        let entries = match fs::read_dir(STATE_DIR) {
+            Ok(entries) => entries,
+            Err(_) => return Self::load_legacy_state(),
+        };
+        
+        let mut state = Self::new();
+        for entry in entries {
+            let path = entry?.path();
+            let Some(stem) = path.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            let key: ShardKey = match stem {
+                GLOBAL_SHARD => None,
+                id => match id.parse::<u64>() {
+                    Ok(id) => Some(GuildId(id)),
+                    Err(_) => continue,
+                },
+            };
+            
+            let json = fs::read_to_string(&path)?;
+            let shard: PlayerStateManager = serde_json::from_str(&json)?;
+            // A combat that moved guilds may briefly be in two shards, trust the one it belongs to
+            state.players.extend(shard.players.into_iter().filter(|(_, s)| s.guild_id == key));
+            state.guild_configs.extend(shard.guild_configs);
+            if key.is_none() {
+                state.fabula_points = shard.fabula_points;
+                state.contribution_counts = shard.contribution_counts;
+                state.characters = shard.characters;
+                state.pending_interactions = shard.pending_interactions;
+                state.combat_archive = shard.combat_archive;
+                state.approvals = shard.approvals;
+                state.preferences = shard.preferences;
+            }
+        }
+        for player in state.players.values_mut() {
+            player.assign_missing_card_ids();
+        }
+        Ok(state)
+    }
+    
+    fn load_legacy_state() -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        match fs::read_to_string(LEGACY_STATE_FILE) {
             Ok(json) => {
                 let mut state: PlayerStateManager = serde_json::from_str(&json)?;
-                state.dirty = false;
+                for player in state.players.values_mut() {
+                    player.assign_missing_card_ids();
+                }
+                // Write everything out as shards on the next save
+                state.dirty = state.players.values().map(|s| s.guild_id)
+                    .chain(state.guild_configs.keys().map(|id| Some(*id)))
+                    .chain([None])
+                    .collect();
                 state.last_save = Some(Instant::now());
                 Ok(state)
             },
@@ -415,8 +1621,29 @@ impl PlayerStateManager {
         }
     }
     
-    // Mark state as modified
-    fn mark_dirty(&mut self) {
-        self.dirty = true;
+    // Mark a shard as modified
+    fn mark_dirty(&mut self, key: ShardKey) {
+        self.dirty.insert(key);
     }
-}
\ No newline at end of file
+}
+
+// Which save file a piece of state lives in: a guild's shard, or None for the global one
+type ShardKey = Option<GuildId>;
+
+// Cards a hand is dealt and refilled to unless the table changes it
+pub const DEFAULT_HAND_SIZE: usize = 5;
+
+fn default_hand_size() -> usize {
+    DEFAULT_HAND_SIZE
+}
+
+const STATE_DIR: &str = "state";
+const GLOBAL_SHARD: &str = "global";
+const LEGACY_STATE_FILE: &str = "player_state.json";
+
+fn shard_path(key: ShardKey) -> String {
+    match key {
+        Some(guild_id) => format!("{}/{}.json", STATE_DIR, guild_id),
+        None => format!("{}/{}.json", STATE_DIR, GLOBAL_SHARD),
+    }
+}
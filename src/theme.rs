@@ -0,0 +1,57 @@
+use serde::{Serialize, Deserialize};
+use crate::character::{is_affine, AFFINITY_MARK};
+use crate::types::{CardType, ElementType};
+
+// Bundled looks a guild can pick for its hand panels
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, poise::ChoiceParameter)]
+pub enum Theme {
+    #[default]
+    Parchment,
+    Neon,
+    Minimal,
+}
+
+impl Theme {
+    pub fn embed_color(&self) -> u32 {
+        match self {
+            Theme::Parchment => 0xC8A165,
+            Theme::Neon => 0x39FF14,
+            Theme::Minimal => 0x2F3136,
+        }
+    }
+
+    pub fn card_face(&self, card: &CardType) -> String {
+        let (value, suit) = match card {
+            CardType::Number(num, suit, _) => (num.map(|n| n.to_string()), suit.symbol.clone()),
+            CardType::Joker { current_value, current_suit, symbol, .. } => match (current_value, current_suit) {
+                (Some(val), Some(suit)) => (Some(val.to_string()), suit.symbol.clone()),
+                _ => (None, symbol.clone()),
+            },
+        };
+        let value = value.unwrap_or_else(|| "?".to_string());
+        
+        let face = match self {
+            Theme::Parchment => format!("「{} {}」", value, suit),
+            Theme::Neon => format!("⟦{}{}⟧", value, suit),
+            Theme::Minimal => format!("{}{}", value, suit),
+        };
+        match card.joker_name() {
+            Some(name) => format!("{} *{}*", face, name),
+            None => face,
+        }
+    }
+
+    pub fn format_hand(&self, hand: &[CardType], affinity: Option<&ElementType>) -> String {
+        if hand.is_empty() {
+            return String::from("Your hand is empty!");
+        }
+        hand.iter()
+            .enumerate()
+            .map(|(i, card)| {
+                let mark = if is_affine(card, affinity) { format!(" {}", AFFINITY_MARK) } else { String::new() };
+                format!("{}. {}{}", i + 1, self.card_face(card), mark)
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
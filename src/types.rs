@@ -1,33 +1,244 @@
 use serde::{Serialize, Deserialize};
-use rand::seq::SliceRandom;
 use rand::thread_rng;
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+use crate::arcana;
+use crate::effects::{HandEffect, HandKind};
+use crate::shuffle::Shuffler;
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum ElementType {
     Fire,
     Ice,
     Earth,
     Air,
+    Bolt,
+    Poison,
+    Light,
+    Dark,
     None,
 }
 
+impl ElementType {
+    // Every element a suit can be, in the order cards sort and tables list them
+    pub const ALL: [ElementType; 8] = [
+        ElementType::Fire,
+        ElementType::Ice,
+        ElementType::Earth,
+        ElementType::Air,
+        ElementType::Bolt,
+        ElementType::Poison,
+        ElementType::Light,
+        ElementType::Dark,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ElementType::Fire => "Fire",
+            ElementType::Ice => "Ice",
+            ElementType::Earth => "Earth",
+            ElementType::Air => "Air",
+            ElementType::Bolt => "Bolt",
+            ElementType::Poison => "Poison",
+            ElementType::Light => "Light",
+            ElementType::Dark => "Dark",
+            ElementType::None => "None",
+        }
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            ElementType::Fire => "🔥",
+            ElementType::Ice => "❄️",
+            ElementType::Earth => "🪨",
+            ElementType::Air => "💨",
+            ElementType::Bolt => "⚡",
+            ElementType::Poison => "☠️",
+            ElementType::Light => "✨",
+            ElementType::Dark => "🌑",
+            ElementType::None => "❔",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ElementType> {
+        ElementType::ALL.into_iter().find(|element| element.name().eq_ignore_ascii_case(name))
+    }
+
+    // Where cards of this element sort, with cards of no element after every suit
+    pub fn sort_order(&self) -> u8 {
+        ElementType::ALL.iter().position(|element| element == self).unwrap_or(ElementType::ALL.len()) as u8
+    }
+
+    // Written out for help and error text, e.g. "Fire, Ice, ... or Dark"
+    pub fn list_names() -> String {
+        let names: Vec<&str> = ElementType::ALL.iter().map(ElementType::name).collect();
+        format!("{} or {}", names[..names.len() - 1].join(", "), names[names.len() - 1])
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Suit {
     pub element: ElementType,
     pub symbol: String,
 }
 
+impl Suit {
+    pub fn of(element: ElementType) -> Suit {
+        Suit {
+            symbol: element.symbol().to_string(),
+            element,
+        }
+    }
+}
+
+// Identifies one physical card for the whole combat, wherever it moves. Positions shift
+// whenever the hand changes, so anything that outlives a single command refers to cards by ID.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CardId(pub u32);
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum CardType {
-    Number(Option<u8>, Suit),
+    Number(Option<u8>, Suit, #[serde(default)] CardId),
     Joker {
         current_value: Option<u8>,
         current_suit: Option<Suit>,
         symbol: String,
+        // Which of the player's jokers this is, so a signature name follows it
+        #[serde(default)]
+        slot: u8,
+        #[serde(default)]
+        name: Option<String>,
+        #[serde(default)]
+        id: CardId,
     },
 }
 
+// Identity of a card ignoring joker assignments, used to compare decks
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum CardKey {
+    Number(u8, ElementType),
+    Joker,
+}
+
+// A card picked by what it is rather than where it sits, e.g. "fire 5" or "joker"
+#[derive(Debug, Clone, PartialEq)]
+pub enum CardQuery {
+    Joker,
+    // Any card of the element, or only the one with this value
+    Element(ElementType, Option<u8>),
+}
+
+pub enum CardMatch {
+    Found(usize),
+    Missing,
+    // Hand positions of the cards it could mean
+    Ambiguous(Vec<usize>),
+}
+
+impl CardQuery {
+    // Jokers match by what they're assigned as, and as jokers
+    pub fn matches(&self, card: &CardType) -> bool {
+        match self {
+            CardQuery::Joker => matches!(card, CardType::Joker { .. }),
+            CardQuery::Element(element, value) => card.face().is_some_and(|(face_value, face_element)| {
+                face_element == element && value.is_none_or(|value| face_value == Some(value))
+            }),
+        }
+    }
+
+    // Find the card in a hand, skipping positions already picked. Several matches are only
+    // ambiguous if they're different cards; any one of two unassigned jokers will do.
+    pub fn resolve(&self, hand: &[CardType], taken: &[usize]) -> CardMatch {
+        let candidates: Vec<usize> = hand.iter()
+            .enumerate()
+            .filter(|(i, card)| !taken.contains(i) && self.matches(card))
+            .map(|(i, _)| i)
+            .collect();
+        match candidates.as_slice() {
+            [] => CardMatch::Missing,
+            [only] => CardMatch::Found(*only),
+            [first, rest @ ..] if rest.iter().all(|&i| hand[i].label() == hand[*first].label()) => CardMatch::Found(*first),
+            _ => CardMatch::Ambiguous(candidates),
+        }
+    }
+}
+
 impl CardType {
+    pub fn key(&self) -> CardKey {
+        match self {
+            CardType::Number(value, suit, _) => CardKey::Number(value.unwrap_or(0), suit.element.clone()),
+            CardType::Joker { .. } => CardKey::Joker,
+        }
+    }
+
+    pub fn id(&self) -> CardId {
+        match self {
+            CardType::Number(_, _, id) | CardType::Joker { id, .. } => *id,
+        }
+    }
+
+    pub fn set_id(&mut self, new_id: CardId) {
+        match self {
+            CardType::Number(_, _, id) | CardType::Joker { id, .. } => *id = new_id,
+        }
+    }
+
+    // Compact label such as "7🔥", or the joker symbol when unassigned
+    pub fn label(&self) -> String {
+        let label = match self {
+            CardType::Number(value, suit, _) => format!("{}{}", value.unwrap_or(0), suit.symbol),
+            CardType::Joker { current_value: Some(value), current_suit: Some(suit), .. } => format!("{}{}", value, suit.symbol),
+            CardType::Joker { symbol, .. } => symbol.clone(),
+        };
+        match self.joker_name() {
+            Some(name) => format!("{} ({})", label, name),
+            None => label,
+        }
+    }
+
+    // What the card is worth when spent for its number, to guard or to boost a check.
+    // A joker that is still wild counts as the deck's highest value.
+    pub fn spent_value(&self, high: u8) -> u8 {
+        match self.face() {
+            Some((value, _)) => value.unwrap_or(0),
+            None => high,
+        }
+    }
+
+    // Value and element as hand detection reads them, None for a joker that is still wild
+    pub fn face(&self) -> Option<(Option<u8>, &ElementType)> {
+        match self {
+            CardType::Number(value, suit, _) => Some((*value, &suit.element)),
+            CardType::Joker { current_value: Some(value), current_suit: Some(suit), .. } => Some((Some(*value), &suit.element)),
+            CardType::Joker { .. } => None,
+        }
+    }
+
+    pub fn assign_joker(&mut self, value: u8, element: ElementType) -> Result<(), String> {
+        let CardType::Joker { current_value, current_suit, .. } = self else {
+            return Err("That card isn't a joker".to_string());
+        };
+        *current_value = Some(value);
+        *current_suit = Some(Suit::of(element));
+        Ok(())
+    }
+
+    // Jokers go back to being wild once they leave the hand
+    pub fn clear_assignment(&mut self) {
+        if let CardType::Joker { current_value, current_suit, .. } = self {
+            *current_value = None;
+            *current_suit = None;
+        }
+    }
+
+    // The player's name for this joker, if they gave it one
+    pub fn joker_name(&self) -> Option<&str> {
+        match self {
+            CardType::Joker { name, .. } => name.as_deref(),
+            CardType::Number(..) => None,
+        }
+    }
+
     pub fn number_to_emoji(number: u8) -> String {
         match number {
             1 => "1️⃣".to_string(),
@@ -37,96 +248,467 @@ impl CardType {
             5 => "5️⃣".to_string(),
             6 => "6️⃣".to_string(),
             7 => "7️⃣".to_string(),
-            _ => "❓".to_string(),
+            8 => "8️⃣".to_string(),
+            9 => "9️⃣".to_string(),
+            10 => "🔟".to_string(),
+            _ => number.to_string(),
+        }
+    }
+}
+
+// How a deck's numbered cards are made up
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub enum DeckKind {
+    // Every value in every suit
+    #[default]
+    Elemental,
+    // One card per trump of the major arcana, each in its own element, with its own hands
+    MajorArcana,
+}
+
+// What goes into a fresh deck for a table. Templates saved before the deck's shape could
+// change only have jokers, and fill the rest in from the standard deck.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct DeckTemplate {
+    pub jokers: u8,
+    // Each suit runs from low to high
+    pub low: u8,
+    pub high: u8,
+    // Copies of every numbered card
+    pub copies: u8,
+    // The suits in the deck, in the order tables list them
+    pub elements: Vec<ElementType>,
+    pub kind: DeckKind,
+}
+
+impl Default for DeckTemplate {
+    fn default() -> Self {
+        DeckTemplate {
+            jokers: 2,
+            low: 1,
+            high: 7,
+            copies: 1,
+            elements: vec![ElementType::Fire, ElementType::Ice, ElementType::Earth, ElementType::Air],
+            kind: DeckKind::Elemental,
         }
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl DeckTemplate {
+    // Most jokers a guild can put in its deck, past that hands are mostly wild
+    pub const MAX_JOKERS: u8 = 4;
+    // Highest card value any deck has, the last trump of the major arcana
+    pub const MAX_VALUE: u8 = 21;
+    pub const MAX_COPIES: u8 = 4;
+    
+    // The same deck with no jokers, for probing which hands numbered cards make
+    pub fn without_jokers(&self) -> Self {
+        DeckTemplate { jokers: 0, ..self.clone() }
+    }
+    
+    pub fn values(&self) -> RangeInclusive<u8> {
+        self.low..=self.high
+    }
+    
+    // The suits a wild joker could be, as hand detection lists them
+    pub fn wild_suits(&self) -> Vec<ElementType> {
+        let mut suits = self.elements.clone();
+        suits.sort_by_key(|element| element.name());
+        suits
+    }
+    
+    // Templates a deck can't be built from, or that would play badly. The deck has to hold an
+    // opening hand of `hand_size` cards and a full refill after it.
+    pub fn check(&self, hand_size: usize) -> Result<(), String> {
+        if self.jokers > Self::MAX_JOKERS {
+            return Err(format!("a deck can have at most {} jokers", Self::MAX_JOKERS));
+        }
+        if self.low == 0 || self.low > self.high || self.high > Self::MAX_VALUE {
+            return Err(format!("values run from 1 to at most {}, lowest first", Self::MAX_VALUE));
+        }
+        if self.copies == 0 || self.copies > Self::MAX_COPIES {
+            return Err(format!("a deck has 1 to {} copies of each card", Self::MAX_COPIES));
+        }
+        if self.elements.is_empty() || self.elements.contains(&ElementType::None) {
+            return Err("a deck needs at least one suit".to_string());
+        }
+        if self.elements.iter().enumerate().any(|(i, element)| self.elements[..i].contains(element)) {
+            return Err("each suit can only be in the deck once".to_string());
+        }
+        let cards = Deck::from_template(self).cards.len();
+        if cards < hand_size * 2 {
+            return Err(format!("it would only have {} card(s), and hands of {} need at least {}", cards, hand_size, hand_size * 2));
+        }
+        Ok(())
+    }
+    
+    // Written out for GMs, e.g. "1-7 in Fire, Ice, Earth, Air, 2 joker(s)"
+    pub fn describe(&self) -> String {
+        if self.kind == DeckKind::MajorArcana {
+            return format!("major arcana {}-{}, {} Fool(s)", self.low, self.high, self.jokers);
+        }
+        let suits = self.elements.iter().map(|element| element.name()).collect::<Vec<_>>().join(", ");
+        let mut text = format!("{}-{} in {}", self.low, self.high, suits);
+        if self.copies > 1 {
+            text.push_str(&format!(", {} copies of each", self.copies));
+        }
+        text.push_str(&format!(", {} joker(s)", self.jokers));
+        text
+    }
+    
+    // How many copies of each card the template produces
+    pub fn card_counts(&self) -> HashMap<CardKey, usize> {
+        let mut counts = HashMap::new();
+        for card in Deck::from_template(self).cards {
+            *counts.entry(card.key()).or_insert(0) += 1;
+        }
+        counts
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Deck {
     pub cards: Vec<CardType>,
 }
 
 impl Deck {
     pub fn new() -> Self {
+        Deck::from_template(&DeckTemplate::default())
+    }
+
+    pub fn from_template(template: &DeckTemplate) -> Self {
         let mut cards = Vec::new();
         
-        // Create suits
-        let fire_suit = Suit {
-            element: ElementType::Fire,
-            symbol: "🔥".to_string(),
-        };
-        
-        let ice_suit = Suit {
-            element: ElementType::Ice,
-            symbol: "❄️".to_string(),
-        };
-        
-        let earth_suit = Suit {
-            element: ElementType::Earth,
-            symbol: "🪨".to_string(),
-        };
-        
-        let air_suit = Suit {
-            element: ElementType::Air,
-            symbol: "💨".to_string(),
-        };
-        
         // Add numbered cards
-        for number in 1..=7 {
-            cards.push(CardType::Number(Some(number), fire_suit.clone()));
-            cards.push(CardType::Number(Some(number), ice_suit.clone()));
-            cards.push(CardType::Number(Some(number), earth_suit.clone()));
-            cards.push(CardType::Number(Some(number), air_suit.clone()));
+        for _ in 0..template.copies {
+            match template.kind {
+                DeckKind::Elemental => {
+                    for number in template.values() {
+                        for element in &template.elements {
+                            cards.push(CardType::Number(Some(number), Suit::of(element.clone()), CardId::default()));
+                        }
+                    }
+                },
+                DeckKind::MajorArcana => {
+                    for (number, element, _) in arcana::trumps() {
+                        if template.values().contains(&number) && template.elements.contains(&element) {
+                            cards.push(CardType::Number(Some(number), Suit::of(element), CardId::default()));
+                        }
+                    }
+                },
+            }
         }
         
         // Add jokers
-        cards.push(CardType::Joker {
-            current_value: None,
-            current_suit: None,
-            symbol: "🃏".to_string(),
-        });
+        for slot in 0..template.jokers {
+            cards.push(CardType::Joker {
+                current_value: None,
+                current_suit: None,
+                symbol: "🃏".to_string(),
+                slot,
+                name: None,
+                id: CardId::default(),
+            });
+        }
         
-        cards.push(CardType::Joker {
-            current_value: None,
-            current_suit: None,
-            symbol: "🃏".to_string(),
-        });
+        // Numbered before shuffling, so IDs say nothing about where a card ended up
+        for (i, card) in cards.iter_mut().enumerate() {
+            card.set_id(CardId(i as u32));
+        }
         
         Deck { cards }
     }
     
-    pub fn shuffle(&mut self) {
-        self.cards.shuffle(&mut thread_rng());
+    pub fn shuffle_with(&mut self, shuffler: &dyn Shuffler) {
+        shuffler.shuffle(&mut self.cards, &mut thread_rng());
+    }
+    
+    // Undrawn numbered cards of one suit for each of the values, in order
+    pub fn value_counts(&self, element: &ElementType, values: RangeInclusive<u8>) -> Vec<usize> {
+        values.map(|value| self.cards.iter()
+                .filter(|card| matches!(card, CardType::Number(Some(v), suit, _) if *v == value && suit.element == *element))
+                .count())
+            .collect()
+    }
+    
+    pub fn suit_count(&self, element: &ElementType) -> usize {
+        self.cards.iter().filter(|card| matches!(card, CardType::Number(_, suit, _) if suit.element == *element)).count()
+    }
+    
+    pub fn joker_count(&self) -> usize {
+        self.cards.iter().filter(|card| matches!(card, CardType::Joker { .. })).count()
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+// Hands recorded before cards had IDs load with no card_ids
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum HandType {
     TripleThreat {
         value: u8,
         suits: Vec<ElementType>,
-        card_indices: Vec<usize>,
+        #[serde(default)]
+        card_ids: Vec<CardId>,
     },
     MatchedEdge {
         value: u8,
         suits: Vec<ElementType>,
-        card_indices: Vec<usize>,
+        #[serde(default)]
+        card_ids: Vec<CardId>,
     },
     Jackpot {
         value: u8,
         suits: Vec<ElementType>,
-        card_indices: Vec<usize>,
+        #[serde(default)]
+        card_ids: Vec<CardId>,
     },
     DoubleTrouble {
         first_pair_value: u8,
         second_pair_value: u8,
         suits: Vec<ElementType>,
-        card_indices: Vec<usize>,
+        #[serde(default)]
+        card_ids: Vec<CardId>,
+    },
+    // Four consecutive values in a single suit, from low_value up
+    MagicFlush {
+        low_value: u8,
+        suits: Vec<ElementType>,
+        #[serde(default)]
+        card_ids: Vec<CardId>,
+    },
+    // Four consecutive values in any suits, from low_value up
+    BlindingFlush {
+        low_value: u8,
+        suits: Vec<ElementType>,
+        #[serde(default)]
+        card_ids: Vec<CardId>,
+    },
+    // Three of one value and two of another
+    FullStatus {
+        triple_value: u8,
+        pair_value: u8,
+        suits: Vec<ElementType>,
+        #[serde(default)]
+        card_ids: Vec<CardId>,
+    },
+    // One of the guild's homebrew hands, by name, with the values of the cards it's built on
+    Homebrew {
+        name: String,
+        values: Vec<u8>,
+        suits: Vec<ElementType>,
+        #[serde(default)]
+        card_ids: Vec<CardId>,
     },
 }
 
 impl HandType {
+    pub fn card_ids(&self) -> &[CardId] {
+        match self {
+            HandType::TripleThreat { card_ids, .. } |
+            HandType::MatchedEdge { card_ids, .. } |
+            HandType::Jackpot { card_ids, .. } |
+            HandType::DoubleTrouble { card_ids, .. } |
+            HandType::MagicFlush { card_ids, .. } |
+            HandType::BlindingFlush { card_ids, .. } |
+            HandType::FullStatus { card_ids, .. } |
+            HandType::Homebrew { card_ids, .. } => card_ids,
+        }
+    }
+
+    // Where the hand's cards sit in the given cards, in hand order. Cards that have
+    // left are skipped, so a shorter result means the hand can no longer be played.
+    pub fn positions_in(&self, cards: &[CardType]) -> Vec<usize> {
+        let mut positions: Vec<usize> = self.card_ids().iter()
+            .filter_map(|id| cards.iter().position(|card| card.id() == *id))
+            .collect();
+        positions.sort_unstable();
+        positions
+    }
+
+    // The same hand however it was found: elements and cards in a fixed order, and a
+    // Double Trouble's lower pair first
+    pub fn canonical(mut self) -> HandType {
+        if let HandType::DoubleTrouble { first_pair_value, second_pair_value, .. } = &mut self {
+            if first_pair_value > second_pair_value {
+                std::mem::swap(first_pair_value, second_pair_value);
+            }
+        }
+        let (suits, card_ids) = match &mut self {
+            HandType::TripleThreat { suits, card_ids, .. } |
+            HandType::MatchedEdge { suits, card_ids, .. } |
+            HandType::Jackpot { suits, card_ids, .. } |
+            HandType::DoubleTrouble { suits, card_ids, .. } |
+            HandType::MagicFlush { suits, card_ids, .. } |
+            HandType::BlindingFlush { suits, card_ids, .. } |
+            HandType::FullStatus { suits, card_ids, .. } |
+            HandType::Homebrew { suits, card_ids, .. } => (suits, card_ids),
+        };
+        suits.sort();
+        suits.dedup();
+        card_ids.sort();
+        self
+    }
+
+    pub fn suits(&self) -> &[ElementType] {
+        match self {
+            HandType::TripleThreat { suits, .. } |
+            HandType::MatchedEdge { suits, .. } |
+            HandType::Jackpot { suits, .. } |
+            HandType::DoubleTrouble { suits, .. } |
+            HandType::MagicFlush { suits, .. } |
+            HandType::BlindingFlush { suits, .. } |
+            HandType::FullStatus { suits, .. } |
+            HandType::Homebrew { suits, .. } => suits,
+        }
+    }
+
+    // The values the hand is worth, for modifiers to adjust
+    pub fn values_mut(&mut self) -> Vec<&mut u8> {
+        match self {
+            HandType::TripleThreat { value, .. } |
+            HandType::MatchedEdge { value, .. } |
+            HandType::Jackpot { value, .. } => vec![value],
+            HandType::DoubleTrouble { first_pair_value, second_pair_value, .. } => vec![first_pair_value, second_pair_value],
+            HandType::FullStatus { triple_value, pair_value, .. } => vec![triple_value, pair_value],
+            // A run is only a run at its real values, so modifiers leave runs alone, along
+            // with homebrew hands that may be straights
+            HandType::MagicFlush { .. } | HandType::BlindingFlush { .. } | HandType::Homebrew { .. } => Vec::new(),
+        }
+    }
+
+    // Hands whose effect hits chosen targets are declared before they're committed
+    pub fn max_targets(&self) -> usize {
+        match self {
+            HandType::TripleThreat { .. } | HandType::FullStatus { .. } => 3,
+            HandType::DoubleTrouble { .. } => 2,
+            HandType::MatchedEdge { .. } | HandType::Jackpot { .. } | HandType::MagicFlush { .. } | HandType::BlindingFlush { .. } => 0,
+            HandType::Homebrew { .. } => 0,
+        }
+    }
+
+    pub fn needs_targets(&self) -> bool {
+        self.max_targets() > 0
+    }
+
+    // Strongest first: by tier, then by the higher values. Sort ascending on this.
+    pub fn sort_key(&self) -> (u8, std::cmp::Reverse<Vec<u8>>) {
+        let tier = match self {
+            HandType::Jackpot { .. } => 0,
+            HandType::MagicFlush { .. } => 1,
+            HandType::FullStatus { .. } => 2,
+            HandType::DoubleTrouble { .. } => 3,
+            HandType::BlindingFlush { .. } => 4,
+            HandType::TripleThreat { .. } => 5,
+            HandType::MatchedEdge { .. } => 6,
+            HandType::Homebrew { .. } => 7,
+        };
+        let mut values = self.values();
+        values.sort_unstable_by(|a, b| b.cmp(a));
+        (tier, std::cmp::Reverse(values))
+    }
+
+    // The cost as written; tables can change it, so read costs through GuildConfig::mp_cost
+    pub fn mp_cost(&self) -> u32 {
+        match self {
+            HandType::TripleThreat { .. } => 10,
+            HandType::BlindingFlush { .. } => 15,
+            HandType::MatchedEdge { .. } => 5,
+            HandType::Jackpot { .. } | HandType::DoubleTrouble { .. } | HandType::MagicFlush { .. } | HandType::FullStatus { .. } => 20,
+            // Homebrew hands only cost what the guild set
+            HandType::Homebrew { .. } => 0,
+        }
+    }
+
+    // Effect announcement, with the damage types already formatted
+    pub fn effect_text(&self, elements_str: &str, effect: &HandEffect) -> String {
+        let damage = effect.damage.unwrap_or(0);
+        let heal = effect.heal.unwrap_or(0);
+        match self {
+            HandType::TripleThreat { .. } => {
+                format!("Triple Threat resolved! Three targets of your choice recover or suffer from **dazed, shaken, slow or weak**. If the target recovers then it also heals Hit Points equal to {}. If the target suffers it also takes {} {} damage.", 
+                    heal, 
+                    damage, 
+                    elements_str)
+            },
+            HandType::MatchedEdge { .. } => {
+                format!("Matched Edge resolved! Your weapon strike deals {} bonus {} damage!", 
+                    damage, 
+                    elements_str)
+            },
+            HandType::Jackpot { .. } => {
+                format!("Jackpot resolved! You and every ally present on the scene recover {} Hit Points, {} Mind Points, and recover from all status effects; any PCs who have surrendered but are still part of the scene immediately regain consciousness (this does not cancel the effects of their Surrender).", 
+                    heal, 
+                    heal)
+            },
+            HandType::DoubleTrouble { .. } => {
+                let breakdown = effect.breakdown.as_ref()
+                    .map(|b| format!(" ({})", b))
+                    .unwrap_or_default();
+                format!("Double Trouble resolved! You deal damage equal to {}{} to each of up to two different enemies you can see that are present on the scene; the type of this damage is one of your choice among those matching the suits of the resolved cards: {}",
+                    damage,
+                    breakdown,
+                    elements_str)
+            },
+            HandType::MagicFlush { .. } => {
+                format!("Magic Flush resolved! You deal {} {} damage to each enemy present on the scene.",
+                    damage,
+                    elements_str)
+            },
+            HandType::BlindingFlush { .. } => {
+                format!("Blinding Flush resolved! Each enemy present on the scene suffers **dazed** and takes {} damage; the type of this damage is one of your choice among those matching the suits of the resolved cards: {}",
+                    damage,
+                    elements_str)
+            },
+            HandType::FullStatus { .. } => {
+                format!("Full Status resolved! Up to three targets of your choice suffer **dazed, shaken, slow and weak**. You and every ally present on the scene recover from all status effects and heal {} Hit Points.",
+                    heal)
+            },
+            // Without its own text a homebrew hand only announces the numbers its formulas give
+            HandType::Homebrew { name, .. } => {
+                let mut text = format!("{} resolved!", name);
+                if let Some(damage) = effect.damage {
+                    text.push_str(&format!(" You deal {} {} damage.", damage, elements_str));
+                }
+                if let Some(heal) = effect.heal {
+                    text.push_str(&format!(" You heal {} Hit Points.", heal));
+                }
+                text
+            }
+        }
+    }
+
+    // The card values the hand is built on, one per set of matching cards
+    pub fn values(&self) -> Vec<u8> {
+        match self {
+            HandType::TripleThreat { value, .. } |
+            HandType::MatchedEdge { value, .. } |
+            HandType::Jackpot { value, .. } => vec![*value],
+            HandType::DoubleTrouble { first_pair_value, second_pair_value, .. } => vec![*first_pair_value, *second_pair_value],
+            HandType::FullStatus { triple_value, pair_value, .. } => vec![*triple_value, *pair_value],
+            HandType::MagicFlush { low_value, .. } |
+            HandType::BlindingFlush { low_value, .. } => (*low_value..*low_value + 4).collect(),
+            HandType::Homebrew { values, .. } => values.clone(),
+        }
+    }
+
+    // Kind and values without the suits, e.g. "Double Trouble of 2 and 6" or "Magic Flush of 3-6"
+    pub fn shape(&self) -> String {
+        if let HandType::MagicFlush { low_value, .. } | HandType::BlindingFlush { low_value, .. } = self {
+            return format!("{} of {}-{}", HandKind::of(self).label(), low_value, low_value + 3);
+        }
+        if let HandType::Homebrew { name, values, .. } = self {
+            let values = values.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(", ");
+            return format!("{} of {}", name, values);
+        }
+        let mut values = self.values();
+        // Either pair can come first, while a Full Status always names its triple first
+        if let HandType::DoubleTrouble { .. } = self {
+            values.sort_unstable();
+        }
+        let values = values.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(" and ");
+        format!("{} of {}", HandKind::of(self).label(), values)
+    }
+
     pub fn to_string(&self) -> String {
         match self {
             HandType::TripleThreat { value, suits, .. } => {
@@ -144,6 +726,23 @@ impl HandType {
             HandType::DoubleTrouble { first_pair_value, second_pair_value, suits, .. } => {
                 let elements = format_element_list(suits);
                 format!("Double Trouble: {} and {} (Elements: {})", first_pair_value, second_pair_value, elements)
+            },
+            HandType::MagicFlush { low_value, suits, .. } => {
+                let elements = format_element_list(suits);
+                format!("Magic Flush: {}-{} (Elements: {})", low_value, low_value + 3, elements)
+            },
+            HandType::BlindingFlush { low_value, suits, .. } => {
+                let elements = format_element_list(suits);
+                format!("Blinding Flush: {}-{} (Elements: {})", low_value, low_value + 3, elements)
+            },
+            HandType::FullStatus { triple_value, pair_value, suits, .. } => {
+                let elements = format_element_list(suits);
+                format!("Full Status: {} and {} (Elements: {})", triple_value, pair_value, elements)
+            },
+            HandType::Homebrew { name, values, suits, .. } => {
+                let elements = format_element_list(suits);
+                let values = values.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(", ");
+                format!("{}: {} (Elements: {})", name, values, elements)
             }
         }
     }
@@ -156,15 +755,9 @@ pub fn format_element_list(elements: &[ElementType]) -> String {
     }
     
     let mut emoji_list = Vec::new();
-    for element in elements {
-        let element_emoji = match element {
-            ElementType::Fire => "🔥",
-            ElementType::Ice => "❄️",
-            ElementType::Earth => "🪨",
-            ElementType::Air => "💨",
-            ElementType::None => "",
-        };
-        if !emoji_list.contains(&element_emoji) && !element_emoji.is_empty() {
+    for element in elements.iter().filter(|element| **element != ElementType::None) {
+        let element_emoji = element.symbol();
+        if !emoji_list.contains(&element_emoji) {
             emoji_list.push(element_emoji);
         }
     }
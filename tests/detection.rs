@@ -0,0 +1,45 @@
+use destiny_draw::config::GuildConfig;
+use destiny_draw::state::PlayerState;
+use destiny_draw::types::HandType;
+
+// Snapshots of the /debug fixtures, as /debug write_fixtures saves them
+fn load_fixture(name: &str) -> PlayerState {
+    let path = format!("{}/tests/fixtures/{}.json", env!("CARGO_MANIFEST_DIR"), name);
+    let json = std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("couldn't read {}: {}", path, e));
+    serde_json::from_str(&json).unwrap_or_else(|e| panic!("{} is not a valid fixture: {}", path, e))
+}
+
+fn config_with(features: &[&str]) -> GuildConfig {
+    GuildConfig {
+        enabled_features: features.iter().map(|f| f.to_string()).collect(),
+        ..GuildConfig::default()
+    }
+}
+
+#[test]
+fn near_jackpot_holds_a_triple() {
+    let state = load_fixture("near_jackpot");
+    let hands = state.find_available_hands(&GuildConfig::default());
+    assert!(hands.iter().any(|hand| matches!(hand, HandType::TripleThreat { value: 4, .. })), "{:?}", hands);
+    assert!(!hands.iter().any(|hand| matches!(hand, HandType::Jackpot { .. })), "{:?}", hands);
+}
+
+#[test]
+fn near_jackpot_completes_from_the_deck() {
+    let mut state = load_fixture("near_jackpot");
+    // The fourth 4 waits on top of the deck
+    state.draw_to_hand(1).unwrap();
+    let hands = state.find_available_hands(&GuildConfig::default());
+    assert!(hands.iter().any(|hand| matches!(hand, HandType::Jackpot { value: 4, .. })), "{:?}", hands);
+}
+
+#[test]
+fn all_jokers_only_make_a_jackpot_when_the_table_allows_it() {
+    let state = load_fixture("all_jokers");
+    let hands = state.find_available_hands(&GuildConfig::default());
+    assert!(!hands.is_empty());
+    assert!(!hands.iter().any(|hand| matches!(hand, HandType::Jackpot { .. })), "{:?}", hands);
+
+    let hands = state.find_available_hands(&config_with(&["jokers_in_jackpot"]));
+    assert!(hands.iter().any(|hand| matches!(hand, HandType::Jackpot { .. })), "{:?}", hands);
+}